@@ -0,0 +1,942 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::disassembler::{evaluate, operands, pseudocode, Operand};
+use crate::machine::{Cpu, Opcode, Program, Registers, Word, N_REGISTERS};
+
+/// Set of registers (one bit per register)
+type RegSet = u8;
+
+fn bit(r: Word) -> RegSet {
+    if r < N_REGISTERS { 1 << r } else { 0 }
+}
+
+fn is_comparison(op: Opcode) -> bool {
+    matches!(op, Opcode::GTIR | Opcode::GTRI | Opcode::GTRR | Opcode::EQIR | Opcode::EQRI | Opcode::EQRR)
+}
+
+/// Decompile a program into structured pseudo-Rust
+pub fn decompile(program: &Program) -> Decompilation {
+    let analysis = Analysis::new(program);
+    let mut emitter = Emitter { analysis: &analysis, visited: BTreeSet::new() };
+    let stmts = emitter.sequence(analysis.entry, &[], None, false);
+
+    let mut natives = Vec::new();
+    analysis.find_natives(&stmts, &mut natives);
+
+    Decompilation { ip_reg: program.ip_reg, stmts, natives }
+}
+
+/// Replace recognised loops in the program with native code, returning what was replaced
+pub fn accelerate(cpu: &mut Cpu) -> Vec<Native> {
+    let natives = decompile(cpu.program()).natives;
+    for native in &natives {
+        let kernel = native.clone();
+        cpu.add_native(native.addr, Box::new(move |reg| kernel.apply(reg)));
+    }
+
+    natives
+}
+
+/// Decompiled program
+#[derive(Clone, Debug)]
+pub struct Decompilation {
+    pub ip_reg: Option<usize>,
+    pub stmts: Vec<Stmt>,
+    /// Loops that can be replaced by native code
+    pub natives: Vec<Native>,
+}
+
+impl Decompilation {
+    /// Show recognised loops as the native code that replaces them
+    pub fn replace_natives(&mut self) {
+        fn replace(stmts: &mut Vec<Stmt>, natives: &[Native]) {
+            for stmt in stmts {
+                match stmt {
+                    Stmt::Loop { header, .. } if natives.iter().any(|n| n.addr == *header) => {
+                        *stmt = Stmt::Native(natives.iter().find(|n| n.addr == *header).unwrap().clone());
+                    },
+                    Stmt::Loop { body, .. } => replace(body, natives),
+                    Stmt::If { then, otherwise, .. } => {
+                        replace(then, natives);
+                        replace(otherwise, natives);
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        replace(&mut self.stmts, &self.natives);
+    }
+}
+
+impl fmt::Display for Decompilation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ip_reg) = self.ip_reg {
+            writeln!(f, "// #ip {}", ip_reg)?;
+        }
+
+        write_stmts(f, &self.stmts, 0, &mut Vec::new())
+    }
+}
+
+/// Statement of decompiled code
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stmt {
+    /// `reg = x op y` (the instruction at `addr`)
+    Assign { addr: usize, reg: Word, op: Opcode, x: Operand, y: Operand },
+    If { cond: Cond, then: Vec<Stmt>, otherwise: Vec<Stmt> },
+    /// Loop starting at `header`
+    Loop { header: usize, body: Vec<Stmt> },
+    /// Leave the loop starting at an address
+    Break(usize),
+    /// Go to the start of the loop starting at an address
+    Continue(usize),
+    /// Halt
+    Return,
+    /// Jump that couldn't be structured
+    Goto(usize),
+    /// Jump to an address calculated by the instruction at `addr`
+    Computed { addr: usize, target: String },
+    /// Native code replacing a loop
+    Native(Native),
+}
+
+/// Condition of an `if`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cond {
+    /// Register that is tested
+    pub flag: Word,
+    /// Comparison that set the flag (if the flag isn't used again, so it's only shown in the condition)
+    pub compare: Option<(Opcode, Operand, Operand)>,
+    /// Is the flag tested for being zero
+    pub negate: bool,
+}
+
+impl Cond {
+    fn negated(self) -> Cond {
+        Cond { negate: !self.negate, ..self }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.compare {
+            Some((op, x, y)) => {
+                let symbol = match (op, self.negate) {
+                    (Opcode::GTIR, false) | (Opcode::GTRI, false) | (Opcode::GTRR, false) => ">",
+                    (Opcode::GTIR, true) | (Opcode::GTRI, true) | (Opcode::GTRR, true) => "<=",
+                    (_, false) => "==",
+                    (_, true) => "!=",
+                };
+                write!(f, "{} {} {}", x.name(), symbol, y.name())
+            },
+            None => write!(f, "r{} {} 0", self.flag, if self.negate { "==" } else { "!=" }),
+        }
+    }
+}
+
+/// Native code replacing a loop
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Native {
+    /// Address of the start of the loop
+    pub addr: usize,
+    /// Address execution continues at after the loop
+    pub exit: usize,
+    pub kernel: Kernel,
+}
+
+/// What a replaced loop calculates
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kernel {
+    /// `loop { t = (i + 1) * k; if t > n { break } i += 1 }`
+    Divide { i: Word, t: Word, n: Word, k: Word, flag: Word },
+    /// Sum of the divisors of `n` that are at least `i`
+    ///
+    /// `loop { j = 1; loop { t = i * j; if t == n { acc += i } j += 1; if j > n { break } } i += 1; if i > n { break } }`
+    SumOfDivisors { i: Word, j: Word, n: Word, acc: Word, t: Word, flags: [Word; 3] },
+}
+
+impl Native {
+    /// Run the loop, returning the address to continue at (or `None` if it would overflow or never finish)
+    pub fn apply(&self, reg: &mut Registers) -> Option<usize> {
+        match self.kernel {
+            Kernel::Divide { i, t, n, k, flag } => {
+                if k == 0 {
+                    return None;
+                }
+                let last = reg[i].max(reg[n] / k);
+                let product = last.checked_add(1)?.checked_mul(k)?;
+
+                reg[i] = last;
+                reg[t] = product;
+                reg[flag] = 1;
+            },
+            Kernel::SumOfDivisors { i, j, n, acc, t, flags } => {
+                // Both loops run at least once
+                let target = reg[n];
+                let last_i = reg[i].max(target);
+                let last_j = target.max(1);
+
+                let mut sum: Word = 0;
+                if target > 0 {
+                    for d in reg[i].max(1)..=last_i {
+                        if target.is_multiple_of(d) {
+                            sum = sum.checked_add(d)?;
+                        }
+                    }
+                }
+                let product = last_i.checked_mul(last_j)?;
+
+                reg[acc] = reg[acc].checked_add(sum)?;
+                reg[t] = product;
+                reg[flags[0]] = (product == target) as Word;
+                reg[j] = last_j.checked_add(1)?;
+                reg[flags[1]] = 1;
+                reg[i] = last_i.checked_add(1)?;
+                reg[flags[2]] = 1;
+            },
+        }
+
+        Some(self.exit)
+    }
+}
+
+/// How the instruction at an address continues
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Exit {
+    /// Continues with the next instruction
+    Next,
+    /// Jumps to a known address
+    Goto(usize),
+    /// Jumps to `base` if `flag` is zero or `base + 1` if it's one
+    Branch { flag: Word, base: usize },
+    /// Jumps to an address that can't be determined statically
+    Computed,
+}
+
+/// How a basic block ends (after threading jumps)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Term {
+    Goto(usize),
+    /// `base` is the address jumped to when `flag` is zero (before threading jumps)
+    Branch { flag: Word, base: usize, zero: usize, nonzero: usize },
+    Computed(usize),
+}
+
+impl Term {
+    fn successors(self) -> Vec<usize> {
+        match self {
+            Term::Goto(next) => vec![next],
+            Term::Branch { zero, nonzero, .. } => vec![zero, nonzero],
+            Term::Computed(_) => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Block {
+    /// Addresses of instructions, other than the jump at the end
+    body: Vec<usize>,
+    term: Term,
+}
+
+#[derive(Clone, Debug)]
+struct Loop {
+    body: BTreeSet<usize>,
+    /// Where execution continues after the loop
+    follow: Option<usize>,
+}
+
+/// Control flow of a program
+///
+/// Addresses outside the program (where it halts) are all treated as `halt`.
+struct Analysis<'a> {
+    program: &'a Program,
+    halt: usize,
+    entry: usize,
+    blocks: BTreeMap<usize, Block>,
+    predecessors: BTreeMap<usize, BTreeSet<usize>>,
+    dominators: BTreeMap<usize, BTreeSet<usize>>,
+    loops: BTreeMap<usize, Loop>,
+    live_in: BTreeMap<usize, RegSet>,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(program: &'a Program) -> Self {
+        let mut analysis = Analysis {
+            program,
+            halt: program.len(),
+            entry: 0,
+            blocks: BTreeMap::new(),
+            predecessors: BTreeMap::new(),
+            dominators: BTreeMap::new(),
+            loops: BTreeMap::new(),
+            live_in: BTreeMap::new(),
+        };
+        analysis.entry = analysis.resolve(0);
+        analysis.find_blocks();
+        analysis.find_dominators();
+        analysis.find_loops();
+        analysis.find_liveness();
+
+        analysis
+    }
+
+    fn exit(&self, addr: usize) -> Exit {
+        let instruction = &self.program.instructions[addr];
+        if Some(instruction.c) != self.program.ip_reg {
+            return Exit::Next;
+        }
+
+        match operands(self.program, addr, instruction) {
+            (Operand::Value(x), Operand::Value(y)) => match evaluate(instruction.op, x, y) {
+                Some(target) => Exit::Goto(target.saturating_add(1)),
+                None => Exit::Computed,
+            },
+            (Operand::Register(flag), Operand::Value(k)) | (Operand::Value(k), Operand::Register(flag))
+                if matches!(instruction.op, Opcode::ADDR | Opcode::ADDI) => Exit::Branch { flag, base: k + 1 },
+            _ => Exit::Computed,
+        }
+    }
+
+    /// Follow jumps that don't do anything else
+    fn resolve(&self, mut target: usize) -> usize {
+        for _ in 0..=self.halt {
+            if target >= self.halt {
+                return self.halt;
+            }
+            match self.exit(target) {
+                Exit::Goto(next) => target = next,
+                _ => return target,
+            }
+        }
+
+        target  // Jumps forever
+    }
+
+    fn successors(&self, addr: usize) -> Vec<usize> {
+        match self.exit(addr) {
+            Exit::Next => vec![self.resolve(addr + 1)],
+            Exit::Goto(target) => vec![self.resolve(target)],
+            Exit::Branch { base, .. } => vec![self.resolve(base), self.resolve(base + 1)],
+            Exit::Computed => vec![],
+        }
+    }
+
+    fn find_blocks(&mut self) {
+        let mut reachable = BTreeSet::new();
+        let mut stack = vec![self.entry];
+        while let Some(addr) = stack.pop() {
+            if addr < self.halt && reachable.insert(addr) {
+                stack.extend(self.successors(addr));
+            }
+        }
+
+        // Blocks start wherever execution doesn't just fall through from the previous instruction
+        let mut leaders = BTreeSet::new();
+        leaders.insert(self.entry);
+        for &addr in &reachable {
+            for next in self.successors(addr) {
+                if next < self.halt && !(self.exit(addr) == Exit::Next && next == addr + 1) {
+                    leaders.insert(next);
+                }
+            }
+        }
+
+        for &start in &leaders {
+            let mut body = Vec::new();
+            let mut addr = start;
+            let term = loop {
+                match self.exit(addr) {
+                    Exit::Next => {
+                        body.push(addr);
+                        let next = self.resolve(addr + 1);
+                        if next != addr + 1 || next >= self.halt || leaders.contains(&next) {
+                            break Term::Goto(next);
+                        }
+                        addr = next;
+                    },
+                    Exit::Goto(target) => break Term::Goto(self.resolve(target)),
+                    Exit::Branch { flag, base } => {
+                        break Term::Branch { flag, base, zero: self.resolve(base), nonzero: self.resolve(base + 1) };
+                    },
+                    Exit::Computed => break Term::Computed(addr),
+                }
+            };
+
+            for next in term.successors() {
+                self.predecessors.entry(next).or_default().insert(start);
+            }
+            self.blocks.insert(start, Block { body, term });
+        }
+    }
+
+    fn find_dominators(&mut self) {
+        let all: BTreeSet<usize> = self.blocks.keys().copied().collect();
+        for &start in &all {
+            let dominators = if start == self.entry { [start].iter().copied().collect() } else { all.clone() };
+            self.dominators.insert(start, dominators);
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &start in &all {
+                if start == self.entry {
+                    continue;
+                }
+                let mut dominators = self.predecessors.get(&start).into_iter().flatten()
+                    .map(|pred| &self.dominators[pred])
+                    .fold(None, |acc: Option<BTreeSet<usize>>, doms| Some(match acc {
+                        Some(acc) => acc.intersection(doms).copied().collect(),
+                        None => doms.clone(),
+                    }))
+                    .unwrap_or_default();
+                dominators.insert(start);
+
+                if dominators != self.dominators[&start] {
+                    self.dominators.insert(start, dominators);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn dominates(&self, a: usize, b: usize) -> bool {
+        self.dominators.get(&b).is_some_and(|doms| doms.contains(&a))
+    }
+
+    /// Find natural loops (from edges back to a block that dominates the source)
+    fn find_loops(&mut self) {
+        let mut loops: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for (&start, block) in &self.blocks {
+            for header in block.term.successors() {
+                if !self.dominates(header, start) {
+                    continue;
+                }
+
+                let body = loops.entry(header).or_default();
+                body.insert(header);
+                let mut stack = vec![start];
+                while let Some(node) = stack.pop() {
+                    if body.insert(node) {
+                        stack.extend(self.predecessors.get(&node).into_iter().flatten());
+                    }
+                }
+            }
+        }
+
+        for (header, body) in loops {
+            // Loops with several exits are given the first one
+            let follow = body.iter()
+                .flat_map(|start| self.blocks[start].term.successors())
+                .filter(|next| *next < self.halt && !body.contains(next))
+                .min();
+            self.loops.insert(header, Loop { body, follow });
+        }
+    }
+
+    /// Registers read and written by a block (reads that come before a write)
+    fn uses_defs(&self, block: &Block) -> (RegSet, RegSet) {
+        let reads = |addr: usize| {
+            let (x, y) = operands(self.program, addr, &self.program.instructions[addr]);
+            [x, y].iter().map(|operand| match operand {
+                Operand::Register(r) => bit(*r),
+                Operand::Value(_) => 0,
+            }).fold(0, |acc, r| acc | r)
+        };
+
+        let (mut uses, mut defs) = (0, 0);
+        for &addr in &block.body {
+            uses |= reads(addr) & !defs;
+            defs |= bit(self.program.instructions[addr].c);
+        }
+        match block.term {
+            Term::Branch { flag, .. } => uses |= bit(flag) & !defs,
+            Term::Computed(addr) => uses |= reads(addr) & !defs,
+            Term::Goto(_) => (),
+        }
+
+        (uses, defs)
+    }
+
+    fn find_liveness(&mut self) {
+        let uses_defs: BTreeMap<_, _> = self.blocks.iter().map(|(&start, block)| (start, self.uses_defs(block))).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (&start, block) in self.blocks.iter().rev() {
+                let live_out = block.term.successors().iter().map(|next| self.live(*next)).fold(0, |acc, r| acc | r);
+                let (uses, defs) = uses_defs[&start];
+                let live_in = uses | (live_out & !defs);
+                if self.live_in.insert(start, live_in) != Some(live_in) {
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// Registers that may be read before being written when execution reaches `addr`
+    fn live(&self, addr: usize) -> RegSet {
+        self.live_in.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Comparison at the end of a block that only sets the flag for the branch
+    fn folded_compare(&self, start: usize) -> Option<(Opcode, Operand, Operand)> {
+        let block = &self.blocks[&start];
+        let (flag, zero, nonzero) = match block.term {
+            Term::Branch { flag, zero, nonzero, .. } => (flag, zero, nonzero),
+            _ => return None,
+        };
+
+        let addr = *block.body.last()?;
+        let instruction = &self.program.instructions[addr];
+        if instruction.c != flag || !is_comparison(instruction.op) || (self.live(zero) | self.live(nonzero)) & bit(flag) != 0 {
+            return None;
+        }
+
+        let (x, y) = operands(self.program, addr, instruction);
+        Some((instruction.op, x, y))
+    }
+
+    /// Address execution continues at after an escape from a loop
+    ///
+    /// This is the address the branch leaving the loop jumps to, so any jumps after it still run.
+    fn escape_target(&self, stmt: &Stmt, header: usize) -> Option<usize> {
+        let target = match stmt {
+            Stmt::Break(h) if *h == header => self.loops.get(h)?.follow?,
+            Stmt::Return => self.halt,
+            _ => return None,
+        };
+
+        self.loops.get(&header)?.body.iter()
+            .find_map(|start| match self.blocks[start].term {
+                Term::Branch { base, zero, .. } if zero == target => Some(base),
+                Term::Branch { base, nonzero, .. } if nonzero == target => Some(base + 1),
+                _ => None,
+            })
+            .or(Some(target))
+    }
+
+    /// Find loops that can be replaced by native code
+    fn find_natives(&self, stmts: &[Stmt], natives: &mut Vec<Native>) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Loop { header, body } => {
+                    match self.recognise(*header, body) {
+                        Some(native) => natives.push(native),
+                        None => self.find_natives(body, natives),
+                    }
+                },
+                Stmt::If { then, otherwise, .. } => {
+                    self.find_natives(then, natives);
+                    self.find_natives(otherwise, natives);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn recognise(&self, header: usize, body: &[Stmt]) -> Option<Native> {
+        use Operand::{Register as R, Value as V};
+
+        let native = |exit, kernel| Some(Native { addr: header, exit, kernel });
+        let compare = |stmt: &Stmt| match stmt {
+            Stmt::If { cond: Cond { flag, compare: Some((op, R(x), R(y))), negate: false }, then, otherwise } if otherwise.is_empty() => {
+                Some((*op, *x, *y, *flag, then.clone()))
+            },
+            _ => None,
+        };
+        let increments = |stmt: &Stmt, r: Word| matches!(stmt, Stmt::Assign { reg, op: Opcode::ADDI, x: R(x), y: V(1), .. } if *reg == r && *x == r);
+        let distinct = |regs: &[Word]| regs.iter().collect::<BTreeSet<_>>().len() == regs.len();
+
+        match body {
+            // Integer division
+            [Stmt::Assign { reg: t, op: Opcode::ADDI, x: R(i), y: V(1), .. },
+             Stmt::Assign { reg: t2, op: Opcode::MULI, x: R(t3), y: V(k), .. },
+             cmp, inc] => {
+                let (op, t4, n, flag, then) = compare(cmp)?;
+                let exit = self.escape_target(then.first()?, header)?;
+                if op == Opcode::GTRR && [*t2, *t3, t4] == [*t; 3] && then.len() == 1 && increments(inc, *i)
+                    && distinct(&[*i, *t, n]) && flag != *i && flag != n {
+                    return native(exit, Kernel::Divide { i: *i, t: *t, n, k: *k, flag });
+                }
+            },
+            // Sum of divisors
+            [Stmt::Assign { reg: j, op: Opcode::SETI, x: V(1), .. },
+             Stmt::Loop { header: inner_header, body: inner },
+             inc_i, cmp_i] => {
+                let (op, i, n, flag_i, then) = compare(cmp_i)?;
+                let exit = self.escape_target(then.first()?, header)?;
+                if op != Opcode::GTRR || then.len() != 1 || !increments(inc_i, i) {
+                    return None;
+                }
+
+                if let [Stmt::Assign { reg: t, op: Opcode::MULR, x: R(a), y: R(b), .. }, cmp_t, inc_j, cmp_j] = inner.as_slice() {
+                    let (op_t, p, q, flag_t, then_t) = compare(cmp_t)?;
+                    let (op_j, j2, n2, flag_j, then_j) = compare(cmp_j)?;
+                    let acc = match then_t.as_slice() {
+                        [Stmt::Assign { reg: acc, op: Opcode::ADDR, x: R(u), y: R(v), .. }]
+                            if (*u == i && v == acc) || (u == acc && *v == i) => *acc,
+                        _ => return None,
+                    };
+
+                    let same = |x: (Word, Word), y: (Word, Word)| x == y || x == (y.1, y.0);
+                    if same((*a, *b), (i, *j)) && op_t == Opcode::EQRR && same((p, q), (*t, n))
+                        && increments(inc_j, *j) && op_j == Opcode::GTRR && (j2, n2) == (*j, n)
+                        && then_j == [Stmt::Break(*inner_header)] && distinct(&[i, *j, n, acc, *t])
+                        && [flag_t, flag_j, flag_i].iter().all(|f| ![i, *j, n, acc].contains(f)) {
+                        return native(exit, Kernel::SumOfDivisors { i, j: *j, n, acc, t: *t, flags: [flag_t, flag_j, flag_i] });
+                    }
+                }
+            },
+            _ => (),
+        }
+
+        None
+    }
+}
+
+/// Builds structured statements from the control flow
+struct Emitter<'a> {
+    analysis: &'a Analysis<'a>,
+    visited: BTreeSet<usize>,
+}
+
+impl<'a> Emitter<'a> {
+    /// Statements from `start` until `stop` (or an escape from the enclosing `loops`)
+    ///
+    /// If `entering` then `start` is the header of the innermost loop.
+    fn sequence(&mut self, start: usize, loops: &[usize], stop: Option<usize>, mut entering: bool) -> Vec<Stmt> {
+        let analysis = self.analysis;
+        let mut stmts = Vec::new();
+        let mut cur = start;
+        loop {
+            if !entering {
+                if Some(cur) == stop {
+                    break;
+                }
+                if let Some(escape) = self.escape(cur, loops) {
+                    stmts.push(escape);
+                    break;
+                }
+                if let Some(l) = analysis.loops.get(&cur) {
+                    let inner: Vec<_> = loops.iter().copied().chain(Some(cur)).collect();
+                    let mut body = self.sequence(cur, &inner, None, true);
+                    if body.last() == Some(&Stmt::Continue(cur)) {
+                        body.pop();
+                    }
+                    stmts.push(Stmt::Loop { header: cur, body });
+
+                    match l.follow {
+                        Some(follow) => { cur = follow; continue },
+                        None => break,
+                    }
+                }
+            }
+            entering = false;
+            self.visited.insert(cur);
+
+            let block = &analysis.blocks[&cur];
+            let compare = analysis.folded_compare(cur);
+            let n = block.body.len() - compare.is_some() as usize;
+            for &addr in &block.body[..n] {
+                let instruction = &analysis.program.instructions[addr];
+                let (x, y) = operands(analysis.program, addr, instruction);
+                stmts.push(Stmt::Assign { addr, reg: instruction.c, op: instruction.op, x, y });
+            }
+
+            match block.term {
+                Term::Goto(next) => cur = next,
+                Term::Computed(addr) => {
+                    let target = pseudocode(analysis.program, addr, &analysis.program.instructions[addr]);
+                    stmts.push(Stmt::Computed { addr, target });
+                    break;
+                },
+                Term::Branch { zero, nonzero, .. } if zero == nonzero => cur = zero,
+                Term::Branch { flag, zero, nonzero, .. } => {
+                    let cond = Cond { flag, compare, negate: false };
+                    if let Some(escape) = self.branch_escape(nonzero, loops, stop) {
+                        stmts.push(Stmt::If { cond, then: vec![escape], otherwise: vec![] });
+                        cur = zero;
+                    } else if let Some(escape) = self.branch_escape(zero, loops, stop) {
+                        stmts.push(Stmt::If { cond: cond.negated(), then: vec![escape], otherwise: vec![] });
+                        cur = nonzero;
+                    } else {
+                        let join = self.join(zero, nonzero, loops, stop);
+                        let then = self.sequence(nonzero, loops, join, false);
+                        let otherwise = self.sequence(zero, loops, join, false);
+                        if then.is_empty() {
+                            stmts.push(Stmt::If { cond: cond.negated(), then: otherwise, otherwise: vec![] });
+                        } else {
+                            stmts.push(Stmt::If { cond, then, otherwise });
+                        }
+
+                        match join {
+                            Some(join) => cur = join,
+                            None => break,
+                        }
+                    }
+                },
+            }
+        }
+
+        stmts
+    }
+
+    /// Statement for leaving the current code to go to `target`, if it can't just continue there
+    fn escape(&self, target: usize, loops: &[usize]) -> Option<Stmt> {
+        if target >= self.analysis.halt {
+            return Some(Stmt::Return);
+        }
+
+        for (depth, &header) in loops.iter().enumerate().rev() {
+            let l = &self.analysis.loops[&header];
+            if target == header {
+                return Some(Stmt::Continue(header));
+            }
+            if Some(target) == l.follow {
+                return Some(Stmt::Break(header));
+            }
+            if l.body.contains(&target) {
+                let innermost = depth + 1 == loops.len();
+                return if innermost && !self.visited.contains(&target) { None } else { Some(Stmt::Goto(target)) };
+            }
+        }
+
+        if !loops.is_empty() || self.visited.contains(&target) {
+            return Some(Stmt::Goto(target));
+        }
+
+        None
+    }
+
+    fn branch_escape(&self, target: usize, loops: &[usize], stop: Option<usize>) -> Option<Stmt> {
+        if Some(target) == stop {
+            return None;
+        }
+
+        self.escape(target, loops)
+    }
+
+    /// Where the two sides of a branch join again
+    fn join(&self, zero: usize, nonzero: usize, loops: &[usize], stop: Option<usize>) -> Option<usize> {
+        let is_boundary = |addr: usize| Some(addr) == stop || self.escape(addr, loops).is_some();
+        let reach = |start: usize| {
+            let mut reached = BTreeSet::new();
+            let mut stack = vec![start];
+            while let Some(addr) = stack.pop() {
+                if reached.insert(addr) && !is_boundary(addr) {
+                    stack.extend(self.analysis.blocks[&addr].term.successors());
+                }
+            }
+            reached
+        };
+
+        let common: BTreeSet<_> = reach(zero).intersection(&reach(nonzero)).copied().collect();
+        let (boundaries, inside): (BTreeSet<_>, BTreeSet<_>) = common.into_iter().partition(|&addr| is_boundary(addr));
+        if inside.is_empty() {
+            return if boundaries.len() == 1 { boundaries.into_iter().next() } else { None };
+        }
+
+        // The first block reached by both sides dominates the others
+        inside.iter().copied()
+            .find(|&a| inside.iter().all(|&b| self.analysis.dominates(a, b)))
+            .or_else(|| inside.iter().next().copied())
+    }
+}
+
+fn write_stmts(f: &mut fmt::Formatter, stmts: &[Stmt], indent: usize, loops: &mut Vec<usize>) -> fmt::Result {
+    let pad = " ".repeat(4 * indent);
+    let label = |header: usize, loops: &[usize]| {
+        if loops.last() == Some(&header) { String::new() } else { format!(" 'l{}", header) }
+    };
+
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign { reg, op, x, y, .. } => writeln!(f, "{}{}", pad, assignment(*reg, *op, *x, *y))?,
+            Stmt::If { cond, then, otherwise } => {
+                writeln!(f, "{}if {} {{", pad, cond)?;
+                write_stmts(f, then, indent + 1, loops)?;
+                if !otherwise.is_empty() {
+                    writeln!(f, "{}}} else {{", pad)?;
+                    write_stmts(f, otherwise, indent + 1, loops)?;
+                }
+                writeln!(f, "{}}}", pad)?;
+            },
+            Stmt::Loop { header, body } => {
+                let label = if is_referenced(body, *header, false) { format!("'l{}: ", header) } else { String::new() };
+                writeln!(f, "{}{}loop {{  // {}", pad, label, header)?;
+                loops.push(*header);
+                write_stmts(f, body, indent + 1, loops)?;
+                loops.pop();
+                writeln!(f, "{}}}", pad)?;
+            },
+            Stmt::Break(header) => writeln!(f, "{}break{};", pad, label(*header, loops))?,
+            Stmt::Continue(header) => writeln!(f, "{}continue{};", pad, label(*header, loops))?,
+            Stmt::Return => writeln!(f, "{}return;", pad)?,
+            Stmt::Goto(addr) => writeln!(f, "{}goto {};", pad, addr)?,
+            Stmt::Computed { target, .. } => writeln!(f, "{}{};", pad, target)?,
+            Stmt::Native(native) => {
+                writeln!(f, "{}// Native code replacing the loop at {} (continues at {})", pad, native.addr, native.exit)?;
+                match native.kernel {
+                    Kernel::Divide { i, n, k, .. } => writeln!(f, "{}r{} = r{}.max(r{} / {});", pad, i, i, n, k)?,
+                    Kernel::SumOfDivisors { i, n, acc, .. } => {
+                        writeln!(f, "{}r{} += (r{}..=r{}).filter(|&d| r{} % d == 0).sum::<usize>();", pad, acc, i, n, n)?;
+                    },
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Is a loop referred to by a `break` or `continue` inside a nested loop (so it needs a label)
+fn is_referenced(stmts: &[Stmt], header: usize, nested: bool) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Break(h) | Stmt::Continue(h) => nested && *h == header,
+        Stmt::If { then, otherwise, .. } => is_referenced(then, header, nested) || is_referenced(otherwise, header, nested),
+        Stmt::Loop { body, .. } => is_referenced(body, header, true),
+        _ => false,
+    })
+}
+
+fn assignment(reg: Word, op: Opcode, x: Operand, y: Operand) -> String {
+    let symbol = match op {
+        Opcode::SETR | Opcode::SETI => return format!("r{} = {};", reg, x.name()),
+        Opcode::ADDR | Opcode::ADDI => "+",
+        Opcode::MULR | Opcode::MULI => "*",
+        Opcode::BANR | Opcode::BANI => "&",
+        Opcode::BORR | Opcode::BORI => "|",
+        Opcode::GTIR | Opcode::GTRI | Opcode::GTRR => return format!("r{} = ({} > {}) as usize;", reg, x.name(), y.name()),
+        Opcode::EQIR | Opcode::EQRI | Opcode::EQRR => return format!("r{} = ({} == {}) as usize;", reg, x.name(), y.name()),
+    };
+
+    if x == Operand::Register(reg) {
+        format!("r{} {}= {};", reg, symbol, y.name())
+    } else if y == Operand::Register(reg) {
+        format!("r{} {}= {};", reg, symbol, x.name())
+    } else {
+        format!("r{} = {} {} {};", reg, x.name(), symbol, y.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    const SUM_OF_DIVISORS: &str = "\
+#ip 5
+seti 12 0 3
+seti 1 8 2
+seti 1 1 1
+mulr 2 1 4
+eqrr 4 3 4
+addr 4 5 5
+addi 5 1 5
+addr 2 0 0
+addi 1 1 1
+gtrr 1 3 4
+addr 5 4 5
+seti 2 8 5
+addi 2 1 2
+gtrr 2 3 4
+addr 4 5 5
+seti 1 7 5
+mulr 5 5 5
+";
+
+    const DIVIDE: &str = "\
+#ip 2
+seti 1000 0 3
+seti 0 0 1
+addi 1 1 4
+muli 4 256 4
+gtrr 4 3 4
+addr 4 2 2
+addi 2 1 2
+seti 9 0 2
+addi 1 1 1
+seti 1 0 2
+";
+
+    fn run(program: &Program, native: bool) -> (Registers, usize) {
+        let mut cpu = Cpu::new(program);
+        if native {
+            accelerate(&mut cpu);
+        }
+        cpu.run().unwrap();
+
+        (*cpu.reg(), cpu.ip())
+    }
+
+    #[test]
+    fn test_decompile() {
+        let program = parse_program(SUM_OF_DIVISORS).unwrap();
+        let mut decompilation = decompile(&program);
+        assert_eq!(decompilation.to_string(), "\
+// #ip 5
+r3 = 12;
+r2 = 1;
+loop {  // 2
+    r1 = 1;
+    loop {  // 3
+        r4 = r2 * r1;
+        if r4 == r3 {
+            r0 += r2;
+        }
+        r1 += 1;
+        if r1 > r3 {
+            break;
+        }
+    }
+    r2 += 1;
+    if r2 > r3 {
+        return;
+    }
+}
+");
+
+        decompilation.replace_natives();
+        assert_eq!(decompilation.to_string(), "\
+// #ip 5
+r3 = 12;
+r2 = 1;
+// Native code replacing the loop at 2 (continues at 16)
+r0 += (r2..=r3).filter(|&d| r3 % d == 0).sum::<usize>();
+");
+    }
+
+    #[test]
+    fn test_sum_of_divisors() {
+        let program = parse_program(SUM_OF_DIVISORS).unwrap();
+        let natives = decompile(&program).natives;
+        assert_eq!(natives, vec![Native {
+            addr: 2,
+            exit: 16,
+            kernel: Kernel::SumOfDivisors { i: 2, j: 1, n: 3, acc: 0, t: 4, flags: [4, 4, 4] },
+        }]);
+
+        let (reg, ip) = run(&program, true);
+        assert_eq!(reg[0], 28);
+        assert_eq!((reg, ip), run(&program, false));
+    }
+
+    #[test]
+    fn test_divide() {
+        let program = parse_program(DIVIDE).unwrap();
+        let natives = decompile(&program).natives;
+        assert_eq!(natives, vec![Native {
+            addr: 2,
+            exit: 7,
+            kernel: Kernel::Divide { i: 1, t: 4, n: 3, k: 256, flag: 4 },
+        }]);
+
+        let (reg, ip) = run(&program, true);
+        assert_eq!(reg[1], 3);
+        assert_eq!((reg, ip), run(&program, false));
+    }
+}
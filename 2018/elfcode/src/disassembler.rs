@@ -0,0 +1,138 @@
+use std::fmt::Write;
+use crate::machine::{Instruction, Opcode, Program, Word};
+
+/// Operand of an instruction
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operand {
+    /// Value known at this address (an immediate, or the bound instruction pointer)
+    Value(Word),
+    Register(Word),
+}
+
+impl Operand {
+    pub fn name(self) -> String {
+        match self {
+            Operand::Value(value) => value.to_string(),
+            Operand::Register(r) => format!("r{}", r),
+        }
+    }
+}
+
+/// Disassemble a program into pseudo-code
+///
+/// Reads of the bound instruction pointer are replaced by the address of the instruction,
+/// so writes to it are shown as jumps (with the target folded into a constant where possible).
+pub fn disassemble(program: &Program) -> String {
+    let mut listing = String::new();
+    if let Some(ip_reg) = program.ip_reg {
+        writeln!(listing, "#ip {}", ip_reg).unwrap();
+    }
+
+    for (addr, instruction) in program.instructions.iter().enumerate() {
+        let code = pseudocode(program, addr, instruction);
+        writeln!(listing, "{:4}: {:<16} {}", addr, instruction.to_string(), code).unwrap();
+    }
+
+    listing
+}
+
+/// Pseudo-code for the instruction at `addr`
+pub fn pseudocode(program: &Program, addr: usize, instruction: &Instruction) -> String {
+    let Instruction { op, c, .. } = *instruction;
+    let (x, y) = operands(program, addr, instruction);
+
+    if Some(c) != program.ip_reg {
+        return format!("r{} = {}", c, expression(op, x, y));
+    }
+
+    // Jumps go to the instruction after the one written to the instruction pointer
+    let target = match (x, y) {
+        (Operand::Value(x), Operand::Value(y)) => evaluate(op, x, y).map(|value| value + 1),
+        _ => None,
+    };
+
+    match (op, x, y, target) {
+        (_, _, _, Some(target)) if target >= program.len() => String::from("halt"),
+        (_, _, _, Some(target)) => format!("goto {}", target),
+        (Opcode::ADDR, Operand::Value(k), r @ Operand::Register(_), None)
+        | (Opcode::ADDR, r @ Operand::Register(_), Operand::Value(k), None)
+        | (Opcode::ADDI, r @ Operand::Register(_), Operand::Value(k), None) => {
+            format!("goto {} + {}", r.name(), k + 1)
+        },
+        _ => format!("goto ({}) + 1", expression(op, x, y)),
+    }
+}
+
+/// Operands of the instruction at `addr`
+pub(crate) fn operands(program: &Program, addr: usize, instruction: &Instruction) -> (Operand, Operand) {
+    let Instruction { op, a, b, .. } = *instruction;
+    let operand = |value: Word, is_register: bool| match value {
+        r if is_register && Some(r) == program.ip_reg => Operand::Value(addr),
+        r if is_register => Operand::Register(r),
+        value => Operand::Value(value),
+    };
+
+    (operand(a, op.a_is_register()), operand(b, op.b_is_register()))
+}
+
+/// Value computed by an opcode when both operands are known
+pub(crate) fn evaluate(op: Opcode, x: Word, y: Word) -> Option<Word> {
+    let mut reg = [x, y, 0];
+    let (a, b) = (if op.a_is_register() { 0 } else { x }, if op.b_is_register() { 1 } else { y });
+    op.call(&mut reg, a, b, 2).ok().map(|_| reg[2])
+}
+
+fn expression(op: Opcode, x: Operand, y: Operand) -> String {
+    if let (Operand::Value(x), Operand::Value(y)) = (x, y) {
+        if let Some(value) = evaluate(op, x, y) {
+            return value.to_string();
+        }
+    }
+
+    let operator = match op {
+        Opcode::SETR | Opcode::SETI => return x.name(),
+        Opcode::ADDR | Opcode::ADDI => "+",
+        Opcode::MULR | Opcode::MULI => "*",
+        Opcode::BANR | Opcode::BANI => "&",
+        Opcode::BORR | Opcode::BORI => "|",
+        Opcode::GTIR | Opcode::GTRI | Opcode::GTRR => ">",
+        Opcode::EQIR | Opcode::EQRI | Opcode::EQRR => "==",
+    };
+
+    format!("{} {} {}", x.name(), operator, y.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn test_disassemble() {
+        let program = parse_program("\
+#ip 5
+addi 5 16 5
+seti 1 8 2
+eqrr 4 3 4
+addr 4 5 5
+addi 5 1 5
+addr 2 0 0
+mulr 5 5 5
+gtir 3 2 1
+setr 5 0 5
+").unwrap();
+
+        assert_eq!(disassemble(&program), "\
+#ip 5
+   0: addi 5 16 5      halt
+   1: seti 1 8 2       r2 = 1
+   2: eqrr 4 3 4       r4 = r4 == r3
+   3: addr 4 5 5       goto r4 + 4
+   4: addi 5 1 5       goto 6
+   5: addr 2 0 0       r0 = r2 + r0
+   6: mulr 5 5 5       halt
+   7: gtir 3 2 1       r1 = 3 > r2
+   8: setr 5 0 5       halt
+");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod machine;
+pub mod parser;
+pub mod disassembler;
+pub mod decompiler;
@@ -0,0 +1,442 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use crate::parser;
+
+pub type Word = usize;
+
+/// Number of registers of the device
+pub const N_REGISTERS: usize = 6;
+
+pub type Registers = [Word; N_REGISTERS];
+
+/// Elfcode opcode
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Opcode {
+    ADDR,
+    ADDI,
+    MULR,
+    MULI,
+    BANR,
+    BANI,
+    BORR,
+    BORI,
+    SETR,
+    SETI,
+    GTIR,
+    GTRI,
+    GTRR,
+    EQIR,
+    EQRI,
+    EQRR,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 16] = [
+        Opcode::ADDR,
+        Opcode::ADDI,
+        Opcode::MULR,
+        Opcode::MULI,
+        Opcode::BANR,
+        Opcode::BANI,
+        Opcode::BORR,
+        Opcode::BORI,
+        Opcode::SETR,
+        Opcode::SETI,
+        Opcode::GTIR,
+        Opcode::GTRI,
+        Opcode::GTRR,
+        Opcode::EQIR,
+        Opcode::EQRI,
+        Opcode::EQRR,
+    ];
+
+    /// Mnemonic used in programs (e.g. `addi`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::ADDR => "addr",
+            Opcode::ADDI => "addi",
+            Opcode::MULR => "mulr",
+            Opcode::MULI => "muli",
+            Opcode::BANR => "banr",
+            Opcode::BANI => "bani",
+            Opcode::BORR => "borr",
+            Opcode::BORI => "bori",
+            Opcode::SETR => "setr",
+            Opcode::SETI => "seti",
+            Opcode::GTIR => "gtir",
+            Opcode::GTRI => "gtri",
+            Opcode::GTRR => "gtrr",
+            Opcode::EQIR => "eqir",
+            Opcode::EQRI => "eqri",
+            Opcode::EQRR => "eqrr",
+        }
+    }
+
+    /// Is operand `a` a register
+    pub fn a_is_register(self) -> bool {
+        !matches!(self, Opcode::SETI | Opcode::GTIR | Opcode::EQIR)
+    }
+
+    /// Is operand `b` a register (`setr` and `seti` ignore `b`)
+    pub fn b_is_register(self) -> bool {
+        matches!(self, Opcode::ADDR | Opcode::MULR | Opcode::BANR | Opcode::BORR
+            | Opcode::GTIR | Opcode::GTRR | Opcode::EQIR | Opcode::EQRR)
+    }
+
+    /// Execute the opcode on `reg`, which may have any number of registers
+    pub fn call(self, reg: &mut [Word], a: Word, b: Word, c: Word) -> Result<(), Exception> {
+        let get = |reg: &[Word], r: Word| reg.get(r).copied().ok_or(Exception::InvalidRegister(r));
+        let a = if self.a_is_register() { get(reg, a)? } else { a };
+        let b = if self.b_is_register() { get(reg, b)? } else { b };
+
+        let value = match self {
+            Opcode::ADDR | Opcode::ADDI => a + b,
+            Opcode::MULR | Opcode::MULI => a * b,
+            Opcode::BANR | Opcode::BANI => a & b,
+            Opcode::BORR | Opcode::BORI => a | b,
+            Opcode::SETR | Opcode::SETI => a,
+            Opcode::GTIR | Opcode::GTRI | Opcode::GTRR => (a > b) as Word,
+            Opcode::EQIR | Opcode::EQRI | Opcode::EQRR => (a == b) as Word,
+        };
+        *reg.get_mut(c).ok_or(Exception::InvalidRegister(c))? = value;
+
+        Ok(())
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Opcode::ALL.iter().copied()
+            .find(|op| op.name() == s)
+            .ok_or_else(|| format!("Unknown opcode {:?}", s))
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A single instruction (`op a b c`)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Instruction {
+    pub op: Opcode,
+    pub a: Word,
+    pub b: Word,
+    pub c: Word,
+}
+
+impl Instruction {
+    pub fn new(op: Opcode, a: Word, b: Word, c: Word) -> Self {
+        Instruction { op, a, b, c }
+    }
+
+    pub fn execute(&self, reg: &mut [Word]) -> Result<(), Exception> {
+        self.op.call(reg, self.a, self.b, self.c)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.op, self.a, self.b, self.c)
+    }
+}
+
+/// An Elfcode program, optionally with the instruction pointer bound to a register (`#ip N`)
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Program {
+    pub ip_reg: Option<usize>,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Program {
+    pub fn new(ip_reg: Option<usize>, instructions: &[Instruction]) -> Self {
+        Program { ip_reg, instructions: instructions.to_owned() }
+    }
+
+    /// Read a program from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Program, String> {
+        let input = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        parser::parse_program(&input)
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ip_reg) = self.ip_reg {
+            writeln!(f, "#ip {}", ip_reg)?;
+        }
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reason execution stopped
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Exception {
+    /// An instruction referred to a register that doesn't exist
+    InvalidRegister(Word),
+    /// Reached a breakpoint (before the instruction at this address was executed)
+    Breakpoint(usize),
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exception::InvalidRegister(r) => write!(f, "Invalid register {}", r),
+            Exception::Breakpoint(ip) => write!(f, "Breakpoint at {}", ip),
+        }
+    }
+}
+
+/// Native code run in place of the instruction at an address
+///
+/// Returns the address to continue at, or `None` to execute the instruction as usual.
+pub type NativeFn = Box<dyn Fn(&mut Registers) -> Option<usize>>;
+
+/// The wrist-mounted device
+///
+/// When the instruction pointer is bound to a register, the register is set to the
+/// instruction pointer before each instruction and read back afterwards, so programs can jump
+/// by writing to it. The device halts once the instruction pointer leaves the program.
+pub struct Cpu {
+    program: Program,
+    ip: usize,
+    reg: Registers,
+    cycles: u64,
+    breakpoints: BTreeSet<usize>,
+    natives: HashMap<usize, NativeFn>,
+    trace: bool,
+    /// Don't stop at the breakpoint at the current address (we've just stopped there)
+    resuming: bool,
+}
+
+impl Cpu {
+    pub fn new(program: &Program) -> Self {
+        Cpu {
+            program: program.clone(),
+            ip: 0,
+            reg: Registers::default(),
+            cycles: 0,
+            breakpoints: BTreeSet::new(),
+            natives: HashMap::new(),
+            trace: false,
+            resuming: false,
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+        self.resuming = false;
+    }
+
+    pub fn reg(&self) -> &Registers {
+        &self.reg
+    }
+
+    pub fn reg_mut(&mut self) -> &mut Registers {
+        &mut self.reg
+    }
+
+    /// Number of instructions executed
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Instruction at the instruction pointer (if not halted)
+    pub fn instruction(&self) -> Option<Instruction> {
+        self.program.instructions.get(self.ip).copied()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.ip >= self.program.len()
+    }
+
+    /// Print each instruction and the registers after it is executed to stderr
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace
+    }
+
+    pub fn add_breakpoint(&mut self, ip: usize) {
+        self.breakpoints.insert(ip);
+    }
+
+    pub fn remove_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.remove(&ip)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item=usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Run `native` instead of the instruction at `addr` (see `decompiler::accelerate`)
+    ///
+    /// A native replacement counts as a single cycle, and breakpoints in the code it replaces aren't hit.
+    pub fn add_native(&mut self, addr: usize, native: NativeFn) {
+        self.natives.insert(addr, native);
+    }
+
+    pub fn clear_natives(&mut self) {
+        self.natives.clear();
+    }
+
+    /// Execute a single instruction (ignoring breakpoints)
+    pub fn step(&mut self) -> Result<(), Exception> {
+        let instruction = match self.instruction() {
+            Some(instruction) => instruction,
+            None => return Ok(()),
+        };
+
+        let reg = &mut self.reg;
+        if let Some(next) = self.natives.get(&self.ip).and_then(|native| native(reg)) {
+            if self.trace {
+                eprintln!("{:4} {:<16} {:?}", self.ip, "(native)", self.reg);
+            }
+            self.ip = next;
+            self.cycles += 1;
+            self.resuming = false;
+            return Ok(());
+        }
+
+        if let Some(ip_reg) = self.program.ip_reg {
+            self.reg[ip_reg] = self.ip;
+        }
+        instruction.execute(&mut self.reg)?;
+        if self.trace {
+            eprintln!("{:4} {:<16} {:?}", self.ip, instruction.to_string(), self.reg);
+        }
+        if let Some(ip_reg) = self.program.ip_reg {
+            self.ip = self.reg[ip_reg];
+        }
+        self.ip += 1;
+        self.cycles += 1;
+        self.resuming = false;
+
+        Ok(())
+    }
+
+    /// Run until the program halts or reaches a breakpoint
+    ///
+    /// Running again after a breakpoint continues from the breakpoint.
+    pub fn run(&mut self) -> Result<(), Exception> {
+        while !self.is_halted() {
+            if !self.resuming && self.breakpoints.contains(&self.ip) {
+                self.resuming = true;
+                return Err(Exception::Breakpoint(self.ip));
+            }
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Run while `condition` holds (checked before each instruction), ignoring breakpoints
+    ///
+    /// Returns `true` if the program halted.
+    pub fn run_while<F>(&mut self, mut condition: F) -> Result<bool, Exception>
+        where F: FnMut(&Cpu) -> bool
+    {
+        while !self.is_halted() {
+            if !condition(self) {
+                return Ok(false);
+            }
+            self.step()?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcodes() {
+        // Example from day 16
+        let before = [3, 2, 1, 1];
+        let after = [3, 2, 2, 1];
+        let matches: Vec<_> = Opcode::ALL.iter().copied().filter(|op| {
+            let mut reg = before;
+            op.call(&mut reg, 2, 1, 2).is_ok() && reg == after
+        }).collect();
+
+        assert_eq!(matches, vec![Opcode::ADDI, Opcode::MULR, Opcode::SETI]);
+    }
+
+    #[test]
+    fn test_invalid_register() {
+        let mut reg = [0; 4];
+        assert_eq!(Opcode::ADDR.call(&mut reg, 0, 4, 0), Err(Exception::InvalidRegister(4)));
+        assert_eq!(Opcode::SETI.call(&mut reg, 4, 0, 4), Err(Exception::InvalidRegister(4)));
+        assert_eq!(Opcode::SETI.call(&mut reg, 4, 9, 3), Ok(()));
+        assert_eq!(reg, [0, 0, 0, 4]);
+    }
+
+    #[test]
+    fn test_ip_binding() {
+        // Example from day 19
+        let program = Program::from_file("../day19/sample.txt").unwrap();
+        let mut cpu = Cpu::new(&program);
+        assert_eq!(cpu.run(), Ok(()));
+
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.reg(), &[6, 5, 6, 0, 0, 9]);
+        assert_eq!(cpu.cycles(), 5);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let program = Program::from_file("../day19/sample.txt").unwrap();
+        let mut cpu = Cpu::new(&program);
+        cpu.add_breakpoint(6);
+
+        assert_eq!(cpu.run(), Err(Exception::Breakpoint(6)));
+        assert_eq!(cpu.reg(), &[5, 5, 6, 0, 0, 0]);
+        assert_eq!(cpu.run(), Ok(()));
+        assert_eq!(cpu.reg(), &[6, 5, 6, 0, 0, 9]);
+    }
+
+    #[test]
+    fn test_run_while() {
+        let program = Program::from_file("../day19/sample.txt").unwrap();
+        let mut cpu = Cpu::new(&program);
+
+        assert_eq!(cpu.run_while(|cpu| cpu.ip() != 4), Ok(false));
+        assert_eq!(cpu.ip(), 4);
+        assert_eq!(cpu.run_while(|_| true), Ok(true));
+    }
+}
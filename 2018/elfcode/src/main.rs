@@ -0,0 +1,305 @@
+use std::{env, fs, io, process};
+use std::io::BufRead;
+use elfcode::machine::{Cpu, Exception, Program, Word, N_REGISTERS};
+use elfcode::disassembler::{disassemble, pseudocode};
+use elfcode::decompiler::{accelerate, decompile};
+
+fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("disasm") => return disasm(),
+        Some("decompile") => return decompile_program(),
+        _ => (),
+    }
+
+    let args = parse_args();
+    let program = match Program::from_file(&args.program) {
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            process::exit(1);
+        },
+        Ok(program) => program,
+    };
+
+    run(&program, &args);
+}
+
+struct Args {
+    debug: bool,
+    break_at_start: bool,
+    breakpoints: Vec<usize>,
+    trace: bool,
+    native: bool,
+    registers: Vec<Word>,
+    program: String,
+}
+
+fn parse_args() -> Args {
+    let mut debug = false;
+    let mut break_at_start = false;
+    let mut breakpoints = Vec::new();
+    let mut trace = false;
+    let mut native = false;
+    let mut registers = Vec::new();
+    let mut posargs = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--debug" => debug = true,
+            "-B" | "--break" => break_at_start = true,
+            "-b" | "--breakpoint" => match args.next().map(|addr| addr.parse::<usize>()) {
+                Some(Ok(addr)) => breakpoints.push(addr),
+                _ => { print_usage(); process::exit(2) },
+            },
+            "-t" | "--trace" => trace = true,
+            "-n" | "--native" => native = true,
+            "-r" | "--registers" => match args.next().map(|r| parse_registers(&r)) {
+                Some(Ok(values)) => registers = values,
+                _ => { print_usage(); process::exit(2) },
+            },
+            "-h" | "--help" => { print_usage(); process::exit(0) },
+            arg if arg.starts_with('-') => {
+                eprintln!("ERROR: Unknown argument '{}'", arg);
+                print_usage();
+                process::exit(2);
+            },
+            _ => posargs.push(arg),
+        }
+    }
+
+    if posargs.len() != 1 {
+        print_usage();
+        process::exit(2);
+    }
+    let program = posargs.pop().unwrap();
+
+    Args { debug, break_at_start, breakpoints, trace, native, registers, program }
+}
+
+/// Parse comma-separated initial register values
+fn parse_registers(s: &str) -> Result<Vec<Word>, String> {
+    let values = s.split(',')
+        .map(|r| r.trim().parse().map_err(|_| format!("Invalid register value {:?}", r)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if values.len() > N_REGISTERS {
+        return Err(format!("Only {} registers", N_REGISTERS));
+    }
+
+    Ok(values)
+}
+
+fn print_usage() {
+    eprintln!("\
+USAGE: elfcode [-d | --debug] [-B | --break] [-b | --breakpoint ADDR]... [-t | --trace]
+               [-n | --native] [-r | --registers R0,R1,...] PROGRAM
+       elfcode disasm PROGRAM
+       elfcode decompile [-n | --native] PROGRAM
+Run Elfcode PROGRAM, disassemble PROGRAM into pseudo-code or decompile PROGRAM into pseudo-Rust.
+
+-d, --debug       enable debugging mode (traces execution and break into debugger on exceptions)
+-B, --break       immediately break into debugger
+-b, --breakpoint  break into debugger before executing the instruction at ADDR
+-t, --trace       print each instruction and the registers after it is executed
+-n, --native      replace recognised loops with native code
+-r, --registers   initial register values (default: all zero)")
+}
+
+/// Disassemble a program (`elfcode disasm`)
+fn disasm() {
+    let args: Vec<_> = env::args().skip(2).collect();
+    if args.len() != 1 {
+        print_usage();
+        process::exit(2);
+    }
+
+    match Program::from_file(&args[0]) {
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            process::exit(1);
+        },
+        Ok(program) => print!("{}", disassemble(&program)),
+    }
+}
+
+/// Decompile a program (`elfcode decompile`)
+fn decompile_program() {
+    let mut native = false;
+    let mut posargs = Vec::new();
+    for arg in env::args().skip(2) {
+        match arg.as_str() {
+            "-n" | "--native" => native = true,
+            _ => posargs.push(arg),
+        }
+    }
+    if posargs.len() != 1 {
+        print_usage();
+        process::exit(2);
+    }
+
+    match Program::from_file(&posargs[0]) {
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            process::exit(1);
+        },
+        Ok(program) => {
+            let mut decompilation = decompile(&program);
+            if native {
+                decompilation.replace_natives();
+            }
+            print!("{}", decompilation);
+        },
+    }
+}
+
+fn run(program: &Program, args: &Args) {
+    let mut cpu = Cpu::new(program);
+    cpu.reg_mut()[..args.registers.len()].copy_from_slice(&args.registers);
+    cpu.set_trace(args.trace || args.debug);
+    for &addr in &args.breakpoints {
+        cpu.add_breakpoint(addr);
+    }
+    if args.native {
+        for native in accelerate(&mut cpu) {
+            eprintln!("Replaced loop at {} with native code", native.addr);
+        }
+    }
+
+    if args.break_at_start {
+        attach_debugger(&mut cpu);
+    }
+
+    loop {
+        match cpu.run() {
+            Ok(()) => break,
+            Err(exception @ Exception::Breakpoint(_)) => {
+                eprintln!("{}", exception);
+                attach_debugger(&mut cpu);
+            },
+            Err(exception) => {
+                eprintln!("ERROR: {} (ip: {})", exception, cpu.ip());
+                if args.debug {
+                    attach_debugger(&mut cpu);
+                } else {
+                    print_state(&cpu);
+                    process::exit(4);
+                }
+            },
+        }
+    }
+
+    println!("HALT ip={} reg={:?} cycles={}", cpu.ip(), cpu.reg(), cpu.cycles());
+}
+
+fn print_state(cpu: &Cpu) {
+    match cpu.instruction() {
+        Some(instruction) => {
+            let code = pseudocode(cpu.program(), cpu.ip(), &instruction);
+            eprintln!("ip={} {:?} {:<16} {}", cpu.ip(), cpu.reg(), instruction.to_string(), code);
+        },
+        None => eprintln!("ip={} {:?} (halted)", cpu.ip(), cpu.reg()),
+    }
+}
+
+fn attach_debugger(cpu: &mut Cpu) {
+    // Read from TTY, even if stdin is redirected
+    let mut tty = match fs::File::open("/dev/tty") {
+        Err(err) => {
+            eprintln!("ERROR: Could not open TTY: {}", err);
+            return;
+        },
+        Ok(file) => io::BufReader::new(file),
+    };
+
+    // Disable tracing while running the debugger
+    let mut last_trace = cpu.is_tracing();
+    cpu.set_trace(false);
+
+    print_state(cpu);
+
+    let mut last_line = String::new();
+    loop {
+        eprint!("debug> ");
+        let mut line = String::new();
+        match tty.read_line(&mut line) {
+            Err(err) => {
+                eprintln!("ERROR: Failed to read input: {}", err);
+                continue
+            }
+            Ok(0) => break,
+            Ok(_) => (),
+        }
+
+        // Keep track of the last non-empty line to allow easy repeat
+        if line.trim().is_empty() {
+            line = last_line.clone();
+        } else {
+            last_line = line.clone();
+        }
+
+        let args: Vec<_> = line.split_whitespace().collect();
+        if args.is_empty() {
+            continue;
+        }
+
+        let result = match args[0] {
+            "p" | "print" => { print_state(cpu); Ok(()) },
+            "c" | "continue" => break,
+            "s" | "step" => cpu.step().map(|_| print_state(cpu)).map_err(|e| e.to_string()),
+            "j" | "jump" => read_param(&args, 1).map(|addr| cpu.set_ip(addr)).map(|_| print_state(cpu)),
+            "set" => {
+                read_param::<usize>(&args, 1)
+                    .and_then(|r| if r < N_REGISTERS { Ok(r) } else { Err(format!("No register {}", r)) })
+                    .and_then(|r| read_param(&args, 2).map(|value| cpu.reg_mut()[r] = value))
+                    .map(|_| print_state(cpu))
+            },
+            "b" | "break" => read_param(&args, 1).map(|addr| cpu.add_breakpoint(addr)),
+            "delete" => match args.get(1) {
+                Some(_) => read_param(&args, 1).map(|addr| { cpu.remove_breakpoint(addr); }),
+                None => { cpu.clear_breakpoints(); Ok(()) },
+            },
+            "i" | "info" => {
+                for addr in cpu.breakpoints() {
+                    eprintln!("breakpoint {}", addr);
+                }
+                Ok(())
+            },
+            "t" | "trace" => {
+                last_trace = !last_trace;
+                eprintln!("Tracing {}", if last_trace { "enabled" } else { "disabled" });
+                Ok(())
+            },
+            "d" | "disassemble" => { eprint!("{}", disassemble(cpu.program())); Ok(()) },
+            "q" | "quit" => process::exit(0),
+            "h" | "help" => {
+                eprintln!("p|print         Print the instruction pointer, registers and current instruction");
+                eprintln!("c|continue      Continue execution");
+                eprintln!("s|step          Step to the next instruction");
+                eprintln!("j|jump ADDR     Jump to address");
+                eprintln!("set REG VALUE   Set a register");
+                eprintln!("b|break ADDR    Set breakpoint");
+                eprintln!("delete [ADDR]   Delete breakpoint at address (default: all)");
+                eprintln!("i|info          List breakpoints");
+                eprintln!("t|trace         Toggle tracing when execution continues");
+                eprintln!("d|disassemble   Disassemble the program");
+                eprintln!("q|quit          Exit debugger and terminate program");
+                eprintln!("h|help          Print this help");
+                Ok(())
+            },
+            arg => Err(format!("Unknown command '{}'", arg)),
+        };
+
+        if let Err(err) = result {
+            eprintln!("ERROR: {}", err);
+        };
+    }
+
+    // Re-enable tracing if it was previously enabled
+    cpu.set_trace(last_trace);
+}
+
+fn read_param<T: std::str::FromStr>(args: &[&str], param: usize) -> Result<T, String> {
+    let arg = args.get(param).ok_or_else(|| String::from("Missing parameter"))?;
+
+    arg.parse::<T>().map_err(|_| format!("Failed to parse parameter {}", param))
+}
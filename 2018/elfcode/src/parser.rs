@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use crate::machine::{Instruction, Opcode, Program, Word, N_REGISTERS};
+
+/// An instruction with a numeric opcode (as in the day 16 samples)
+pub type RawInstruction = [Word; 4];
+
+/// Registers before and after executing an instruction with an unknown opcode
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Sample {
+    pub before: [Word; 4],
+    pub instruction: RawInstruction,
+    pub after: [Word; 4],
+}
+
+impl Sample {
+    /// Opcodes that behave like the sample
+    pub fn matches(&self) -> Vec<Opcode> {
+        let [_, a, b, c] = self.instruction;
+        Opcode::ALL.iter().copied()
+            .filter(|op| {
+                let mut reg = self.before;
+                op.call(&mut reg, a, b, c).is_ok() && reg == self.after
+            })
+            .collect()
+    }
+}
+
+/// Parse a program (one `op a b c` per line, with an optional `#ip N` directive)
+pub fn parse_program(input: &str) -> Result<Program, String> {
+    let mut program = Program::default();
+    for (n, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parse = || -> Result<(), String> {
+            if let Some(reg) = line.strip_prefix("#ip") {
+                let reg = reg.trim().parse().ok()
+                    .filter(|&reg| reg < N_REGISTERS)
+                    .ok_or_else(|| format!("Invalid register {:?}", reg.trim()))?;
+                if program.ip_reg.replace(reg).is_some() {
+                    return Err(String::from("Duplicate #ip directive"));
+                }
+            } else {
+                let (op, args) = line.split_at(line.find(' ').unwrap_or(line.len()));
+                let [a, b, c] = parse_words(args)?;
+                program.instructions.push(Instruction::new(op.parse()?, a, b, c));
+            }
+
+            Ok(())
+        };
+        parse().map_err(|err| format!("Line {}: {}", n + 1, err))?;
+    }
+
+    Ok(program)
+}
+
+/// Parse samples followed by a program with numeric opcodes (the day 16 format)
+///
+/// ```text
+/// Before: [3, 2, 1, 1]
+/// 9 2 1 2
+/// After:  [3, 2, 2, 1]
+///
+///
+///
+/// 9 2 1 2
+/// ```
+pub fn parse_samples(input: &str) -> Result<(Vec<Sample>, Vec<RawInstruction>), String> {
+    let (samples_input, program_input) = match input.find("\n\n\n") {
+        Some(split) => input.split_at(split),
+        None => (input, ""),
+    };
+
+    let mut samples = Vec::new();
+    let mut lines = samples_input.lines().map(str::trim).filter(|line| !line.is_empty());
+    while let Some(line) = lines.next() {
+        let before = parse_registers(line, "Before:")?;
+        let instruction = parse_words(lines.next().ok_or("Sample is missing instruction")?)?;
+        let after = parse_registers(lines.next().ok_or("Sample is missing result")?, "After:")?;
+
+        samples.push(Sample { before, instruction, after });
+    }
+
+    let program = program_input.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_words)
+        .collect::<Result<_, _>>()?;
+
+    Ok((samples, program))
+}
+
+/// Convert a program with numeric opcodes to a `Program`
+pub fn decode(program: &[RawInstruction], opcodes: &HashMap<Word, Opcode>) -> Result<Program, String> {
+    let instructions = program.iter()
+        .map(|&[op, a, b, c]| {
+            let op = *opcodes.get(&op).ok_or_else(|| format!("Unknown opcode {}", op))?;
+            Ok(Instruction::new(op, a, b, c))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(Program::new(None, &instructions))
+}
+
+/// Parse `PREFIX [a, b, c, d]`
+fn parse_registers(line: &str, prefix: &str) -> Result<[Word; 4], String> {
+    let registers = line.strip_prefix(prefix)
+        .map(str::trim)
+        .and_then(|s| s.strip_prefix('['))
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or_else(|| format!("Expected {:?} followed by registers: {:?}", prefix, line))?;
+
+    parse_words(&registers.replace(',', " "))
+}
+
+/// Parse exactly `N` whitespace-separated words
+fn parse_words<const N: usize>(s: &str) -> Result<[Word; N], String> {
+    let words = s.split_whitespace()
+        .map(|w| w.parse().map_err(|_| format!("Invalid value {:?}", w)))
+        .collect::<Result<Vec<Word>, _>>()?;
+
+    let n = words.len();
+    words.try_into().map_err(|_| format!("Expected {} values, found {}", N, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_program() {
+        let program = parse_program("#ip 0\nseti 5 0 1\neqrr 1 2 3\n").unwrap();
+        assert_eq!(program.ip_reg, Some(0));
+        assert_eq!(program.instructions, vec![
+            Instruction::new(Opcode::SETI, 5, 0, 1),
+            Instruction::new(Opcode::EQRR, 1, 2, 3),
+        ]);
+        assert_eq!(program.to_string(), "#ip 0\nseti 5 0 1\neqrr 1 2 3\n");
+
+        assert_eq!(parse_program("seti 5 0\n"), Err(String::from("Line 1: Expected 3 values, found 2")));
+        assert_eq!(parse_program("nop 1 2 3\n"), Err(String::from("Line 1: Unknown opcode \"nop\"")));
+    }
+
+    #[test]
+    fn test_parse_ip_register() {
+        assert_eq!(parse_program("#ip 5\n").map(|program| program.ip_reg), Ok(Some(5)));
+        assert_eq!(parse_program("#ip 6\n"), Err(String::from("Line 1: Invalid register \"6\"")));
+        assert_eq!(parse_program("#ip -1\n"), Err(String::from("Line 1: Invalid register \"-1\"")));
+        assert_eq!(parse_program("#ip 0\n#ip 1\n"), Err(String::from("Line 2: Duplicate #ip directive")));
+    }
+
+    #[test]
+    fn test_parse_samples() {
+        let input = "\
+Before: [3, 2, 1, 1]
+9 2 1 2
+After:  [3, 2, 2, 1]
+
+Before: [0, 1, 2, 3]
+1 0 2 1
+After:  [0, 2, 2, 3]
+
+
+
+9 2 1 2
+1 0 2 1
+";
+        let (samples, program) = parse_samples(input).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0], Sample { before: [3, 2, 1, 1], instruction: [9, 2, 1, 2], after: [3, 2, 2, 1] });
+        assert_eq!(samples[0].matches(), vec![Opcode::ADDI, Opcode::MULR, Opcode::SETI]);
+        assert_eq!(program, vec![[9, 2, 1, 2], [1, 0, 2, 1]]);
+
+        let opcodes = [(9, Opcode::ADDI), (1, Opcode::ADDR)].iter().copied().collect();
+        let program = decode(&program, &opcodes).unwrap();
+        assert_eq!(program.to_string(), "addi 2 1 2\naddr 0 2 1\n");
+    }
+}
@@ -0,0 +1,130 @@
+// Joystick controllers
+use std::{cmp, fs, io};
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use crate::Screen;
+use intcode::emulator::Word;
+
+/// Position of the joystick
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Joystick {
+    Left,
+    Neutral,
+    Right,
+}
+
+impl Joystick {
+    fn parse(s: &str) -> Result<Joystick, String> {
+        match s.trim() {
+            "-1" => Ok(Joystick::Left),
+            "0" => Ok(Joystick::Neutral),
+            "1" => Ok(Joystick::Right),
+            s => Err(format!("Unknown joystick position {:?}", s)),
+        }
+    }
+}
+
+impl From<Joystick> for Word {
+    fn from(joystick: Joystick) -> Self {
+        match joystick {
+            Joystick::Left => -1,
+            Joystick::Neutral => 0,
+            Joystick::Right => 1,
+        }
+    }
+}
+
+/// Moves the joystick
+pub trait Controller {
+    /// Position of the joystick for the next frame
+    fn joystick(&mut self, screen: &Screen) -> Joystick;
+}
+
+/// Keeps the paddle under the ball
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Autopilot;
+
+impl Controller for Autopilot {
+    fn joystick(&mut self, screen: &Screen) -> Joystick {
+        match screen.ball.0.cmp(&screen.paddle.0) {
+            cmp::Ordering::Less => Joystick::Left,
+            cmp::Ordering::Greater => Joystick::Right,
+            cmp::Ordering::Equal => Joystick::Neutral,
+        }
+    }
+}
+
+/// Reads moves from stdin, one line per frame
+///
+/// `a` (or `h`) moves left, `d` (or `l`) moves right and anything else holds the paddle still.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Keyboard;
+
+impl Controller for Keyboard {
+    fn joystick(&mut self, _: &Screen) -> Joystick {
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line).is_err() {
+            return Joystick::Neutral;
+        }
+
+        match line.trim() {
+            "a" | "h" => Joystick::Left,
+            "d" | "l" => Joystick::Right,
+            _ => Joystick::Neutral,
+        }
+    }
+}
+
+/// Replays a joystick log, then holds the paddle still
+#[derive(Clone, Debug, Default)]
+pub struct Replay {
+    moves: VecDeque<Joystick>,
+}
+
+impl Replay {
+    pub fn new(moves: &[Joystick]) -> Self {
+        Replay { moves: moves.iter().copied().collect() }
+    }
+
+    /// Read a joystick log (see `Logger`)
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let log = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let moves = log.lines().map(Joystick::parse).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Replay::new(&moves))
+    }
+}
+
+impl Controller for Replay {
+    fn joystick(&mut self, _: &Screen) -> Joystick {
+        self.moves.pop_front().unwrap_or(Joystick::Neutral)
+    }
+}
+
+/// Writes every move made by another controller to a joystick log (one position per line)
+pub struct Logger<C: Controller> {
+    controller: C,
+    writer: Box<dyn Write>,
+}
+
+impl<C: Controller> Logger<C> {
+    pub fn new(controller: C, writer: Box<dyn Write>) -> Self {
+        Logger { controller, writer }
+    }
+}
+
+impl<C: Controller> Controller for Logger<C> {
+    fn joystick(&mut self, screen: &Screen) -> Joystick {
+        let joystick = self.controller.joystick(screen);
+        writeln!(self.writer, "{}", Word::from(joystick)).expect("Failed to write joystick log");
+
+        joystick
+    }
+}
+
+impl Controller for Box<dyn Controller> {
+    fn joystick(&mut self, screen: &Screen) -> Joystick {
+        (**self).joystick(screen)
+    }
+}
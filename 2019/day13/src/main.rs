@@ -1,86 +1,130 @@
-use intcode::emulator::{Program, IntcodeEmulator, Word};
-use std::collections::{VecDeque};
-use std::{fmt, cmp, time, thread, env, io};
-use std::io::Write;
-use std::cell::RefCell;
-use std::rc::Rc;
-
-const WIDTH: usize = 44;
-const HEIGHT: usize = 20;
+use intcode::emulator::{Program, IntcodeEmulator, Event, Word};
+use intcode::terminal::Terminal;
+use std::collections::HashMap;
+use std::{fmt, time, thread, env, fs, io, process};
+use crate::controller::{Controller, Autopilot, Keyboard, Replay, Logger};
+
+mod controller;
+
+const WIDTH: u32 = 44;
+const HEIGHT: u32 = 20;
 const FPS: u64 = 12;
-const TURBO_FPS: u64 = 9999;
 
 // Use full-width characters for the score (not all terminals support)
 const FULLWIDTH: bool = true;
 
+const USAGE: &str = "USAGE: day13 [--turbo] [--record FILE] [--headless] [--play | --replay LOG] [--log LOG]";
+
 fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let arg_value = |name: &str| args.iter().position(|arg| arg == name).map(|i| args.get(i + 1).unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }));
+
     let program = Program::from_file("input.txt").expect("Failed to read input");
 
     // Part 1
-    let mut arcade = ArcadeCabinet::new();
-    arcade.run(&program);
-    println!("Part 1: Tiles on screen: {}", arcade.state.borrow().n_blocks);
+    let mut arcade = ArcadeCabinet::new(&program, false, Box::new(Autopilot));
+    while arcade.next_frame() {}
+    println!("Part 1: Tiles on screen: {}", arcade.screen().count(Tile::Block));
 
     // Part 2
-    println!("Part 2:");
-    let mut arcade = ArcadeCabinet::new();
-    arcade.freeplay(true);
-    if env::args().any(|a| a.trim() == "--turbo") {
-        arcade.turbo(true);
+    let mut controller: Box<dyn Controller> = if args.iter().any(|arg| arg == "--play") {
+        Box::new(Keyboard)
+    } else if let Some(path) = arg_value("--replay") {
+        Box::new(Replay::from_file(path).unwrap_or_else(|err| {
+            eprintln!("ERROR: Failed to read {}: {}", path, err);
+            process::exit(1);
+        }))
     } else {
+        Box::new(Autopilot)
+    };
+    if let Some(path) = arg_value("--log") {
+        let file = fs::File::create(path).expect("Failed to create joystick log");
+        controller = Box::new(Logger::new(controller, Box::new(io::BufWriter::new(file))));
+    }
+
+    let mut arcade = ArcadeCabinet::new(&program, true, controller);
+    if args.iter().any(|arg| arg == "--headless") {
+        while arcade.next_frame() {}
+        println!("Part 2: Final score: {}", arcade.screen().score);
+        return;
+    }
+
+    let mut terminal = Terminal::from_args(WIDTH, HEIGHT + 2, FPS).expect("Failed to open terminal");
+    println!("Part 2:");
+    if !terminal.is_turbo() && !terminal.is_recording() {
         println!();
         println!(" [ You may wish to run this with --turbo ]");
         println!();
         thread::sleep(time::Duration::from_secs(4));
     }
-    arcade.run(&program);
+
+    terminal.setup();
+    loop {
+        let running = arcade.next_frame();
+        draw(&mut terminal, arcade.screen());
+        terminal.frame().expect("Failed to draw frame");
+        if !running {
+            break;
+        }
+    }
 
     // Be nice and reset the user's terminal
-    print!("\x1Bc");
-    println!("Final score: {}", arcade.state.borrow().score);
+    terminal.finish().expect("Failed to reset terminal");
+    println!("Final score: {}", arcade.screen().score);
 }
 
+/// Arcade cabinet, run one frame at a time
 struct ArcadeCabinet {
-    freeplay: bool,
     cpu: IntcodeEmulator,
-    state: Rc<RefCell<GameState>>
+    controller: Box<dyn Controller>,
+    screen: Screen,
+    output_queue: Vec<Word>,
+    joystick: Option<Word>,
 }
 
 impl ArcadeCabinet {
-    fn new() -> ArcadeCabinet {
-        ArcadeCabinet {
-            freeplay: false,
-            cpu: IntcodeEmulator::default(),
-            state: Rc::new(RefCell::new(GameState::new())),
+    /// Create a new arcade cabinet running `program`
+    ///
+    /// With `freeplay` no quarters are required, so the game can be played.
+    fn new(program: &Program, freeplay: bool, controller: Box<dyn Controller>) -> ArcadeCabinet {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(program);
+        if freeplay {
+            cpu.mem_mut()[0] = 2;
         }
-    }
 
-    fn freeplay(&mut self, freeplay: bool) {
-        self.freeplay = freeplay;
+        ArcadeCabinet { cpu, controller, screen: Screen::default(), output_queue: Vec::new(), joystick: None }
     }
 
-    fn turbo(&mut self, turbo: bool) {
-        self.state.borrow_mut().fps = if turbo { TURBO_FPS } else { FPS };
+    /// The screen, as of the last frame
+    fn screen(&self) -> &Screen {
+        &self.screen
     }
 
-    fn run(&mut self, program: &Program) {
-        print!("\x1B[8;{};{}t", HEIGHT + 2, WIDTH);  // Resize console
-        print!("\x1B[2J");  // Clear screen
-        print!("\x1B[?25l");  // Hide cursor
-
-        self.cpu.load_program(program);
-        let state = Rc::clone(&self.state);
-        self.cpu.set_input_handler(Box::new(move |_| state.borrow_mut().handle_input()));
-        let state = Rc::clone(&self.state);
-        self.cpu.set_output_handler(Box::new(move |_, word| state.borrow_mut().handle_output(word)));
-
-        if self.freeplay {
-            self.cpu.mem_mut()[0] = 2;
-        }
+    /// Run until the game finishes drawing the next frame (when it reads the joystick)
+    ///
+    /// Returns `false` once the game is over.
+    fn next_frame(&mut self) -> bool {
+        self.screen.changed.clear();
 
+        let mut input = self.joystick.take();
         loop {
-            match self.cpu.run() {
-                Ok(()) => break,
+            match self.cpu.resume(input.take()) {
+                Ok(Event::Output(word)) => {
+                    self.output_queue.push(word);
+                    if let [x, y, value] = self.output_queue[..] {
+                        self.screen.update((x, y), value);
+                        self.output_queue.clear();
+                    }
+                },
+                Ok(Event::NeedInput) => {
+                    self.joystick = Some(self.controller.joystick(&self.screen).into());
+                    return true;
+                },
+                Ok(Event::Halted) => return false,
                 Err(exception) => {
                     self.cpu.dump_registers();
                     self.cpu.print_disassembled();
@@ -89,129 +133,98 @@ impl ArcadeCabinet {
                 },
             }
         }
-
-        // Make sure previous line is closed
-        println!();
-    }
-
-    fn draw_score(score: Word) {
-        print!("\x1B[H");  // Move cursor to HOME
-        print!("\x1B[2K");  // Clear score line
-        print!("\x1B]0;SCORE: {:08}\x07", score);  // Show score in console title
-        if FULLWIDTH {
-            let chars: String = format!("{:08}", score).chars().map(|c| match c {
-                '0' => '０',
-                '1' => '１',
-                '2' => '２',
-                '3' => '３',
-                '4' => '４',
-                '5' => '５',
-                '6' => '６',
-                '7' => '７',
-                '8' => '８',
-                '9' => '９',
-                _ => '?',
-            }).collect();
-            println!("SCORE: {}", chars);
-        } else {
-            println!("SCORE: {:08}", score);
-        }
-    }
-
-    fn draw_tile(tile: Tile, pos: (Word, Word)) {
-        let color = match tile {
-           Tile::Block => match pos.1 {
-                2..=3 => "\x1B[35m",  // Magenta
-                4..=5 => "\x1B[31m",  // Red
-                6..=7 => "\x1B[33m",  // Yellow
-                8..=9 => "\x1B[32m",  // Green
-                10..=11 => "\x1B[34m",  // Blue
-                12..=13 => "\x1B[36m",  // Cyan
-                _ => "",
-            },
-            Tile::Ball | Tile::Paddle => "\x1B[35;1m",  // Magenta
-            _ => "",
-        };
-        print!("\x1B[{};{}H{}{}\x1B[m", pos.1 + 2, pos.0 + 1, color, tile);
     }
 }
 
-struct GameState {
+/// What's shown on the arcade's screen
+#[derive(Clone, Debug, Default)]
+struct Screen {
+    tiles: HashMap<(Word, Word), Tile>,
+    ball: (Word, Word),
+    paddle: (Word, Word),
     score: Word,
-    output_queue: VecDeque<Word>,
-    ball_pos: (Word, Word),
-    paddle_pos: (Word, Word),
-    n_blocks: u32,
-    fps: u64,
+    /// Positions drawn since the previous frame
+    changed: Vec<(Word, Word)>,
 }
 
-impl GameState {
-    fn new() -> Self {
-        GameState {
-            score: 0,
-            output_queue: VecDeque::new(),
-            ball_pos: (0, 0),
-            paddle_pos: (0, 0),
-            n_blocks: 0,
-            fps: FPS,
+impl Screen {
+    /// Handle a draw instruction (the position `(-1, 0)` sets the score)
+    fn update(&mut self, pos: (Word, Word), value: Word) {
+        if pos == (-1, 0) {
+            self.score = value;
+            return;
         }
-    }
 
-    fn handle_input(&mut self) -> io::Result<Word> {
-        let input = match self.ball_pos.0.cmp(&self.paddle_pos.0) {
-            cmp::Ordering::Less => -1,  // Left
-            cmp::Ordering::Greater => 1,  // Right
-            cmp::Ordering::Equal => 0,  // Hold
-        };
+        let tile = value.into();
+        match tile {
+            Tile::Ball => self.ball = pos,
+            Tile::Paddle => self.paddle = pos,
+            _ => (),
+        }
+        self.tiles.insert(pos, tile);
+        self.changed.push(pos);
+    }
 
-        Ok(input)
+    /// The tile at `pos`
+    fn tile(&self, pos: (Word, Word)) -> Tile {
+        self.tiles.get(&pos).copied().unwrap_or(Tile::Empty)
     }
 
-    fn handle_output(&mut self, word: Word) -> io::Result<()> {
-        self.output_queue.push_back(word);
-        if self.output_queue.len() < 3 {
-            // Need more input
-            return Ok(());
-        }
+    /// Number of tiles of a certain type
+    fn count(&self, tile: Tile) -> usize {
+        self.tiles.values().filter(|&&t| t == tile).count()
+    }
+}
 
-        let x = self.output_queue.pop_front().unwrap();
-        let y = self.output_queue.pop_front().unwrap();
-        let tile_id = self.output_queue.pop_front().unwrap();
-
-        if x == -1 && y == 0 {
-            self.score = tile_id;
-            ArcadeCabinet::draw_score(self.score);
-        } else {
-            let tile = tile_id.into();
-
-            match tile {
-                Tile::Ball => {
-                    self.ball_pos = (x, y);
-                    ArcadeCabinet::draw_tile(tile, (x, y));
-
-                    // Update of the ball position is used to flush display output
-                    // and rate-limit the game's FPS
-                    io::stdout().flush().expect("Failed to flush stdout");
-                    if self.fps < 1000 {
-                        thread::sleep(time::Duration::from_micros(1_000_000 / self.fps));
-                    }
-                },
-                Tile::Paddle => {
-                    self.paddle_pos = (x, y);
-                    ArcadeCabinet::draw_tile(tile, (x, y));
-                },
-                Tile::Block => {
-                    self.n_blocks += 1;
-                    ArcadeCabinet::draw_tile(tile, (x, y));
-                },
-                _ => ArcadeCabinet::draw_tile(tile, (x, y)),
-            }
-        }
+/// Draw changes to the screen since the last frame
+fn draw(terminal: &mut Terminal, screen: &Screen) {
+    for &pos in &screen.changed {
+        draw_tile(terminal, screen.tile(pos), pos);
+    }
+    draw_score(terminal, screen.score);
+}
 
-        Ok(())
+fn draw_score(terminal: &mut Terminal, score: Word) {
+    terminal.print("\x1B[H");  // Move cursor to HOME
+    terminal.print("\x1B[2K");  // Clear score line
+    terminal.print(&format!("\x1B]0;SCORE: {:08}\x07", score));  // Show score in console title
+    if FULLWIDTH {
+        let chars: String = format!("{:08}", score).chars().map(|c| match c {
+            '0' => '０',
+            '1' => '１',
+            '2' => '２',
+            '3' => '３',
+            '4' => '４',
+            '5' => '５',
+            '6' => '６',
+            '7' => '７',
+            '8' => '８',
+            '9' => '９',
+            _ => '?',
+        }).collect();
+        terminal.print(&format!("SCORE: {}\n", chars));
+    } else {
+        terminal.print(&format!("SCORE: {:08}\n", score));
     }
 }
 
+fn draw_tile(terminal: &mut Terminal, tile: Tile, pos: (Word, Word)) {
+    let color = match tile {
+       Tile::Block => match pos.1 {
+            2..=3 => "\x1B[35m",  // Magenta
+            4..=5 => "\x1B[31m",  // Red
+            6..=7 => "\x1B[33m",  // Yellow
+            8..=9 => "\x1B[32m",  // Green
+            10..=11 => "\x1B[34m",  // Blue
+            12..=13 => "\x1B[36m",  // Cyan
+            _ => "",
+        },
+        Tile::Ball | Tile::Paddle => "\x1B[35;1m",  // Magenta
+        _ => "",
+    };
+    terminal.print(&format!("\x1B[{};{}H{}{}\x1B[m", pos.1 + 2, pos.0 + 1, color, tile));
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 enum Tile {
     Empty,
@@ -253,3 +266,53 @@ impl fmt::Display for Tile {
         write!(f, "{}", self.to_char())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::Joystick;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Autopilot that remembers its moves
+    struct Recorder(Rc<RefCell<Vec<Joystick>>>);
+
+    impl Controller for Recorder {
+        fn joystick(&mut self, screen: &Screen) -> Joystick {
+            let joystick = Autopilot.joystick(screen);
+            self.0.borrow_mut().push(joystick);
+
+            joystick
+        }
+    }
+
+    fn play(controller: Box<dyn Controller>) -> (Screen, usize) {
+        let program = Program::from_file("input.txt").expect("Failed to read input");
+        let mut arcade = ArcadeCabinet::new(&program, true, controller);
+        let mut frames = 0;
+        while arcade.next_frame() {
+            frames += 1;
+            assert!(arcade.screen().count(Tile::Ball) <= 1);
+        }
+
+        (arcade.screen().clone(), frames)
+    }
+
+    #[test]
+    fn test_autopilot() {
+        let (screen, _) = play(Box::new(Autopilot));
+        assert_eq!(screen.count(Tile::Block), 0);
+        assert_eq!(screen.score, 16309);
+    }
+
+    #[test]
+    fn test_replay() {
+        let moves = Rc::new(RefCell::new(Vec::new()));
+        let (screen, frames) = play(Box::new(Recorder(Rc::clone(&moves))));
+        assert_eq!(moves.borrow().len(), frames);
+
+        let (replay, _) = play(Box::new(Replay::new(&moves.borrow())));
+        assert_eq!(replay.score, screen.score);
+        assert_eq!(replay.tiles, screen.tiles);
+    }
+}
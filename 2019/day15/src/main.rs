@@ -1,8 +1,8 @@
 use intcode::emulator::{IntcodeEmulator, Context, Program, Exception, Word};
+use intcode::terminal::Terminal;
 use std::convert::{TryFrom, TryInto};
 use std::collections::{HashMap, HashSet};
-use std::{ops, thread, env, io};
-use std::time::Duration;
+use std::{ops, io};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -23,10 +23,10 @@ const FPS: u64 = 12;
 
 // Helper macro for printing the status line
 macro_rules! print_status {
-    ( $($e:expr),* ) => { print_status(&format!($($e),*)) };
+    ( $terminal:expr, $($e:expr),* ) => { print_status($terminal, &format!($($e),*)) };
 }
 
-fn draw(tile: char, pos: Pos) {
+fn draw(terminal: &mut Terminal, tile: char, pos: Pos) {
     let color = match tile {
         TARGET => "\x1B[31m",  // Red
         DROID => "\x1B[32m",  // Green
@@ -35,17 +35,17 @@ fn draw(tile: char, pos: Pos) {
         OXYGEN => "\x1B[36m",  // Cyan
         _ => "",
     };
-    print!("\x1B[{};{}H{}{}\x1B[m", SCREEN_OFFSET_Y as i32 - pos.y + 1, SCREEN_OFFSET_X as i32 + pos.x + 1, color, tile);
+    terminal.print(&format!("\x1B[{};{}H{}{}\x1B[m", SCREEN_OFFSET_Y as i32 - pos.y + 1, SCREEN_OFFSET_X as i32 + pos.x + 1, color, tile));
 }
 
-fn print_status(message: &str) {
+fn print_status(terminal: &mut Terminal, message: &str) {
     // Move to status line (second last line), clear it and print message
-    println!("\x1B[{}H\x1B[K{}", HEIGHT - 1, message);
+    terminal.print(&format!("\x1B[{}H\x1B[K{}\n", HEIGHT - 1, message));
 }
 
 fn main() {
-    // The --turbo flag skips all animation delays
-    let turbo = env::args().skip(1).any(|arg| &arg == "--turbo");
+    // The --turbo flag skips all animation delays and --record FILE records an asciicast
+    let mut terminal = Terminal::from_args(WIDTH, HEIGHT, FPS).expect("Failed to open terminal");
 
     // Part 1
     let program = Program::from_file("input.txt").expect("Failed to read input");
@@ -53,45 +53,40 @@ fn main() {
     let mut droid = Droid::new(&program, ORIGIN);
     let mut planner = Planner::new();
 
-    print!("\x1B[2J");  // Clear screen
-    print!("\x1B[?25l");  // Hide cursor
-    print!("\x1B[8;{};{}t", HEIGHT, WIDTH);  // Resize console
+    terminal.setup();
 
     // Part 1: Find the broken O₂ system
     let mut o2_system = None;
     while let Some(command) = planner.plan(droid.pos) {
         // Clear the droid from the map
-        draw( planner.get_tile(droid.pos), droid.pos);
+        draw(&mut terminal, planner.get_tile(droid.pos), droid.pos);
 
         let target = droid.pos + command.direction();
         match droid.execute(command) {
             Status::Wall => {
                 planner.update_map(target, WALL);
-                draw( WALL, target);
+                draw(&mut terminal, WALL, target);
             },
             Status::Moved => {
                 planner.update_map(target, OPEN);
-                draw( OPEN, target);
+                draw(&mut terminal, OPEN, target);
             },
             Status::MovedAndFoundTarget => {
                 o2_system = Some(target);
                 planner.update_map(target, TARGET);
-                draw( TARGET, target);
+                draw(&mut terminal, TARGET, target);
             },
         }
 
-        draw( DROID, droid.pos);
-        print_status!("{:?} (distance: {})", droid.pos, planner.distance_from_origin(droid.pos));
-
-        if !turbo {
-            thread::sleep(Duration::from_millis(1000 / FPS));
-        }
+        draw(&mut terminal, DROID, droid.pos);
+        print_status!(&mut terminal, "{:?} (distance: {})", droid.pos, planner.distance_from_origin(droid.pos));
+        terminal.frame().expect("Failed to draw frame");
     }
 
     // Found the leak!
     let o2_system = o2_system.expect("Failed to find the O₂ system");
     let distance = planner.distance_from_origin(o2_system);
-    draw(planner.get_tile(droid.pos), droid.pos); // Hide the droid, it's work is done
+    draw(&mut terminal, planner.get_tile(droid.pos), droid.pos); // Hide the droid, it's work is done
 
     // Part 2: Fill the map with O₂
     let mut oxygenated: HashSet<Pos> = [o2_system].iter().copied().collect();
@@ -101,21 +96,18 @@ fn main() {
         let mut new_edge = HashSet::new();
         for pos in edge.drain() {
             oxygenated.insert(pos);
-            draw(OXYGEN, pos);
+            draw(&mut terminal, OXYGEN, pos);
             new_edge.extend(planner.adjacent(pos).into_iter().filter(|&pos| !oxygenated.contains(&pos)));
         }
 
         edge = new_edge;
         t += 1;
 
-        print_status!("t: {} min", t);
-
-        if !turbo {
-            thread::sleep(Duration::from_millis(1000 / FPS));
-        }
+        print_status!(&mut terminal, "t: {} min", t);
+        terminal.frame().expect("Failed to draw frame");
     }
 
-    print!("\x1Bc");  // Reset the terminal
+    terminal.finish().expect("Failed to reset terminal");
     println!("Part 1: Found the O₂ system at {:?} (distance: {})", o2_system, distance);
     println!("Part 2: Time required to fill map with O₂: {} min", t);
 }
@@ -1,9 +1,9 @@
 use intcode::emulator::{Program, IntcodeEmulator, Word, Context};
-use std::{io, thread};
-use std::cell::RefCell;
+use intcode::terminal::Terminal;
+use std::io;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::collections::{HashMap, VecDeque};
-use std::time::Duration;
 
 type Pos = (usize, usize);
 
@@ -11,6 +11,10 @@ const SPACE: char = '.';
 const SCAFFOLDING: char = '#';
 const FPS: u64 = 24;
 
+// Movement functions
+const FUNCTION_NAMES: [&str; 3] = ["A", "B", "C"];
+const MAX_ROUTINE_LEN: usize = 20;
+
 fn main() {
     let program = Program::from_file("input.txt").expect("Failed to read input");
 
@@ -23,11 +27,14 @@ fn main() {
     println!("Part 1: Sum of alignment parameters: {}", calibration);
 
     // Part 2
+    let path = map.trace_path();
+    let routines = Routines::compress(&path).expect("Failed to compress path");
     println!("Part 2:");
-    let mut robot = Robot::new(&program);
+    let terminal = Terminal::from_args(map.width as u32, map.height as u32 + 2, FPS).expect("Failed to open terminal");
+    let mut robot = Robot::new(&program, &routines, terminal);
     robot.set_active(true);
-    robot.run();
-
+    let dust = robot.run();
+    println!("Part 2: Dust collected: {}", dust);
 }
 
 fn alignment_parameters(intersections: &[Pos]) -> Vec<usize> {
@@ -56,24 +63,25 @@ fn get_view(program: &Program) -> String {
 struct Robot {
     cpu: IntcodeEmulator,
     program: Program,
+    terminal: Rc<RefCell<Terminal>>,
+    dust: Rc<Cell<Option<Word>>>,
 }
 
 impl Robot {
-    fn new(program: &Program) -> Self {
+    fn new(program: &Program, routines: &Routines, terminal: Terminal) -> Self {
+        let terminal = Rc::new(RefCell::new(terminal));
+        let dust = Rc::new(Cell::new(None));
+
         // Input handler for Robot program
-        let mut p: VecDeque<_> = [
-            //        1         2
-            //2345678901234567890
-            "A,B,B,A,C,B,C,C,B,A",  // Main
-            "R,10,R,8,L,10,L,10",  // A
-            "R,8,L,6,L,6",  // B
-            "L,10,R,10,L,6",  // C
-            "y",  // enable continuous video feed
-            "",  // EOF
-        ].join("\n").chars().collect();
+        let mut lines = vec![routines.main.as_str()];
+        lines.extend(routines.functions.iter().map(String::as_str));
+        lines.push("y");  // enable continuous video feed
+        lines.push("");  // EOF
+        let mut p: VecDeque<_> = lines.join("\n").chars().collect();
+        let terminal_ = Rc::clone(&terminal);
         let input_handler = Box::new(move |_: &mut Context| {
             if let Some(c) = p.pop_front() {
-                print!("{}", c);  // Echo input
+                terminal_.borrow_mut().print(&c.to_string());  // Echo input
                 Ok(c as Word)
             } else {
                 Err(io::Error::new(io::ErrorKind::BrokenPipe, "No more input"))
@@ -83,7 +91,11 @@ impl Robot {
         // Output handler
         let mut line = String::new();
         let mut last_line = String::new();
+        let terminal_ = Rc::clone(&terminal);
+        let dust_ = Rc::clone(&dust);
         let output_handler = Box::new(move |_: &mut Context, word| {
+            let mut terminal = terminal_.borrow_mut();
+
             // Check if in ASCII range
             if (0..=127).contains(&word) {
                 let c: char = (word as u8).into();
@@ -92,24 +104,24 @@ impl Robot {
                 if c == '\n' {
                     // Detect double newline
                     if &last_line == "\n" {
-                        print!("\x1B[H");  // Move to home
+                        terminal.frame()?;
+                        terminal.print("\x1B[H");  // Move to home
 
                         // Status?
                         if line.chars().next().unwrap_or('\0').is_alphanumeric() {
-                            print!("\x1B[2J");  // Clear screen
+                            terminal.print("\x1B[2J");  // Clear screen
                         }
-
-                        thread::sleep(Duration::from_millis(1000 / FPS));
                     }
 
-                    print!("{}", line);
+                    terminal.print(&line);
 
                     last_line = line.clone();
                     line.clear();
                 }
             } else {
-                print!("\x1B[1000H\x1B[K");  // Move to end and clear line
-                println!("STATUS: {}", word);
+                terminal.print("\x1B[1000H\x1B[K");  // Move to end and clear line
+                terminal.print(&format!("STATUS: {}\n", word));
+                dust_.set(Some(word));
             }
 
             Ok(())
@@ -117,17 +129,17 @@ impl Robot {
 
         let cpu = IntcodeEmulator::new(input_handler, output_handler);
 
-        Robot { cpu, program: program.clone() }
+        Robot { cpu, program: program.clone(), terminal, dust }
     }
 
     fn set_active(&mut self, active: bool) {
         self.program[0] = if active { 2 } else { 1 };
     }
 
-    fn run(&mut self) {
-        print!("\x1B[2J");  // Clear screen
-        print!("\x1B[?25l");  // Hide cursor
-        print!("\x1B[H");  // Move to home
+    /// Run the robot, returning the amount of dust collected
+    fn run(&mut self) -> Word {
+        self.terminal.borrow_mut().setup();
+        self.terminal.borrow_mut().print("\x1B[H");  // Move to home
 
         self.cpu.load_program(&self.program);
 
@@ -136,8 +148,132 @@ impl Robot {
             Err(exception) => panic!("Unhandled exception: {}", exception),
         }
 
-        println!("END");
-        print!("\x1Bc");  // Reset the terminal
+        let mut terminal = self.terminal.borrow_mut();
+        terminal.print("END\n");
+        terminal.frame().expect("Failed to draw frame");
+        terminal.finish().expect("Failed to reset terminal");
+
+        self.dust.get().expect("Robot didn't report dust collected")
+    }
+}
+
+/// Main movement routine and movement functions
+#[derive(Debug, PartialEq)]
+struct Routines {
+    main: String,
+    functions: Vec<String>,
+}
+
+impl Routines {
+    /// Split a path of moves (e.g. `R,8`) into a main routine calling up to three movement functions
+    fn compress(path: &[String]) -> Option<Routines> {
+        let mut functions = Vec::new();
+        let mut main = Vec::new();
+        if !Self::compress_(path, &mut functions, &mut main) {
+            return None;
+        }
+
+        let main = main.into_iter().map(|i| FUNCTION_NAMES[i]).collect::<Vec<_>>().join(",");
+        let mut functions: Vec<_> = functions.into_iter().map(|f| f.join(",")).collect();
+
+        // Robot always expects every function
+        while functions.len() < FUNCTION_NAMES.len() {
+            functions.push(functions.last().cloned().unwrap_or_default());
+        }
+
+        Some(Routines { main, functions })
+    }
+
+    fn compress_<'a>(path: &'a [String], functions: &mut Vec<&'a [String]>, main: &mut Vec<usize>) -> bool {
+        if path.is_empty() {
+            return true;
+        }
+
+        // Each call takes one character plus a separator
+        if 2 * (main.len() + 1) - 1 > MAX_ROUTINE_LEN {
+            return false;
+        }
+
+        // Try an existing function
+        for i in 0..functions.len() {
+            let function = functions[i];
+            if path.starts_with(function) {
+                main.push(i);
+                if Self::compress_(&path[function.len()..], functions, main) {
+                    return true;
+                }
+                main.pop();
+            }
+        }
+
+        // Try a new function
+        if functions.len() < FUNCTION_NAMES.len() {
+            for len in 1..=path.len() {
+                let function = &path[..len];
+                if function.join(",").len() > MAX_ROUTINE_LEN {
+                    break;
+                }
+
+                main.push(functions.len());
+                functions.push(function);
+                if Self::compress_(&path[len..], functions, main) {
+                    return true;
+                }
+                functions.pop();
+                main.pop();
+            }
+        }
+
+        false
+    }
+}
+
+/// Direction the robot is facing
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    fn from_tile(c: char) -> Option<Direction> {
+        match c {
+            '^' => Some(Direction::Up),
+            '>' => Some(Direction::Right),
+            'v' => Some(Direction::Down),
+            '<' => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Right => Direction::Up,
+            Direction::Down => Direction::Right,
+            Direction::Left => Direction::Down,
+        }
+    }
+
+    fn turn_right(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    /// Next position in this direction (if any)
+    fn step(self, (x, y): Pos) -> Option<Pos> {
+        match self {
+            Direction::Up => Some((x, y.checked_sub(1)?)),
+            Direction::Right => Some((x + 1, y)),
+            Direction::Down => Some((x, y + 1)),
+            Direction::Left => Some((x.checked_sub(1)?, y)),
+        }
     }
 }
 
@@ -175,6 +311,49 @@ impl Map {
         self.tiles.get(&pos).copied().unwrap_or(SPACE)
     }
 
+    /// Is there scaffolding at `pos` (including under the robot)
+    fn is_scaffolding(&self, pos: Option<Pos>) -> bool {
+        pos.map(|pos| self.get_tile(pos) != SPACE).unwrap_or(false)
+    }
+
+    /// Find the position and direction of the robot
+    fn find_robot(&self) -> Option<(Pos, Direction)> {
+        self.tiles.iter()
+            .find_map(|(&pos, &c)| Direction::from_tile(c).map(|dir| (pos, dir)))
+    }
+
+    /// Trace the path from the robot to the end of the scaffolding as a list of moves (e.g. `R,8`)
+    ///
+    /// The robot always goes straight across intersections.
+    fn trace_path(&self) -> Vec<String> {
+        let (mut pos, mut dir) = self.find_robot().expect("No robot on map");
+        let mut moves = Vec::new();
+        loop {
+            let mut turn = "";
+            if !self.is_scaffolding(dir.step(pos)) {
+                if self.is_scaffolding(dir.turn_left().step(pos)) {
+                    turn = "L,";
+                    dir = dir.turn_left();
+                } else if self.is_scaffolding(dir.turn_right().step(pos)) {
+                    turn = "R,";
+                    dir = dir.turn_right();
+                } else {
+                    // End of the scaffolding
+                    break;
+                }
+            }
+
+            let mut steps = 0;
+            while let Some(next) = dir.step(pos).filter(|&next| self.is_scaffolding(Some(next))) {
+                pos = next;
+                steps += 1;
+            }
+            moves.push(format!("{}{}", turn, steps));
+        }
+
+        moves
+    }
+
     fn find_intersections(&self) -> Vec<Pos> {
         self.tiles.keys()
             .copied()
@@ -195,3 +374,46 @@ impl Map {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+#######...#####
+#.....#...#...#
+#.....#...#...#
+......#...#...#
+......#...###.#
+......#.....#.#
+^########...#.#
+......#.#...#.#
+......#########
+........#...#..
+....#########..
+....#...#......
+....#...#......
+....#...#......
+....#####......
+";
+
+    #[test]
+    fn test_trace_path() {
+        let map = Map::from_view(EXAMPLE);
+        assert_eq!(map.trace_path().join(","), "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2");
+    }
+
+    #[test]
+    fn test_compress() {
+        let map = Map::from_view(EXAMPLE);
+        let routines = Routines::compress(&map.trace_path()).unwrap();
+
+        // Expanding the main routine must give back the original path
+        let expanded: Vec<_> = routines.main.split(',')
+            .map(|name| &routines.functions[FUNCTION_NAMES.iter().position(|&n| n == name).unwrap()])
+            .cloned()
+            .collect();
+        assert_eq!(expanded.join(","), map.trace_path().join(","));
+        assert!(routines.main.len() <= MAX_ROUTINE_LEN);
+        assert!(routines.functions.iter().all(|f| f.len() <= MAX_ROUTINE_LEN));
+    }
+}
@@ -1,8 +1,4 @@
-use intcode::emulator::{Program, IntcodeEmulator, Word, Context};
-use std::collections::VecDeque;
-use std::io;
-use std::cell::Cell;
-use std::rc::Rc;
+use intcode::emulator::{Program, IntcodeEmulator, Word, Event};
 
 // Drone status
 const INVALID: Word = -1;
@@ -96,22 +92,12 @@ fn fit(program: &Program, min_width: Word, min_height: Word) -> (Word, Word) {
 fn scan(program: &Program, x: Word, y: Word) -> Word {
     assert!(x >= 0);
     assert!(y >= 0);
-    let mut input: VecDeque<Word> = [x, y].iter().copied().collect();
-    let input_handler = Box::new(move |_: &mut Context| {
-        input.pop_front()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "No more input!"))
-    });
-
-    let output = Rc::new(Cell::new(INVALID));
-    let output_ = Rc::clone(&output);
-    let output_handler = Box::new(move |_: &mut Context, word| {
-        output_.set(word);
-        Ok(())
-    });
-
-    let mut cpu = IntcodeEmulator::new(input_handler, output_handler);
+    let mut cpu = IntcodeEmulator::default();
     cpu.load_program(&program);
-    cpu.run().expect("Failed to run program");
 
-    output.get()
+    cpu.resume(Some(x)).expect("Failed to run program");
+    match cpu.resume(Some(y)).expect("Failed to run program") {
+        Event::Output(word) => word,
+        _ => INVALID,
+    }
 }
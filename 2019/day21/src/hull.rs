@@ -0,0 +1,101 @@
+// Hull simulator
+use std::fmt;
+use crate::springscript::Sensors;
+
+const GROUND: char = '#';
+const HOLE: char = '.';
+
+/// How far the springdroid moves when it jumps
+pub const JUMP: usize = 4;
+
+/// Hull patterns reported by the intcode program when the springdroid fell (for `WALK`)
+pub const WALK_HULLS: &[&str] = &[
+    "#####.###########",
+    "#####...#########",
+    "#####.#..########",
+];
+
+/// Hull patterns reported by the intcode program when the springdroid fell (for `RUN`)
+pub const RUN_HULLS: &[&str] = &[
+    "#####.###########",
+    "#####...#########",
+    "#####.#..########",
+    "#####..#.########",
+    "#####..###...####",
+    "#####.###..#.####",
+    "#####...##.##.###",
+    "#####...#.#.#.###",
+    "#####.#.#..######",
+    "#####.##..#.#####",
+    "#####.#...#..####",
+    "#####.####.#..###",
+    "#####...#.###.###",
+    "#####.#.#.##..###",
+];
+
+/// Section of hull, with the springdroid starting on the first tile
+///
+/// The hull continues as ground past the end of the section.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Hull {
+    tiles: Vec<bool>,
+}
+
+impl Hull {
+    /// Parse a hull pattern (e.g. `#####.#..########`)
+    pub fn parse(s: &str) -> Result<Hull, String> {
+        let tiles = s.trim().chars().map(|c| match c {
+            GROUND => Ok(true),
+            HOLE => Ok(false),
+            _ => Err(format!("Unknown tile {:?}", c)),
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        if tiles.first() != Some(&true) {
+            return Err(format!("Hull {:?} must start with ground", s));
+        }
+
+        Ok(Hull { tiles })
+    }
+
+    /// Parse a list of hull patterns
+    pub fn parse_all(patterns: &[&str]) -> Vec<Hull> {
+        patterns.iter().map(|s| Hull::parse(s).expect("Invalid hull pattern")).collect()
+    }
+
+    /// Number of tiles in the section
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Is the tile at `pos` ground
+    pub fn is_ground(&self, pos: usize) -> bool {
+        self.tiles.get(pos).copied().unwrap_or(true)
+    }
+
+    /// Read `n` sensors from `pos`
+    pub fn sensors(&self, pos: usize, n: usize) -> Sensors {
+        (0..n).filter(|&i| self.is_ground(pos + 1 + i)).fold(0, |sensors, i| sensors | 1 << i)
+    }
+
+    /// Move the springdroid along the hull, deciding whether to jump using `n` sensors
+    ///
+    /// Returns the position of the hole the springdroid fell into, if it did.
+    pub fn run<F: FnMut(Sensors) -> bool>(&self, n: usize, mut jump: F) -> Result<(), usize> {
+        let mut pos = 0;
+        while pos < self.len() {
+            pos += if jump(self.sensors(pos, n)) { JUMP } else { 1 };
+            if !self.is_ground(pos) {
+                return Err(pos);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Hull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s: String = self.tiles.iter().map(|&ground| if ground { GROUND } else { HOLE }).collect();
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,98 @@
+mod hull;
+mod search;
+mod springscript;
+
+use std::{env, fs, process};
+use intcode::emulator::{Program, IntcodeEmulator, Event, Word};
+use crate::hull::{Hull, WALK_HULLS, RUN_HULLS};
+use crate::springscript::{Mode, Script};
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => solve(),
+        Some("check") if args.len() > 1 => check(&args[1..]),
+        _ => {
+            eprintln!("USAGE: day21 [check SCRIPT...]");
+            process::exit(2);
+        },
+    }
+}
+
+/// Search for scripts and run them on the springdroid
+fn solve() {
+    let program = Program::from_file("input.txt").expect("Failed to read input");
+
+    for (part, mode, patterns) in [(1, Mode::Walk, WALK_HULLS), (2, Mode::Run, RUN_HULLS)] {
+        let mut hulls = Hull::parse_all(patterns);
+        let damage = loop {
+            let script = search::search(mode, &hulls).unwrap_or_else(|err| panic!("{}", err));
+
+            // The intcode program may test hulls we haven't seen before
+            match run_script(&program, &script) {
+                Ok(damage) => {
+                    println!("Part {}: Found {} instruction script:", part, script.instructions.len());
+                    print!("{}", script);
+                    break damage;
+                },
+                Err(hull) => {
+                    println!("Part {}: Springdroid fell on new hull {}", part, hull);
+                    hulls.push(hull);
+                },
+            }
+        };
+        println!("Part {}: Hull damage: {}", part, damage);
+        println!();
+    }
+}
+
+/// Check scripts against the known hull patterns without running the intcode program
+fn check(paths: &[String]) {
+    let mut ok = true;
+    for path in paths {
+        let script = match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|s| Script::parse(&s)) {
+            Ok(script) => script,
+            Err(err) => {
+                eprintln!("ERROR: {}: {}", path, err);
+                process::exit(1);
+            },
+        };
+
+        let hulls = Hull::parse_all(if script.mode == Mode::Walk { WALK_HULLS } else { RUN_HULLS });
+        let failures = search::failures(&script, &hulls);
+        println!("{}: {}/{} hulls crossed", path, hulls.len() - failures.len(), hulls.len());
+        for &(hull, pos) in &failures {
+            println!("  {} (fell at {})", hull, pos);
+        }
+        ok &= failures.is_empty();
+    }
+
+    if !ok {
+        process::exit(1);
+    }
+}
+
+/// Run a script on the springdroid, returning the amount of hull damage or the hull where it fell
+fn run_script(program: &Program, script: &Script) -> Result<Word, Hull> {
+    let mut cpu = IntcodeEmulator::default();
+    cpu.load_program(program);
+
+    let mut input = script.to_string().into_bytes().into_iter();
+    let mut output = String::new();
+    let mut next = None;
+    loop {
+        match cpu.resume(next.take()).expect("Unhandled exception") {
+            Event::NeedInput => next = Some(Word::from(input.next().expect("Script exhausted"))),
+            Event::Output(word) if (0x00..=0x7F).contains(&word) => output.push(word as u8 as char),
+            Event::Output(word) => return Ok(word),
+            Event::Halted => break,
+        }
+    }
+
+    // The last frame shows the hull without the springdroid
+    let hull = output.lines().rev()
+        .find(|line| line.contains('#') && line.chars().all(|c| c == '#' || c == '.'))
+        .unwrap_or_else(|| panic!("Springdroid didn't report a hull:\n{}", output));
+
+    Err(Hull::parse(hull).expect("Invalid hull"))
+}
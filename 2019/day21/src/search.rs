@@ -0,0 +1,269 @@
+// Search for SpringScript programs
+use std::collections::HashMap;
+use crate::hull::{Hull, JUMP};
+use crate::springscript::{Instruction, Mode, Op, Register, Script, Sensors, MAX_INSTRUCTIONS};
+
+/// Value of a register for every distinct sensor reading (one bit per reading)
+type Table = u128;
+
+/// Maximum number of distinct sensor readings that can be searched
+const MAX_READINGS: usize = Table::BITS as usize;
+
+/// Number of instructions from the start where register states are remembered
+const MEMO_DEPTH: usize = 4;
+
+/// Find the shortest script that gets the springdroid across every hull
+///
+/// Scripts are enumerated by iterative deepening. All registers are evaluated for every
+/// sensor reading at once, so each script is only run once per instruction.
+/// Scripts that can't be minimal are skipped (e.g. overwriting a register that was never read,
+/// or a different order of instructions that don't depend on each other).
+pub fn search(mode: Mode, hulls: &[Hull]) -> Result<Script, String> {
+    let searcher = Searcher::new(mode, hulls)?;
+
+    for len in 0..=MAX_INSTRUCTIONS {
+        let mut script = Vec::with_capacity(len);
+        let mut memo = Memo::new();
+        if searcher.search(len, State::default(), &mut script, &mut memo) {
+            return Ok(Script { instructions: script, mode });
+        }
+    }
+
+    Err(format!("No script of {} instructions or less", MAX_INSTRUCTIONS))
+}
+
+/// Instructions remaining when a state (and the instruction that reached it) was explored
+type Memo = HashMap<(State, Option<Instruction>), usize>;
+
+/// Values of registers
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+struct State {
+    t: Table,
+    j: Table,
+    /// Has `T` been written and not yet read
+    t_unread: bool,
+    /// Has `J` been written and not yet read
+    j_unread: bool,
+}
+
+struct Searcher<'a> {
+    hulls: &'a [Hull],
+    /// Index of the sensor reading at each position of each hull
+    readings: Vec<Vec<usize>>,
+    sensors: Vec<Table>,
+    instructions: Vec<Instruction>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(mode: Mode, hulls: &'a [Hull]) -> Result<Self, String> {
+        let n = mode.sensors();
+
+        // Give each distinct reading the springdroid could see an index
+        let mut indexes: HashMap<Sensors, usize> = HashMap::new();
+        let mut readings = Vec::new();
+        for hull in hulls {
+            let mut hull_readings = vec![0; hull.len()];
+            for pos in standing(hull) {
+                let next = indexes.len();
+                hull_readings[pos] = *indexes.entry(hull.sensors(pos, n)).or_insert(next);
+            }
+            readings.push(hull_readings);
+        }
+        if indexes.len() > MAX_READINGS {
+            return Err(format!("Too many distinct sensor readings ({} > {})", indexes.len(), MAX_READINGS));
+        }
+
+        let mut sensors: Vec<Table> = vec![0; n];
+        for (&reading, &index) in &indexes {
+            for (i, table) in sensors.iter_mut().enumerate() {
+                if reading & (1 << i) != 0 {
+                    *table |= 1 << index;
+                }
+            }
+        }
+
+        let instructions = Op::ALL.iter()
+            .flat_map(|&op| mode.registers().into_iter().map(move |x| (op, x)))
+            .flat_map(|(op, x)| [Register::Temp, Register::Jump].iter().map(move |&y| Instruction { op, x, y }))
+            .filter(|i| !(i.op != Op::Not && i.x == i.y))  // `AND J J` and `OR J J` do nothing
+            .collect();
+
+        Ok(Searcher { hulls, readings, sensors, instructions })
+    }
+
+    /// Search for `remaining` more instructions, returning `true` if a solution was found
+    fn search(&self, remaining: usize, state: State, script: &mut Vec<Instruction>,
+              memo: &mut Memo) -> bool {
+        if remaining == 0 {
+            return !state.t_unread && self.is_solution(state.j);
+        }
+
+        // Skip states already explored with at least as many instructions remaining
+        if script.len() <= MEMO_DEPTH {
+            let key = (state, script.last().copied());
+            match memo.get(&key) {
+                Some(&explored) if explored >= remaining => return false,
+                _ => memo.insert(key, remaining),
+            };
+        }
+
+        for &instruction in &self.instructions {
+            if remaining == 1 && instruction.y != Register::Jump {
+                // Last instruction must write `J`
+                continue;
+            }
+            if !self.is_canonical(script.last(), instruction, state) {
+                continue;
+            }
+
+            let next = self.execute(state, instruction);
+            if (next.t, next.j) == (state.t, state.j) {
+                continue;
+            }
+
+            script.push(instruction);
+            if self.search(remaining - 1, next, script, memo) {
+                return true;
+            }
+            script.pop();
+        }
+
+        false
+    }
+
+    /// Could `instruction` follow `prev` in a minimal script
+    fn is_canonical(&self, prev: Option<&Instruction>, instruction: Instruction, state: State) -> bool {
+        let unread = if instruction.y == Register::Temp { state.t_unread } else { state.j_unread };
+        if instruction.op == Op::Not && instruction.x != instruction.y && unread {
+            // Overwrites a value that was never used
+            return false;
+        }
+
+        let prev = match prev {
+            Some(&prev) => prev,
+            None => return true,
+        };
+
+        let key = |i: Instruction| (i.y == Register::Jump, i.op as u8, register_index(i.x));
+        if prev.y != instruction.y {
+            // Independent instructions can be run in either order
+            let independent = prev.x != instruction.y && instruction.x != prev.y;
+            !independent || key(prev) < key(instruction)
+        } else if prev.op == instruction.op && prev.op != Op::Not && prev.x != prev.y && instruction.x != instruction.y {
+            // Consecutive `AND`s (or `OR`s) into the same register can be run in either order
+            key(prev) < key(instruction)
+        } else {
+            true
+        }
+    }
+
+    fn execute(&self, state: State, instruction: Instruction) -> State {
+        let mut next = state;
+        let x = match instruction.x {
+            Register::Sensor(i) => self.sensors[i as usize],
+            Register::Temp => {
+                next.t_unread = false;
+                state.t
+            },
+            Register::Jump => {
+                next.j_unread = false;
+                state.j
+            },
+        };
+
+        match instruction.y {
+            Register::Temp => {
+                next.t = instruction.op.apply(x, state.t);
+                next.t_unread = true;
+            },
+            Register::Jump => {
+                next.j = instruction.op.apply(x, state.j);
+                next.j_unread = true;
+            },
+            Register::Sensor(_) => unreachable!("Sensors are read-only"),
+        }
+
+        next
+    }
+
+    /// Does jumping according to `j` get the springdroid across every hull
+    fn is_solution(&self, j: Table) -> bool {
+        self.hulls.iter().zip(&self.readings).all(|(hull, readings)| {
+            let mut pos = 0;
+            while pos < hull.len() {
+                pos += if j & (1 << readings[pos]) != 0 { JUMP } else { 1 };
+                if !hull.is_ground(pos) {
+                    return false;
+                }
+            }
+
+            true
+        })
+    }
+}
+
+/// Positions the springdroid could be standing on
+fn standing(hull: &Hull) -> Vec<usize> {
+    let mut reachable = vec![false; hull.len()];
+    reachable[0] = true;
+    for pos in 0..hull.len() {
+        if reachable[pos] {
+            for next in &[pos + 1, pos + JUMP] {
+                if *next < hull.len() && hull.is_ground(*next) {
+                    reachable[*next] = true;
+                }
+            }
+        }
+    }
+
+    (0..hull.len()).filter(|&pos| reachable[pos]).collect()
+}
+
+fn register_index(reg: Register) -> u8 {
+    match reg {
+        Register::Sensor(i) => i,
+        Register::Temp => u8::MAX - 1,
+        Register::Jump => u8::MAX,
+    }
+}
+
+/// Hulls that the script fails to cross, along with the position the springdroid fell
+pub fn failures<'a>(script: &Script, hulls: &'a [Hull]) -> Vec<(&'a Hull, usize)> {
+    hulls.iter()
+        .filter_map(|hull| hull.run(script.mode.sensors(), |sensors| script.eval(sensors)).err().map(|pos| (hull, pos)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hull::{WALK_HULLS, RUN_HULLS};
+
+    #[test]
+    fn test_search_walk() {
+        let hulls = Hull::parse_all(WALK_HULLS);
+        let script = search(Mode::Walk, &hulls).unwrap();
+        assert!(failures(&script, &hulls).is_empty());
+
+        // Needs to check for a hole at `A` and `C` and ground at `D`
+        assert_eq!(script.instructions.len(), 4);
+    }
+
+    #[test]
+    fn test_search_run() {
+        let hulls = Hull::parse_all(RUN_HULLS);
+        let script = search(Mode::Run, &hulls).unwrap();
+        assert!(failures(&script, &hulls).is_empty());
+
+        let handwritten = Script::parse(include_str!("../part2.txt")).unwrap();
+        assert!(failures(&handwritten, &hulls).is_empty());
+        assert!(script.instructions.len() <= handwritten.instructions.len());
+    }
+
+    #[test]
+    fn test_failures() {
+        let hulls = Hull::parse_all(WALK_HULLS);
+        let script = Script::parse("NOT A J\nWALK\n").unwrap();
+        assert_eq!(failures(&script, &hulls), vec![(&hulls[2], 8)]);
+    }
+}
@@ -0,0 +1,254 @@
+// SpringScript
+use std::fmt;
+
+/// Maximum number of instructions the springdroid can remember
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+/// Sensors, where sensor `n` reads the tile `n + 1` tiles ahead (`true` is ground)
+pub type Sensors = u16;
+
+/// Register
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Register {
+    /// Read-only sensor (0 is `A`)
+    Sensor(u8),
+    /// Temporary value register
+    Temp,
+    /// Jump register
+    Jump,
+}
+
+impl Register {
+    fn parse(s: &str) -> Result<Register, String> {
+        match s {
+            "T" => Ok(Register::Temp),
+            "J" => Ok(Register::Jump),
+            s if s.len() == 1 && ("A"..="I").contains(&s) => Ok(Register::Sensor(s.as_bytes()[0] - b'A')),
+            _ => Err(format!("Unknown register {:?}", s)),
+        }
+    }
+
+    /// Is this register writable
+    pub fn is_writable(self) -> bool {
+        !matches!(self, Register::Sensor(_))
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::Sensor(n) => write!(f, "{}", (b'A' + n) as char),
+            Register::Temp => write!(f, "T"),
+            Register::Jump => write!(f, "J"),
+        }
+    }
+}
+
+/// Operation
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Op {
+    /// `Y = X && Y`
+    And,
+    /// `Y = X || Y`
+    Or,
+    /// `Y = !X`
+    Not,
+}
+
+impl Op {
+    pub const ALL: [Op; 3] = [Op::And, Op::Or, Op::Not];
+
+    /// Apply the operation to `x` and `y`, returning the new value of `y`
+    ///
+    /// Works on individual bits, so can evaluate many sets of sensors at once.
+    pub fn apply(self, x: u128, y: u128) -> u128 {
+        match self {
+            Op::And => x & y,
+            Op::Or => x | y,
+            Op::Not => !x,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::And => write!(f, "AND"),
+            Op::Or => write!(f, "OR"),
+            Op::Not => write!(f, "NOT"),
+        }
+    }
+}
+
+/// Instruction (`OP X Y`)
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Instruction {
+    pub op: Op,
+    pub x: Register,
+    pub y: Register,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.op, self.x, self.y)
+    }
+}
+
+/// How far the springdroid can see
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// Sensors `A` to `D`
+    Walk,
+    /// Sensors `A` to `I`
+    Run,
+}
+
+impl Mode {
+    /// Number of sensors
+    pub fn sensors(self) -> usize {
+        match self {
+            Mode::Walk => 4,
+            Mode::Run => 9,
+        }
+    }
+
+    /// All registers that can be read
+    pub fn registers(self) -> Vec<Register> {
+        let mut registers: Vec<_> = (0..self.sensors() as u8).map(Register::Sensor).collect();
+        registers.extend(&[Register::Temp, Register::Jump]);
+
+        registers
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Walk => write!(f, "WALK"),
+            Mode::Run => write!(f, "RUN"),
+        }
+    }
+}
+
+/// SpringScript program
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Script {
+    pub instructions: Vec<Instruction>,
+    pub mode: Mode,
+}
+
+impl Script {
+    /// Parse a script
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    /// The script must end with `WALK` or `RUN`.
+    pub fn parse(s: &str) -> Result<Script, String> {
+        let mut instructions = Vec::new();
+        let mut mode = None;
+
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: String| format!("Line {}: {}", n + 1, msg);
+            if mode.is_some() {
+                return Err(err(String::from("Instruction after end of script")));
+            }
+
+            let words: Vec<_> = line.split_whitespace().collect();
+            let op = match words[0] {
+                "WALK" | "RUN" if words.len() == 1 => {
+                    mode = Some(if words[0] == "WALK" { Mode::Walk } else { Mode::Run });
+                    continue;
+                },
+                "AND" => Op::And,
+                "OR" => Op::Or,
+                "NOT" => Op::Not,
+                _ => return Err(err(format!("Unknown instruction {:?}", line))),
+            };
+
+            if words.len() != 3 {
+                return Err(err(format!("Expected 2 registers: {:?}", line)));
+            }
+            let x = Register::parse(words[1]).map_err(err)?;
+            let y = Register::parse(words[2]).map_err(err)?;
+            if !y.is_writable() {
+                return Err(err(format!("Register {} is read-only", y)));
+            }
+
+            instructions.push(Instruction { op, x, y });
+        }
+
+        let mode = mode.ok_or("Script must end with WALK or RUN")?;
+        if let Some(reg) = instructions.iter().flat_map(|i| vec![i.x, i.y])
+            .find(|&reg| matches!(reg, Register::Sensor(n) if n as usize >= mode.sensors())) {
+            return Err(format!("Register {} can't be used with {}", reg, mode));
+        }
+        if instructions.len() > MAX_INSTRUCTIONS {
+            return Err(format!("Too many instructions ({} > {})", instructions.len(), MAX_INSTRUCTIONS));
+        }
+
+        Ok(Script { instructions, mode })
+    }
+
+    /// Should the springdroid jump
+    pub fn eval(&self, sensors: Sensors) -> bool {
+        let mut t = false;
+        let mut j = false;
+        for instruction in &self.instructions {
+            let x = match instruction.x {
+                Register::Sensor(n) => sensors & (1 << n) != 0,
+                Register::Temp => t,
+                Register::Jump => j,
+            };
+            let y = match instruction.y {
+                Register::Temp => &mut t,
+                Register::Jump => &mut j,
+                Register::Sensor(_) => unreachable!("Sensors are read-only"),
+            };
+            *y = instruction.op.apply(x as u128, *y as u128) & 1 != 0;
+        }
+
+        j
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        writeln!(f, "{}", self.mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script = Script::parse("# Jump if there's a hole ahead\nNOT A J\n\nWALK\n").unwrap();
+        assert_eq!(script.mode, Mode::Walk);
+        assert_eq!(script.instructions, vec![Instruction { op: Op::Not, x: Register::Sensor(0), y: Register::Jump }]);
+        assert_eq!(script.to_string(), "NOT A J\nWALK\n");
+
+        assert!(Script::parse("NOT A J\n").is_err());
+        assert!(Script::parse("NOT J A\nWALK\n").is_err());
+        assert!(Script::parse("NOT E J\nWALK\n").is_err());
+        assert!(Script::parse("NOT E J\nRUN\n").is_ok());
+        assert!(Script::parse(&format!("{}WALK\n", "NOT A J\n".repeat(16))).is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        // Jump if there's a hole in A, B or C and ground at D
+        let script = Script::parse("NOT A J\nNOT B T\nOR T J\nNOT C T\nOR T J\nAND D J\nWALK\n").unwrap();
+        assert!(!script.eval(0b1111));
+        assert!(script.eval(0b1011));
+        assert!(!script.eval(0b0011));
+        assert!(script.eval(0b1000));
+    }
+}
@@ -10,29 +10,9 @@ function intcode() {
 }
 
 if [[ "$1" == "--solve" ]]; then
-	intcode -A "${BASEDIR}"/input.txt <<EOF
-north
-north
-take monolith
-north
-take hypercube
-south
-south
-east
-east
-take easter egg
-east
-south
-take ornament
-west
-south
-drop planetoid
-drop candy cane
-drop spool of cat6
-drop fixed point
-west
-west
-EOF
+	intcode --script "${BASEDIR}"/solution.txt "${BASEDIR}"/input.txt
+elif [[ "$1" == "--explore" ]]; then
+	cargo run -q --release
 else
 	intcode -A "${BASEDIR}"/input.txt
 fi
@@ -0,0 +1,370 @@
+use intcode::emulator::{Program, IntcodeEmulator, Event, Snapshot, Word};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// Maximum number of characters to output for a single command (some items cause an infinite loop)
+const MAX_OUTPUT: usize = 100_000;
+
+const CHECKPOINT: &str = "Security Checkpoint";
+const PRESSURE_FLOOR: &str = "Pressure-Sensitive Floor";
+
+fn main() {
+    let program = Program::from_file("input.txt").expect("Failed to read input");
+    let mut droid = Droid::new(&program);
+
+    let ship = Ship::explore(&mut droid);
+    println!("Map:");
+    print!("{}", ship.draw());
+    println!();
+
+    let password = ship.solve(&mut droid).expect("Failed to find password");
+    println!("Part 1: Password for the main airlock: {}", password);
+}
+
+/// Result of running a command
+enum Response {
+    /// Output, with the droid waiting for another command
+    Ready(String),
+    /// Output, after which the program halted
+    Halted(String),
+    /// The program output too much without asking for input
+    Stuck,
+}
+
+/// The droid, controlled by text commands
+struct Droid {
+    cpu: IntcodeEmulator,
+}
+
+impl Droid {
+    fn new(program: &Program) -> Self {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(program);
+
+        Droid { cpu }
+    }
+
+    /// Run until the droid asks for a command
+    fn start(&mut self) -> Response {
+        self.run("")
+    }
+
+    /// Send a command and run until the droid asks for the next one
+    fn command(&mut self, command: &str) -> Response {
+        self.run(&format!("{}\n", command))
+    }
+
+    /// Feed `input` to the droid, collecting its output until it asks for more
+    fn run(&mut self, input: &str) -> Response {
+        let mut input = input.bytes().map(Word::from);
+        let mut output = String::new();
+        let mut next = None;
+        loop {
+            match self.cpu.resume(next.take()).expect("Unhandled exception") {
+                Event::NeedInput => match input.next() {
+                    Some(word) => next = Some(word),
+                    None => return Response::Ready(output),
+                },
+                Event::Output(word) => {
+                    output.push(word as u8 as char);
+                    if output.len() > MAX_OUTPUT {
+                        return Response::Stuck;
+                    }
+                },
+                Event::Halted => return Response::Halted(output),
+            }
+        }
+    }
+
+    /// Send a command, expecting the droid to ask for another
+    fn expect(&mut self, command: &str) -> String {
+        match self.command(command) {
+            Response::Ready(output) => output,
+            _ => panic!("Droid didn't survive {:?}", command),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.cpu.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu.restore(snapshot);
+    }
+}
+
+/// A room, as described by the droid
+#[derive(Clone, Debug, Default)]
+struct Room {
+    name: String,
+    description: String,
+    doors: Vec<String>,
+    items: Vec<String>,
+}
+
+impl Room {
+    /// Parse every room description in the droid's output
+    fn parse_all(output: &str) -> Vec<Room> {
+        enum Section { Doors, Items }
+
+        let mut rooms: Vec<Room> = Vec::new();
+        let mut section = None;
+        for line in output.lines() {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix("== ").and_then(|line| line.strip_suffix(" ==")) {
+                rooms.push(Room { name: name.to_owned(), ..Room::default() });
+                section = None;
+                continue;
+            }
+
+            let room = match rooms.last_mut() {
+                Some(room) => room,
+                None => continue,
+            };
+
+            match (line, line.strip_prefix("- "), &section) {
+                ("Doors here lead:", _, _) => section = Some(Section::Doors),
+                ("Items here:", _, _) => section = Some(Section::Items),
+                ("", _, _) => section = None,
+                (_, Some(door), Some(Section::Doors)) => room.doors.push(door.to_owned()),
+                (_, Some(item), Some(Section::Items)) => room.items.push(item.to_owned()),
+                (line, _, None) if room.description.is_empty() => room.description = line.to_owned(),
+                _ => (),
+            }
+        }
+
+        rooms
+    }
+}
+
+/// Everything discovered about the ship
+struct Ship {
+    /// Rooms, in the order they were discovered
+    rooms: Vec<Room>,
+    /// Where each door leads
+    doors: HashMap<usize, BTreeMap<String, usize>>,
+    /// Droid state on first entering each room
+    snapshots: Vec<Snapshot>,
+    /// Items that can be picked up without harming the droid
+    safe_items: HashSet<String>,
+    /// Door from the Security Checkpoint to the Pressure-Sensitive Floor
+    checkpoint: Option<(usize, String)>,
+}
+
+impl Ship {
+    /// Explore every room using BFS, rolling back to a snapshot of each room rather than walking
+    fn explore(droid: &mut Droid) -> Ship {
+        let room = match droid.start() {
+            Response::Ready(output) => Room::parse_all(&output).pop().expect("No starting room"),
+            _ => panic!("Droid didn't start"),
+        };
+
+        let mut ship = Ship {
+            rooms: vec![room],
+            doors: HashMap::new(),
+            snapshots: vec![droid.snapshot()],
+            safe_items: HashSet::new(),
+            checkpoint: None,
+        };
+        let mut names: HashMap<String, usize> = HashMap::new();
+        names.insert(ship.rooms[0].name.clone(), 0);
+
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+        while let Some(current) = queue.pop_front() {
+            for item in ship.rooms[current].items.clone() {
+                droid.restore(&ship.snapshots[current]);
+                if ship.is_safe(droid, current, &item) {
+                    ship.safe_items.insert(item);
+                }
+            }
+
+            for door in ship.rooms[current].doors.clone() {
+                droid.restore(&ship.snapshots[current]);
+                let rooms = Room::parse_all(&droid.expect(&door));
+
+                let visited: Vec<_> = rooms.iter().map(|room| room.name.as_str()).collect();
+                if visited == [PRESSURE_FLOOR, CHECKPOINT] {
+                    // Ejected back to the checkpoint
+                    ship.checkpoint = Some((current, door));
+                    continue;
+                }
+
+                let room = rooms.into_iter().last().expect("Door didn't lead anywhere");
+                let next = match names.get(&room.name) {
+                    Some(&next) => next,
+                    None => {
+                        let next = ship.rooms.len();
+                        names.insert(room.name.clone(), next);
+                        ship.rooms.push(room);
+                        ship.snapshots.push(droid.snapshot());
+                        queue.push_back(next);
+                        next
+                    },
+                };
+                ship.doors.entry(current).or_default().insert(door, next);
+            }
+        }
+
+        ship
+    }
+
+    /// Check if taking `item` from room `current` kills or traps the droid
+    fn is_safe(&self, droid: &mut Droid, current: usize, item: &str) -> bool {
+        match droid.command(&format!("take {}", item)) {
+            Response::Ready(_) => (),
+            Response::Halted(_) | Response::Stuck => return false,
+        }
+
+        // Check the droid can still move
+        let door = &self.rooms[current].doors[0];
+        match droid.command(door) {
+            Response::Ready(output) => !Room::parse_all(&output).is_empty(),
+            Response::Halted(_) | Response::Stuck => false,
+        }
+    }
+
+    /// Shortest list of doors to go from room `from` to room `to`
+    fn route(&self, from: usize, to: usize) -> Vec<String> {
+        let mut previous: HashMap<usize, (usize, &str)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                break;
+            }
+
+            for (door, &next) in self.doors.get(&current).into_iter().flatten() {
+                if next != from && !previous.contains_key(&next) {
+                    previous.insert(next, (current, door));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut route = Vec::new();
+        let mut current = to;
+        while current != from {
+            let (prev, door) = previous[&current];
+            route.push(door.to_owned());
+            current = prev;
+        }
+        route.reverse();
+
+        route
+    }
+
+    /// Collect all the safe items, then find the combination that gets past the Pressure-Sensitive Floor
+    fn solve(&self, droid: &mut Droid) -> Option<String> {
+        let (checkpoint, floor) = self.checkpoint.clone()?;
+
+        // Collect all safe items
+        droid.restore(&self.snapshots[0]);
+        let mut current = 0;
+        let mut items = Vec::new();
+        for (n, room) in self.rooms.iter().enumerate() {
+            for item in room.items.iter().filter(|&item| self.safe_items.contains(item)) {
+                for door in self.route(current, n) {
+                    droid.expect(&door);
+                }
+                current = n;
+                droid.expect(&format!("take {}", item));
+                items.push(item.clone());
+            }
+        }
+        for door in self.route(current, checkpoint) {
+            droid.expect(&door);
+        }
+        let snapshot = droid.snapshot();
+
+        // Try every subset of items
+        for subset in 0..(1_u32 << items.len()) {
+            droid.restore(&snapshot);
+            for (n, item) in items.iter().enumerate() {
+                if subset & (1 << n) == 0 {
+                    droid.expect(&format!("drop {}", item));
+                }
+            }
+
+            let output = match droid.command(&floor) {
+                Response::Ready(output) | Response::Halted(output) => output,
+                Response::Stuck => continue,
+            };
+
+            if let Some(i) = output.find("typing ") {
+                let password: String = output[i + "typing ".len()..].chars().take_while(|c| c.is_ascii_digit()).collect();
+                return Some(password);
+            }
+        }
+
+        None
+    }
+
+    /// Draw a map of the ship, as a tree of doors leading out from the starting room
+    ///
+    /// Doors back to a room that has already been drawn are shown as a reference to that room.
+    fn draw(&self) -> String {
+        let mut map = String::new();
+        let mut drawn = HashSet::new();
+        drawn.insert(0);
+        map.push_str(&self.describe(0));
+        map.push('\n');
+        self.draw_doors(0, 1, &mut drawn, &mut map);
+
+        map
+    }
+
+    fn draw_doors(&self, room: usize, depth: usize, drawn: &mut HashSet<usize>, map: &mut String) {
+        let indent = "    ".repeat(depth);
+        for (door, &next) in self.doors.get(&room).into_iter().flatten() {
+            if drawn.insert(next) {
+                map.push_str(&format!("{}{:5} -> {}\n", indent, door, self.describe(next)));
+                self.draw_doors(next, depth + 1, drawn, map);
+            } else if !self.doors.get(&next).map(|d| d.values().any(|&r| r == room)).unwrap_or(false) {
+                // Only show doors that aren't just the way back
+                map.push_str(&format!("{}{:5} -> [{:02}]\n", indent, door, next));
+            }
+        }
+
+        if let Some((checkpoint, door)) = &self.checkpoint {
+            if *checkpoint == room {
+                map.push_str(&format!("{}{:5} -> {}\n", indent, door, PRESSURE_FLOOR));
+            }
+        }
+    }
+
+    /// Room number, name and items (dangerous items are marked with `[!]`)
+    fn describe(&self, room: usize) -> String {
+        let room_ = &self.rooms[room];
+        let mut items: Vec<_> = room_.items.iter().map(|item| {
+            if self.safe_items.contains(item) { item.clone() } else { format!("[!] {}", item) }
+        }).collect();
+        items.sort();
+
+        if items.is_empty() {
+            format!("[{:02}] {}", room, room_.name)
+        } else {
+            format!("[{:02}] {} ({})", room, room_.name, items.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rooms() {
+        let output = "\n\n\n== Pressure-Sensitive Floor ==\nAnalyzing...\n\nDoors here lead:\n- east\n\n\
+            A loud, robotic voice says \"Alert!\" and you are ejected back to the checkpoint.\n\n\n\n\
+            == Security Checkpoint ==\nIn the next room, a pressure-sensitive floor will verify your identity.\n\n\
+            Doors here lead:\n- north\n- west\n\nItems here:\n- ornament\n\nCommand?\n";
+
+        let rooms = Room::parse_all(output);
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms[0].name, PRESSURE_FLOOR);
+        assert_eq!(rooms[1].name, CHECKPOINT);
+        assert_eq!(rooms[1].description, "In the next room, a pressure-sensitive floor will verify your identity.");
+        assert_eq!(rooms[1].doors, vec!["north", "west"]);
+        assert_eq!(rooms[1].items, vec!["ornament"]);
+    }
+}
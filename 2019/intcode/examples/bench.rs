@@ -0,0 +1,132 @@
+//! Compare the execution engines on real puzzle inputs.
+//!
+//! Run with `cargo run --release --example bench [ITERATIONS]`.
+
+use std::env;
+use std::time::{Duration, Instant};
+use intcode::emulator::{Program, IntcodeEmulator, Engine, Event, Word};
+
+const DEFAULT_ITERATIONS: usize = 10;
+
+/// Runs a workload using an engine, returning its results
+type Benchmark = fn(&Program, Engine) -> Vec<Word>;
+
+fn main() {
+    let iterations = match env::args().nth(1).map(|n| n.parse()) {
+        None => DEFAULT_ITERATIONS,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("USAGE: bench [ITERATIONS]");
+            std::process::exit(2);
+        },
+    };
+
+    let benchmarks: &[(&str, Benchmark)] = &[
+        ("day02", day02),
+        ("day05", day05),
+        ("day09", day09),
+        ("day19", day19),
+    ];
+
+    println!("{:8} {:>12} {:>12} {:>8}", "", "interpreter", "cached", "speedup");
+    for &(day, benchmark) in benchmarks {
+        let program = Program::from_file(format!("../{}/input.txt", day)).expect("Failed to read input");
+
+        let (interpreter, expected) = time(iterations, || benchmark(&program, Engine::Interpreter));
+        let (cached, result) = time(iterations, || benchmark(&program, Engine::Cached));
+        assert_eq!(result, expected, "{}: engines produced different results", day);
+
+        println!("{:8} {:>10.2}ms {:>10.2}ms {:>7.1}x", day,
+                 millis(interpreter), millis(cached), millis(interpreter) / millis(cached));
+    }
+}
+
+/// Average time of `iterations` runs of `f`, along with its result
+fn time<F: FnMut() -> Vec<Word>>(iterations: usize, mut f: F) -> (Duration, Vec<Word>) {
+    let start = Instant::now();
+    let mut result = Vec::new();
+    for _ in 0..iterations {
+        result = f();
+    }
+
+    (start.elapsed() / iterations.max(1) as u32, result)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Run a program to completion, returning its output
+///
+/// The final state (or exception) is included so that it is compared too.
+fn run(program: &Program, engine: Engine, input: &[Word]) -> Vec<Word> {
+    let mut cpu = IntcodeEmulator::default();
+    cpu.set_engine(engine);
+    cpu.load_program(program);
+    run_cpu(&mut cpu, input)
+}
+
+fn run_cpu(cpu: &mut IntcodeEmulator, input: &[Word]) -> Vec<Word> {
+    let mut input = input.iter().copied();
+    let mut output = Vec::new();
+    let mut next = None;
+    loop {
+        match cpu.resume(next.take()) {
+            Ok(Event::NeedInput) => match input.next() {
+                Some(word) => next = Some(word),
+                None => panic!("Input exhausted"),
+            },
+            Ok(Event::Output(word)) => output.push(word),
+            Ok(Event::Halted) => break,
+            Err(exception) => panic!("Unhandled exception: {}", exception),
+        }
+    }
+    output.extend(&[cpu.ip() as Word, cpu.rb()]);
+
+    output
+}
+
+/// Search for the noun and verb that produce 19690720
+fn day02(program: &Program, engine: Engine) -> Vec<Word> {
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut cpu = IntcodeEmulator::default();
+            cpu.set_engine(engine);
+            cpu.load_program(program);
+            cpu.mem_mut()[1] = noun;
+            cpu.mem_mut()[2] = verb;
+            run_cpu(&mut cpu, &[]);
+            if cpu.mem()[0] == 19690720 {
+                return vec![noun, verb];
+            }
+        }
+    }
+
+    Vec::new()
+}
+
+fn day05(program: &Program, engine: Engine) -> Vec<Word> {
+    let mut output = run(program, engine, &[1]);
+    output.extend(run(program, engine, &[5]));
+
+    output
+}
+
+fn day09(program: &Program, engine: Engine) -> Vec<Word> {
+    let mut output = run(program, engine, &[1]);
+    output.extend(run(program, engine, &[2]));
+
+    output
+}
+
+/// Scan a 50x50 area of the tractor beam
+fn day19(program: &Program, engine: Engine) -> Vec<Word> {
+    let mut output = Vec::new();
+    for y in 0..50 {
+        for x in 0..50 {
+            output.extend(run(program, engine, &[x, y]));
+        }
+    }
+
+    output
+}
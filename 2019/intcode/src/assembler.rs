@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use crate::emulator::{Word, Opcode, Program, MODE_POSITION, MODE_IMMEDIATE, MODE_RELATIVE};
+
+/// Assemble Intcode source into a program.
+///
+/// The syntax matches the output of `IntcodeEmulator::disassemble`:
+///
+/// ```text
+/// ; Comments start with `;` or `#`
+/// start:  RBOFFSET $1           ; immediate
+///         OUTPUT %rb-1          ; relative to `rb`
+///         ADD 0x00000064 $1 count   ; position (address or label)
+///         JMPTRUE $1 $start     ; immediate label (i.e. address of `start`)
+///         HALT
+/// count:  .data 0
+/// buf:    .zero 8
+/// ```
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let mut lines = Vec::new();
+    let mut labels = HashMap::new();
+
+    // First pass: Parse each line and assign addresses to labels
+    let mut addr = 0;
+    for (n, line) in source.lines().enumerate() {
+        let lineno = n + 1;
+        let line = parse_line(line).map_err(|err| format!("Line {}: {}", lineno, err))?;
+
+        for label in &line.labels {
+            if labels.insert(label.clone(), addr).is_some() {
+                return Err(format!("Line {}: Duplicate label {:?}", lineno, label));
+            }
+        }
+
+        addr += line.statement.as_ref().map(Statement::size).unwrap_or(0);
+        lines.push((lineno, line));
+    }
+
+    // Second pass: Encode statements, resolving labels
+    let mut words = Vec::with_capacity(addr);
+    for (lineno, line) in &lines {
+        if let Some(statement) = &line.statement {
+            statement.encode(&labels, &mut words).map_err(|err| format!("Line {}: {}", lineno, err))?;
+        }
+    }
+
+    Ok(Program::new(&words))
+}
+
+/// Assemble an Intcode source file into a program.
+pub fn assemble_file<T: AsRef<Path>>(path: T) -> Result<Program, String> {
+    let source = fs::read_to_string(&path).map_err(|err| format!("Failed to read file: {}", err))?;
+
+    assemble(&source)
+}
+
+/// A parsed line of source
+struct Line {
+    labels: Vec<String>,
+    statement: Option<Statement>,
+}
+
+/// An instruction or directive
+enum Statement {
+    Instruction(Opcode, Vec<Operand>),
+    Data(Vec<Value>),
+    Zero(usize),
+}
+
+impl Statement {
+    /// Number of words this statement assembles to
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(op, _) => op.nparams() + 1,
+            Statement::Data(values) => values.len(),
+            Statement::Zero(n) => *n,
+        }
+    }
+
+    /// Encode this statement, appending it to `words`
+    fn encode(&self, labels: &HashMap<String, usize>, words: &mut Vec<Word>) -> Result<(), String> {
+        match self {
+            Statement::Instruction(op, operands) => {
+                let mut instruction = Word::from(*op);
+                let mut params = Vec::with_capacity(operands.len());
+                for (n, operand) in operands.iter().enumerate() {
+                    let (mode, value) = operand.resolve(labels)?;
+                    instruction += mode * 10_i64.pow(n as u32 + 2);
+                    params.push(value);
+                }
+                words.push(instruction);
+                words.extend(params);
+            },
+            Statement::Data(values) => {
+                for value in values {
+                    words.push(value.resolve(labels)?);
+                }
+            },
+            Statement::Zero(n) => words.extend((0..*n).map(|_| 0)),
+        }
+
+        Ok(())
+    }
+}
+
+/// An instruction parameter
+enum Operand {
+    Position(Value),
+    Immediate(Value),
+    Relative(Word),
+}
+
+impl Operand {
+    /// Resolve to a `(mode, value)` pair
+    fn resolve(&self, labels: &HashMap<String, usize>) -> Result<(Word, Word), String> {
+        Ok(match self {
+            Operand::Position(value) => (MODE_POSITION, value.resolve(labels)?),
+            Operand::Immediate(value) => (MODE_IMMEDIATE, value.resolve(labels)?),
+            Operand::Relative(offset) => (MODE_RELATIVE, *offset),
+        })
+    }
+}
+
+impl FromStr for Operand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(value) = s.strip_prefix('$') {
+            // $5, $-1, $label
+            Ok(Operand::Immediate(value.parse()?))
+        } else if let Some(offset) = s.strip_prefix("%rb") {
+            // %rb+3, %rb-1, %rb
+            if offset.is_empty() {
+                Ok(Operand::Relative(0))
+            } else {
+                let offset = offset.strip_prefix('+').unwrap_or(offset);
+                offset.parse().map(Operand::Relative)
+                    .map_err(|err| format!("Invalid relative offset {:?}: {}", s, err))
+            }
+        } else {
+            // 0x00000004, 4, label
+            Ok(Operand::Position(s.parse()?))
+        }
+    }
+}
+
+/// A literal value or label reference (with optional offset)
+enum Value {
+    Literal(Word),
+    Label(String, Word),
+}
+
+impl Value {
+    fn resolve(&self, labels: &HashMap<String, usize>) -> Result<Word, String> {
+        match self {
+            Value::Literal(word) => Ok(*word),
+            Value::Label(label, offset) => {
+                let addr = labels.get(label).ok_or_else(|| format!("Undefined label {:?}", label))?;
+                Ok(*addr as Word + offset)
+            },
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = s.strip_prefix("0x") {
+            // Parse as unsigned to allow round-tripping negative values
+            u64::from_str_radix(hex, 16).map(|word| Value::Literal(word as Word))
+                .map_err(|err| format!("Invalid value {:?}: {}", s, err))
+        } else if s.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+            s.parse().map(Value::Literal)
+                .map_err(|err| format!("Invalid value {:?}: {}", s, err))
+        } else {
+            // label, label+1, label-1
+            let (label, offset) = match s.find(['+', '-']) {
+                Some(i) => {
+                    let offset = s[i..].strip_prefix('+').unwrap_or(&s[i..]);
+                    (&s[..i], offset.parse().map_err(|err| format!("Invalid offset {:?}: {}", s, err))?)
+                },
+                None => (s, 0),
+            };
+
+            if !is_label(label) {
+                return Err(format!("Invalid label {:?}", label));
+            }
+
+            Ok(Value::Label(label.to_owned(), offset))
+        }
+    }
+}
+
+/// Parse a single line of source
+fn parse_line(line: &str) -> Result<Line, String> {
+    // Strip comments
+    let line = match line.find([';', '#']) {
+        Some(i) => &line[..i],
+        None => line,
+    };
+
+    let mut labels = Vec::new();
+    let mut rest = line.trim();
+    while let Some(i) = rest.find(':') {
+        let label = rest[..i].trim();
+        if !is_label(label) {
+            return Err(format!("Invalid label {:?}", label));
+        }
+        labels.push(label.to_owned());
+        rest = rest[i+1..].trim();
+    }
+
+    let mut tokens = rest.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty());
+    let statement = match tokens.next() {
+        None => None,
+        Some(".data") => {
+            let values: Result<Vec<Value>, String> = tokens.map(str::parse).collect();
+            Some(Statement::Data(values?))
+        },
+        Some(".zero") => {
+            let n = tokens.next().ok_or_else(|| String::from("Missing size for .zero"))?;
+            let n = n.parse().map_err(|err| format!("Invalid size {:?}: {}", n, err))?;
+            if tokens.next().is_some() {
+                return Err(String::from("Too many arguments for .zero"));
+            }
+            Some(Statement::Zero(n))
+        },
+        Some(directive) if directive.starts_with('.') => {
+            return Err(format!("Unknown directive {:?}", directive));
+        },
+        Some(mnemonic) => {
+            let op: Opcode = mnemonic.parse()?;
+            let operands: Result<Vec<Operand>, String> = tokens.map(str::parse).collect();
+            let operands = operands?;
+
+            if operands.len() != op.nparams() {
+                return Err(format!("{} takes {} parameters, got {}", op, op.nparams(), operands.len()));
+            }
+
+            // Parameters written to must not be immediate
+            let store_param = match op {
+                Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => Some(2),
+                Opcode::Input => Some(0),
+                _ => None,
+            };
+            if let Some(Operand::Immediate(_)) = store_param.map(|n| &operands[n]) {
+                return Err(format!("{} can not store to an immediate parameter", op));
+            }
+
+            Some(Statement::Instruction(op, operands))
+        },
+    };
+
+    Ok(Line { labels, statement })
+}
+
+/// Is this a valid label name
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::IntcodeEmulator;
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble("\
+            ; Multiply then halt
+            MUL 0x00000004 $3 0x00000004
+            .data 33
+        ").unwrap();
+
+        assert_eq!(program.to_string(), "1002,4,3,4,33");
+    }
+
+    #[test]
+    fn test_labels() {
+        let program = assemble("\
+            start:  ADD count $1 count
+                    CMPLT count $10 flag
+                    JMPTRUE flag $start
+                    HALT
+            count:  .data 0
+            flag:   .zero 1
+        ").unwrap();
+
+        assert_eq!(program.to_string(), "1001,12,1,12,1007,12,10,13,1005,13,0,99,0,0");
+
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&program);
+        assert!(cpu.run().is_ok());
+        assert_eq!(cpu.mem()[12], 10);
+    }
+
+    #[test]
+    fn test_label_offset() {
+        // Self-modifying code: patch the immediate parameter of the next instruction
+        let program = assemble("\
+                    ADD $0 $42 patch+1
+            patch:  OUTPUT $0
+                    HALT
+        ").unwrap();
+
+        assert_eq!(program.to_string(), "1101,0,42,5,104,0,99");
+    }
+
+    #[test]
+    fn test_disassemble_round_trip() {
+        // Quine from day 9
+        let words = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&Program::new(&words));
+
+        let mut source = String::new();
+        while cpu.ip() < words.len() {
+            source.push_str(&cpu.disassemble().unwrap());
+            source.push('\n');
+            cpu.set_ip(cpu.ip() + cpu.current_instruction().unwrap().op().nparams() + 1);
+        }
+
+        let program = assemble(&source).unwrap();
+        assert_eq!(program.to_string(), Program::new(&words).to_string());
+    }
+
+    #[test]
+    fn test_negative_position() {
+        // Disassembler prints negative positions as two's complement
+        let program = assemble("JMPTRUE $1 0xffffffffffffffff").unwrap();
+        assert_eq!(program.to_string(), "105,1,-1");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(assemble("FOO 1").is_err());
+        assert!(assemble("ADD $1 $2").is_err());
+        assert!(assemble("ADD $1 $2 $3").is_err());
+        assert!(assemble("INPUT $1").is_err());
+        assert!(assemble("JMPTRUE $1 $nowhere").is_err());
+        assert!(assemble("a: HALT\na: HALT").is_err());
+        assert!(assemble(".bogus").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::emulator::{Word, Opcode};
+
+/// Highest address + 1 that will be cached
+///
+/// Instructions above this are decoded every time they are executed.
+const CACHE_LIMIT: usize = 1 << 20;
+
+/// Maximum length of an instruction (opcode + parameters)
+const MAX_INSTRUCTION_LEN: usize = 4;
+
+/// A fully decoded instruction
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Decoded {
+    pub op: Opcode,
+    pub modes: [Word; 3],
+    pub params: [Word; 3],
+}
+
+impl Decoded {
+    /// Number of words used by this instruction
+    pub fn len(&self) -> usize {
+        self.op.nparams() + 1
+    }
+}
+
+/// Cache of decoded instructions, indexed by address
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DecodeCache {
+    entries: Vec<Option<Decoded>>,
+}
+
+impl DecodeCache {
+    /// Cached instruction at `addr`
+    pub fn get(&self, addr: usize) -> Option<Decoded> {
+        self.entries.get(addr).copied().flatten()
+    }
+
+    /// Cache the instruction at `addr`
+    pub fn insert(&mut self, addr: usize, decoded: Decoded) {
+        if addr >= CACHE_LIMIT {
+            return;
+        }
+
+        if addr >= self.entries.len() {
+            self.entries.resize(addr + 1, None);
+        }
+        self.entries[addr] = Some(decoded);
+    }
+
+    /// Invalidate any instruction that includes the word at `addr`
+    pub fn invalidate(&mut self, addr: usize) {
+        let start = addr.saturating_sub(MAX_INSTRUCTION_LEN - 1);
+        let end = (addr + 1).min(self.entries.len());
+        for entry in self.entries.iter_mut().take(end).skip(start) {
+            *entry = None;
+        }
+    }
+
+    /// Invalidate all instructions
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate() {
+        let decoded = Decoded { op: Opcode::Add, modes: [0; 3], params: [0; 3] };
+        let mut cache = DecodeCache::default();
+        cache.insert(0, decoded);
+        cache.insert(4, decoded);
+
+        // Write to last parameter of first instruction
+        cache.invalidate(3);
+        assert!(cache.get(0).is_none());
+        assert!(cache.get(4).is_some());
+
+        cache.invalidate(100);
+        assert!(cache.get(4).is_some());
+
+        cache.clear();
+        assert!(cache.get(4).is_none());
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt::Write;
+use crate::emulator::{Word, Opcode, Instruction, Program, MODE_POSITION, MODE_IMMEDIATE, MODE_RELATIVE};
+
+/// Number of data words to print per line
+const DATA_PER_LINE: usize = 8;
+
+/// Static disassembly of a whole program
+///
+/// Code is found by walking reachable instructions from address 0, following
+/// `JumpIfTrue`/`JumpIfFalse` immediate targets. Computed jumps (e.g. `JMPTRUE $1 %rb+0`)
+/// can't be followed, but the common call sequence of pushing a return address
+/// immediately before an unconditional jump is recognised so that code after a call
+/// is still found. Everything else is treated as data.
+pub struct Disassembly {
+    words: Vec<Word>,
+    code: BTreeMap<usize, Instruction>,
+    blocks: BTreeMap<usize, Block>,
+}
+
+/// A basic block
+#[derive(Clone, Debug)]
+pub struct Block {
+    start: usize,
+    end: usize,
+    successors: Vec<Edge>,
+}
+
+impl Block {
+    /// Address of the first instruction in this block
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Address immediately after the last instruction of this block
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Control-flow edges out of this block
+    pub fn successors(&self) -> &[Edge] {
+        &self.successors
+    }
+}
+
+/// A control-flow edge
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Edge {
+    /// Execution continues with the next instruction
+    Fallthrough(usize),
+    /// Jump taken to an immediate target
+    Jump(usize),
+    /// Return address of a call
+    Return(usize),
+}
+
+impl Edge {
+    /// Target address of this edge
+    pub fn target(self) -> usize {
+        match self {
+            Edge::Fallthrough(addr) | Edge::Jump(addr) | Edge::Return(addr) => addr,
+        }
+    }
+}
+
+impl Disassembly {
+    /// Disassemble a program
+    pub fn new(program: &Program) -> Disassembly {
+        let words: Vec<Word> = (0..program.len()).map(|addr| program[addr]).collect();
+        let mut code = BTreeMap::new();
+        let mut claimed = BTreeSet::new();
+        let mut leaders = BTreeSet::new();
+        let mut edges: BTreeMap<usize, Vec<Edge>> = BTreeMap::new();
+
+        let mut queue = VecDeque::new();
+        if !words.is_empty() {
+            queue.push_back(0);
+            leaders.insert(0);
+        }
+
+        while let Some(addr) = queue.pop_front() {
+            if code.contains_key(&addr) {
+                continue;
+            }
+
+            let instruction = match decode(&words, addr) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+
+            // Don't decode instructions that overlap with ones we've already found
+            let size = instruction.op().nparams() + 1;
+            if (addr..addr + size).any(|a| claimed.contains(&a)) {
+                continue;
+            }
+            claimed.extend(addr..addr + size);
+            code.insert(addr, instruction);
+
+            let next = addr + size;
+            let mut successors = Vec::new();
+            match instruction.op() {
+                Opcode::Halt => (),
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let always = instruction.op() == Opcode::JumpIfTrue;
+                    let condition = if instruction.mode_for(1) == MODE_IMMEDIATE {
+                        Some((words[addr + 1] != 0) == always)
+                    } else {
+                        None
+                    };
+
+                    if condition != Some(false) && instruction.mode_for(2) == MODE_IMMEDIATE {
+                        if let Ok(target) = usize::try_from(words[addr + 2]) {
+                            successors.push(Edge::Jump(target));
+                        }
+                    }
+
+                    if condition != Some(true) {
+                        successors.push(Edge::Fallthrough(next));
+                    } else if let Some(ret) = return_address(&words, &code, addr) {
+                        successors.push(Edge::Return(ret));
+                    }
+
+                    // Whatever follows a jump starts a new block
+                    leaders.insert(next);
+                },
+                _ => successors.push(Edge::Fallthrough(next)),
+            }
+
+            for &edge in &successors {
+                if let Edge::Jump(target) | Edge::Return(target) = edge {
+                    leaders.insert(target);
+                }
+                queue.push_back(edge.target());
+            }
+            edges.insert(addr, successors);
+        }
+
+        // Split code into basic blocks
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<Block> = None;
+        for (&addr, &instruction) in &code {
+            let size = instruction.op().nparams() + 1;
+
+            match &mut current {
+                Some(block) if block.end == addr && !leaders.contains(&addr) => {
+                    block.end = addr + size;
+                },
+                _ => {
+                    if let Some(block) = current.take() {
+                        blocks.insert(block.start, block);
+                    }
+                    current = Some(Block { start: addr, end: addr + size, successors: Vec::new() });
+                },
+            }
+
+            let block = current.as_mut().unwrap();
+            block.successors = edges[&addr].clone();
+            if block.successors.iter().any(|edge| !matches!(edge, Edge::Fallthrough(_))) || block.successors.is_empty() {
+                blocks.insert(block.start, current.take().unwrap());
+            }
+        }
+        if let Some(block) = current.take() {
+            blocks.insert(block.start, block);
+        }
+
+        Disassembly { words, code, blocks }
+    }
+
+    /// Is `addr` the start of a reachable instruction
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.code.contains_key(&addr)
+    }
+
+    /// The basic blocks, keyed by start address
+    pub fn blocks(&self) -> &BTreeMap<usize, Block> {
+        &self.blocks
+    }
+
+    /// Label for an address, if it is the start of a basic block
+    pub fn label(&self, addr: usize) -> Option<String> {
+        if addr == 0 && self.blocks.contains_key(&0) {
+            Some(String::from("start"))
+        } else if self.blocks.contains_key(&addr) {
+            Some(format!("L{:04x}", addr))
+        } else {
+            None
+        }
+    }
+
+    /// Full program listing
+    ///
+    /// The listing is valid assembler source, with addresses in comments.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        let mut addr = 0;
+        while addr < self.words.len() {
+            if let Some(label) = self.label(addr) {
+                if addr != 0 {
+                    out.push('\n');
+                }
+                writeln!(out, "{}:", label).unwrap();
+            }
+
+            if let Some(&instruction) = self.code.get(&addr) {
+                let size = instruction.op().nparams() + 1;
+                let raw: Vec<_> = self.words[addr..addr + size].iter().map(Word::to_string).collect();
+                writeln!(out, "        {:40} ; {:08x}: {}", self.format_instruction(addr, instruction), addr, raw.join(",")).unwrap();
+                addr += size;
+            } else {
+                // Data runs until the next instruction
+                let end = (addr..self.words.len()).find(|&a| self.is_code(a)).unwrap_or(self.words.len());
+                for chunk_start in (addr..end).step_by(DATA_PER_LINE) {
+                    let chunk = &self.words[chunk_start..end.min(chunk_start + DATA_PER_LINE)];
+                    let data: Vec<_> = chunk.iter().map(Word::to_string).collect();
+                    writeln!(out, "        {:40} ; {:08x}", format!(".data {}", data.join(", ")), chunk_start).unwrap();
+                }
+                addr = end;
+            }
+        }
+
+        out
+    }
+
+    /// Control-flow graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box fontname=\"monospace\"];").unwrap();
+
+        for block in self.blocks.values() {
+            let name = self.label(block.start).unwrap();
+            let mut label = format!("{}:\\l", name);
+            for (&addr, &instruction) in self.code.range(block.start..block.end) {
+                label.push_str(&format!("{:08x} {}\\l", addr, self.format_instruction(addr, instruction)));
+            }
+            writeln!(out, "    {} [label=\"{}\"];", name, label.replace('"', "\\\"")).unwrap();
+        }
+
+        for block in self.blocks.values() {
+            let name = self.label(block.start).unwrap();
+            for &edge in &block.successors {
+                let target = match self.label(edge.target()) {
+                    Some(target) => target,
+                    None => continue,  // Target isn't decodable
+                };
+                let style = match edge {
+                    Edge::Fallthrough(_) => "",
+                    Edge::Jump(_) => " [color=blue]",
+                    Edge::Return(_) => " [style=dashed]",
+                };
+                writeln!(out, "    {} -> {}{};", name, target, style).unwrap();
+            }
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    /// Format an instruction, using labels for immediate jump targets
+    fn format_instruction(&self, addr: usize, instruction: Instruction) -> String {
+        let op = instruction.op();
+        let params: Vec<_> = (1..=op.nparams()).map(|n| {
+            let value = self.words[addr + n];
+            let mode = instruction.mode_for(n);
+            let is_target = matches!(op, Opcode::JumpIfTrue | Opcode::JumpIfFalse) && n == 2;
+
+            match mode {
+                MODE_IMMEDIATE if is_target => {
+                    match usize::try_from(value).ok().and_then(|target| self.label(target)) {
+                        Some(label) => format!("${}", label),
+                        None => format!("${}", value),
+                    }
+                },
+                MODE_POSITION => format!("0x{:08x}", value),
+                MODE_IMMEDIATE => format!("${}", value),
+                MODE_RELATIVE => format!("%rb{:+}", value),
+                _ => format!("?{}", value),
+            }
+        }).collect();
+
+        format!("{} {}", op, params.join(" ")).trim_end().to_owned()
+    }
+}
+
+/// Decode the instruction at `addr`, if it is valid and fits within the program
+fn decode(words: &[Word], addr: usize) -> Option<Instruction> {
+    let instruction = Instruction::new(*words.get(addr)?).ok()?;
+    let nparams = instruction.op().nparams();
+    if addr + nparams >= words.len() {
+        return None;
+    }
+
+    // Check all modes are valid, without any extra mode digits
+    let modes_valid = (1..=nparams).all(|n| matches!(instruction.mode_for(n), MODE_POSITION | MODE_IMMEDIATE | MODE_RELATIVE))
+        && Word::from(instruction) / 100 / 10_i64.pow(nparams as u32) == 0;
+
+    // Stores must not be immediate
+    let store_valid = match instruction.op() {
+        Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => instruction.mode_for(3) != MODE_IMMEDIATE,
+        Opcode::Input => instruction.mode_for(1) != MODE_IMMEDIATE,
+        _ => true,
+    };
+
+    if modes_valid && store_valid { Some(instruction) } else { None }
+}
+
+/// Find the return address of a call ending in the unconditional jump at `addr`
+///
+/// Calls push a constant return address (e.g. `MUL $13 $1 %rb+0`) immediately
+/// before jumping, where the return address is the instruction following the jump.
+fn return_address(words: &[Word], code: &BTreeMap<usize, Instruction>, addr: usize) -> Option<usize> {
+    let ret = addr + 3;
+    let (&prev, &instruction) = code.range(..addr).next_back()?;
+    if prev + instruction.op().nparams() + 1 != addr {
+        return None;
+    }
+
+    let value = match instruction.op() {
+        Opcode::Add if instruction.mode_for(1) == MODE_IMMEDIATE && instruction.mode_for(2) == MODE_IMMEDIATE => {
+            words[prev + 1] + words[prev + 2]
+        },
+        Opcode::Mul if instruction.mode_for(1) == MODE_IMMEDIATE && instruction.mode_for(2) == MODE_IMMEDIATE => {
+            words[prev + 1] * words[prev + 2]
+        },
+        _ => return None,
+    };
+
+    if value == ret as Word { Some(ret) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_blocks() {
+        let program = assemble("\
+            start:  INPUT 0x00000010
+                    JMPFALSE 0x00000010 $zero
+                    OUTPUT $1
+                    HALT
+            zero:   OUTPUT $0
+                    HALT
+                    .data 0, 7, 8
+        ").unwrap();
+        let disassembly = Disassembly::new(&program);
+
+        let starts: Vec<_> = disassembly.blocks().keys().copied().collect();
+        assert_eq!(starts, vec![0, 5, 8]);
+        assert_eq!(disassembly.blocks()[&0].successors(), &[Edge::Jump(8), Edge::Fallthrough(5)]);
+        assert!(disassembly.blocks()[&5].successors().is_empty());
+
+        assert!(disassembly.is_code(8));
+        assert!(!disassembly.is_code(11));
+    }
+
+    #[test]
+    fn test_call() {
+        let program = assemble("\
+            start:  RBOFFSET $100
+                    MUL $ret $1 %rb+0
+                    JMPTRUE $1 $func
+            ret:    HALT
+            func:   OUTPUT $42
+                    JMPFALSE $0 %rb+0
+        ").unwrap();
+        let disassembly = Disassembly::new(&program);
+
+        assert_eq!(disassembly.blocks()[&0].successors(), &[Edge::Jump(10), Edge::Return(9)]);
+        assert!(disassembly.is_code(9));
+        assert!(disassembly.blocks()[&10].successors().is_empty());
+    }
+
+    #[test]
+    fn test_listing_round_trip() {
+        for path in &["../day09/input.txt", "../day21/input.txt", "../day25/input.txt"] {
+            let program = Program::from_file(path).expect("Failed to read input");
+            let listing = Disassembly::new(&program).listing();
+            let reassembled = assemble(&listing).unwrap();
+
+            assert_eq!(reassembled.to_string(), program.to_string());
+        }
+    }
+
+    #[test]
+    fn test_dot() {
+        let program = assemble("\
+            start:  JMPTRUE $1 $end
+                    OUTPUT $0
+            end:    HALT
+        ").unwrap();
+        let dot = Disassembly::new(&program).to_dot();
+
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("start -> L0005 [color=blue];"));
+        assert!(!dot.contains("L0003"));
+    }
+}
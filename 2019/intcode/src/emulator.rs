@@ -1,21 +1,26 @@
 use std::convert::{TryInto, TryFrom};
 use std::{fmt, fs, io, ops};
 use std::path::Path;
+use std::str::FromStr;
 use std::io::{Write, BufRead};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::cell::RefCell;
 use std::rc::Rc;
 use crate::emulator::Opcode::Halt;
+use crate::memory::{self, Memory, DenseMemory};
+use crate::cache::{DecodeCache, Decoded};
+use crate::profiler::{Profiler, IoKind};
 
 pub type Word = i64;
 pub type InputHandler = dyn FnMut(&mut Context) -> io::Result<Word>;
 pub type OutputHandler = dyn FnMut(&mut Context, Word) -> io::Result<()>;
 
-pub const MEMSIZE: usize = 1 << 15;  // 32 KiB
+/// Size of the original fixed-size memory (the address space of the default `DenseMemory`)
+pub const MEMSIZE: usize = memory::DENSE_INITIAL;
 
-const MODE_POSITION: Word = 0;
-const MODE_IMMEDIATE: Word = 1;
-const MODE_RELATIVE: Word = 2;
+pub(crate) const MODE_POSITION: Word = 0;
+pub(crate) const MODE_IMMEDIATE: Word = 1;
+pub(crate) const MODE_RELATIVE: Word = 2;
 
 
 /// An Intcode program
@@ -41,6 +46,23 @@ impl Program {
 
         Ok(Program::new(&instructions?))
     }
+
+    /// Number of words in the program
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Is the program empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let words: Vec<_> = self.0.iter().map(|word| word.to_string()).collect();
+        f.write_str(&words.join(","))
+    }
 }
 
 impl ops::Index<usize> for Program {
@@ -61,27 +83,59 @@ impl ops::IndexMut<usize> for Program {
 pub struct IntcodeEmulator {
     ip: usize,
     relbase: Word,
-    mem: Vec<Word>,
-    decoded_instruction: Instruction,
+    mem: Box<dyn Memory>,
+    decoded_instruction: Decoded,
     input_handler: Box<InputHandler>,
     output_handler: Box<OutputHandler>,
     yield_: bool,
     debug: bool,
+    breakpoints: BTreeMap<usize, Option<Condition>>,
+    watchpoints: BTreeMap<usize, Access>,
+    resume_from: Option<usize>,
+    history: Option<History>,
+    undo: Option<Undo>,
+    snapshots: BTreeMap<String, Snapshot>,
+    resuming: bool,
+    resume_input: VecDeque<Word>,
+    resume_output: Option<Word>,
+    engine: Engine,
+    cache: DecodeCache,
+    profiler: Option<Profiler>,
 }
 
 impl IntcodeEmulator {
     /// Create a new IntcodeEmulator
     pub fn new(input_handler: Box<InputHandler>, output_handler: Box<OutputHandler>) -> IntcodeEmulator {
-        let decoded_instruction = Instruction::new(Halt.into()).unwrap();
+        Self::with_memory(input_handler, output_handler, Box::new(DenseMemory::default()))
+    }
+
+    /// Create a new IntcodeEmulator using a specific memory backend
+    pub fn with_memory(input_handler: Box<InputHandler>, output_handler: Box<OutputHandler>, mut mem: Box<dyn Memory>) -> IntcodeEmulator {
+        let halt = Instruction::new(Halt.into()).unwrap();
+        mem.clear();
+        mem[0] = halt.into();
+
         IntcodeEmulator {
             ip: 0,
             relbase: 0,
-            mem: vec![decoded_instruction.into()],
-            decoded_instruction,
+            mem,
+            decoded_instruction: Decoded { op: Halt, modes: [0; 3], params: [0; 3] },
             input_handler,
             output_handler,
             yield_: false,
             debug: false,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            resume_from: None,
+            history: None,
+            undo: None,
+            snapshots: BTreeMap::new(),
+            resuming: false,
+            resume_input: VecDeque::new(),
+            resume_output: None,
+            engine: Engine::default(),
+            cache: DecodeCache::default(),
+            profiler: None,
         }
     }
 
@@ -117,13 +171,15 @@ impl IntcodeEmulator {
     }
 
     /// The current memory contents
-    pub fn mem(&self) -> &[Word] {
-        &self.mem
+    pub fn mem(&self) -> &dyn Memory {
+        self.mem.as_ref()
     }
 
     /// The current memory contents
-    pub fn mem_mut(&mut self) -> &mut [Word] {
-        &mut self.mem
+    pub fn mem_mut(&mut self) -> &mut dyn Memory {
+        // Any word may be modified
+        self.cache.clear();
+        self.mem.as_mut()
     }
 
     pub fn set_input_handler(&mut self, handler: Box<InputHandler>) {
@@ -137,8 +193,14 @@ impl IntcodeEmulator {
     /// Load a program into memory
     pub fn load_program(&mut self, program: &Program) {
         self.ip = 0;
-        self.mem = vec![0; MEMSIZE];
-        self.mem.splice(..program.0.len(), program.0.iter().copied());
+        self.mem.clear();
+        self.cache.clear();
+        for (addr, &word) in program.0.iter().enumerate() {
+            self.mem[addr] = word;
+        }
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
     }
 
     /// Get debugging flag
@@ -151,73 +213,371 @@ impl IntcodeEmulator {
         self.debug = debug;
     }
 
+    /// Get execution engine
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Set execution engine
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// Set a breakpoint, optionally only triggered if `condition` is true
+    pub fn add_breakpoint(&mut self, addr: usize, condition: Option<Condition>) {
+        self.breakpoints.insert(addr, condition);
+    }
+
+    /// Remove a breakpoint, returning whether it was set
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    /// Current breakpoints
+    pub fn breakpoints(&self) -> &BTreeMap<usize, Option<Condition>> {
+        &self.breakpoints
+    }
+
+    /// Set a watchpoint on a memory address
+    pub fn add_watchpoint(&mut self, addr: usize, access: Access) {
+        self.watchpoints.insert(addr, access);
+    }
+
+    /// Remove a watchpoint, returning whether it was set
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr).is_some()
+    }
+
+    /// Current watchpoints
+    pub fn watchpoints(&self) -> &BTreeMap<usize, Access> {
+        &self.watchpoints
+    }
+
     /// Run a program until an exception is encountered
     pub fn run(&mut self) -> Result<(), Exception> {
-        while !self.is_halted() {
-            self.step()?
+        self.run_while(|_| true)
+    }
+
+    /// Run a program while `predicate` holds, or until an exception is encountered
+    ///
+    /// Breakpoints are checked before executing an instruction and watchpoints after.
+    /// Continuing after a breakpoint will execute the instruction at the breakpoint.
+    pub fn run_while<F: FnMut(&Self) -> bool>(&mut self, mut predicate: F) -> Result<(), Exception> {
+        if self.engine == Engine::Cached && !self.debug && self.history.is_none() && self.profiler.is_none()
+            && self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            return self.run_cached(predicate);
+        }
+
+        while !self.is_halted() && predicate(self) {
+            if self.resume_from.take() != Some(self.ip) && self.is_breakpoint() {
+                self.resume_from = Some(self.ip);
+                return Err(Exception::Breakpoint(self.ip));
+            }
+
+            if self.watchpoints.is_empty() {
+                self.step()?;
+            } else {
+                let hit = self.memory_accesses().into_iter()
+                    .find(|&(addr, access)| self.watchpoints.get(&addr).map(|w| w.matches(access)).unwrap_or(false));
+                self.step()?;
+                if let Some((addr, access)) = hit {
+                    return Err(Exception::Watchpoint(addr, access));
+                }
+            }
         }
         Ok(())
     }
 
+    /// Run until `ip` reaches `addr`, or an exception is encountered
+    pub fn run_until(&mut self, addr: usize) -> Result<(), Exception> {
+        let mut started = false;
+        self.run_while(|cpu| {
+            let run = !started || cpu.ip() != addr;
+            started = true;
+            run
+        })
+    }
+
+    /// Run until the program needs input, produces output or halts
+    ///
+    /// This drives the CPU as a coroutine without using the input and output handlers.
+    /// `input` is queued until it is read by an `INPUT` instruction,
+    /// so it is not lost if the program produces output first.
+    /// Breakpoints, watchpoints and other exceptions are returned as errors.
+    pub fn resume(&mut self, input: Option<Word>) -> Result<Event, Exception> {
+        self.resume_input.extend(input);
+
+        self.resuming = true;
+        let result = self.run_while(|cpu| cpu.resume_output.is_none() && !(cpu.resume_input.is_empty() && cpu.is_input()));
+        self.resuming = false;
+        result?;
+
+        Ok(match self.resume_output.take() {
+            Some(word) => Event::Output(word),
+            None if self.is_halted() => Event::Halted,
+            None => Event::NeedInput,
+        })
+    }
+
+    /// Is the current instruction `INPUT`
+    fn is_input(&self) -> bool {
+        self.mem.get(self.ip).map(|&word| word % 100 == Word::from(Opcode::Input))
+            .unwrap_or(false)
+    }
+
+    /// Is there a breakpoint at the current instruction whose condition holds
+    fn is_breakpoint(&self) -> bool {
+        match self.breakpoints.get(&self.ip) {
+            None => false,
+            Some(None) => true,
+            Some(Some(condition)) => condition.eval(self),
+        }
+    }
+
+    /// Memory addresses that will be accessed by the current instruction
+    fn memory_accesses(&self) -> Vec<(usize, Access)> {
+        let instruction = match self.current_instruction() {
+            Ok(instruction) => instruction,
+            Err(_) => return Vec::new(),
+        };
+
+        let nparams = instruction.op().nparams();
+        let store = match instruction.op() {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => Some(3),
+            Opcode::Input => Some(1),
+            _ => None,
+        };
+
+        (1..=nparams).filter_map(|param| {
+            let value = *self.mem.get(self.ip + param)?;
+            let addr = match instruction.mode_for(param) {
+                MODE_POSITION => value,
+                MODE_RELATIVE => self.relbase + value,
+                _ => return None,
+            };
+            let access = if Some(param) == store { Access::Write } else { Access::Read };
+
+            usize::try_from(addr).ok().map(|addr| (addr, access))
+        }).collect()
+    }
+
     /// Try to step a single instruction
     pub fn step(&mut self) -> Result<(), Exception> {
-        if self.ip >= self.mem.len() {
-            return Err(Exception::SegmentationFault(self.ip));
+        if self.history.is_none() && self.profiler.is_none() {
+            return self.execute();
+        }
+
+        let ip = self.ip;
+        if self.history.is_some() {
+            self.undo = Some(Undo { ip: self.ip, relbase: self.relbase, writes: Vec::new() });
+        }
+        let result = self.execute();
+
+        if let Some(undo) = self.undo.take() {
+            if undo.ip != self.ip || undo.relbase != self.relbase || !undo.writes.is_empty() {
+                self.history.as_mut().unwrap().push(undo);
+            }
         }
 
-        self.decoded_instruction = self.current_instruction().map_err(|_| Exception::IllegalInstruction(self.mem[self.ip]))?;
+        if let Some(profiler) = &mut self.profiler {
+            let op = self.decoded_instruction.op;
+            if matches!(result, Ok(()) | Err(Exception::Yield)) && !op.is_halt() {
+                profiler.record_step(ip, op, self.ip);
+            }
+        }
+
+        result
+    }
+
+    /// Enable recording of up to `limit` steps of history for reverse execution
+    ///
+    /// Only the CPU state is recorded, so stepping back over I/O does not undo it.
+    pub fn enable_history(&mut self, limit: usize) {
+        self.history = Some(History::new(limit));
+    }
+
+    /// Disable recording of history
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Is history being recorded
+    pub fn has_history(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// Undo the last step, returning `false` if there's no history
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(History::pop) {
+            Some(undo) => undo,
+            None => return false,
+        };
+
+        for &(addr, word) in undo.writes.iter().rev() {
+            self.mem[addr] = word;
+            self.cache.invalidate(addr);
+        }
+        self.ip = undo.ip;
+        self.relbase = undo.relbase;
+        self.resume_from = None;
+
+        true
+    }
+
+    /// Run backwards until a breakpoint or write watchpoint is hit, or history is exhausted
+    pub fn reverse_continue(&mut self) -> Result<(), Exception> {
+        loop {
+            let write = self.history.as_ref().and_then(History::last)
+                .and_then(|undo| undo.writes.iter().map(|&(addr, _)| addr)
+                    .find(|addr| self.watchpoints.get(addr).map(|w| w.matches(Access::Write)).unwrap_or(false)));
+
+            if !self.step_back() {
+                return Ok(());
+            }
+
+            if let Some(addr) = write {
+                self.resume_from = Some(self.ip);
+                return Err(Exception::Watchpoint(addr, Access::Write));
+            }
+
+            if self.is_breakpoint() {
+                self.resume_from = Some(self.ip);
+                return Err(Exception::Breakpoint(self.ip));
+            }
+        }
+    }
+
+    /// Enable profiling, replacing any existing profiler
+    ///
+    /// Programs always run using `Engine::Interpreter` while profiling.
+    pub fn enable_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Disable profiling, returning the profiler
+    pub fn disable_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// The current profiler (if any)
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Take a snapshot of the current CPU state
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot { ip: self.ip, relbase: self.relbase, mem: self.mem.clone() }
+    }
+
+    /// Restore the CPU state from a snapshot
+    ///
+    /// This clears any recorded history.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.ip = snapshot.ip;
+        self.relbase = snapshot.relbase;
+        self.mem = snapshot.mem.clone();
+        self.cache.clear();
+        self.resume_from = None;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// Save a named snapshot of the current CPU state
+    pub fn save_snapshot(&mut self, name: &str) {
+        let snapshot = self.snapshot();
+        self.snapshots.insert(name.to_owned(), snapshot);
+    }
+
+    /// Restore a named snapshot, returning `false` if it doesn't exist
+    pub fn restore_snapshot(&mut self, name: &str) -> bool {
+        match self.snapshots.remove(name) {
+            Some(snapshot) => {
+                self.restore(&snapshot);
+                self.snapshots.insert(name.to_owned(), snapshot);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Named snapshots
+    pub fn snapshots(&self) -> &BTreeMap<String, Snapshot> {
+        &self.snapshots
+    }
+
+    /// Execute a single instruction
+    fn execute(&mut self) -> Result<(), Exception> {
+        self.decoded_instruction = self.decode()?;
         if self.debug {
             self.print_disassembled();
         }
 
-        if self.ip + self.decoded_instruction.op.nparams() >= self.mem.len() {
-            return Err(Exception::SegmentationFault(self.ip));
-        }
+        let decoded = self.decoded_instruction;
+        self.execute_decoded(&decoded)
+    }
 
-        match self.decoded_instruction.op {
+    /// Execute a decoded instruction
+    ///
+    /// These are the semantics of every opcode, shared by all engines.
+    fn execute_decoded(&mut self, decoded: &Decoded) -> Result<(), Exception> {
+        match decoded.op {
             Opcode::Add => {
-                *self.store(3)? = self.load(1)? + self.load(2)?;
+                let value = self.load_decoded(decoded, 0)? + self.load_decoded(decoded, 1)?;
+                self.store_decoded(decoded, 2, value)?;
             },
             Opcode::Mul => {
-                *self.store(3)? = self.load(1)? * self.load(2)?;
+                let value = self.load_decoded(decoded, 0)? * self.load_decoded(decoded, 1)?;
+                self.store_decoded(decoded, 2, value)?;
             },
             Opcode::Input => {
                 let mut context = Context::new();
-                *self.store(1)? = (self.input_handler)(&mut context).map_err(Exception::IOError)?;
+                let word = if self.resuming {
+                    self.resume_input.pop_front().expect("no input for resume")
+                } else {
+                    (self.input_handler)(&mut context).map_err(Exception::IOError)?
+                };
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.record_io(self.ip, IoKind::Input, word);
+                }
+                self.store_decoded(decoded, 0, word)?;
                 self.yield_ = context.yield_;
             },
             Opcode::Output => {
                 let mut context = Context::new();
-                let word = self.load(1)?;
-                (self.output_handler)(&mut context, word).map_err(Exception::IOError)?;
-                self.yield_ = context.yield_;
-            },
-            Opcode::JumpIfTrue => {
-                if self.load(1)? != 0 {
-                    self.ip = self.load(2)?.try_into()  // must not be negative
-                        .or(Err(Exception::IllegalInstruction(self.mem[self.ip])))?;
-                    return self.maybe_yield();
+                let word = self.load_decoded(decoded, 0)?;
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.record_io(self.ip, IoKind::Output, word);
                 }
+                if self.resuming {
+                    self.resume_output = Some(word);
+                } else {
+                    (self.output_handler)(&mut context, word).map_err(Exception::IOError)?;
+                }
+                self.yield_ = context.yield_;
             },
-            Opcode::JumpIfFalse => {
-                if self.load(1)? == 0 {
-                    self.ip = self.load(2)?.try_into()  // must not be negative
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let value = self.load_decoded(decoded, 0)?;
+                if (value != 0) == (decoded.op == Opcode::JumpIfTrue) {
+                    self.ip = self.load_decoded(decoded, 1)?.try_into()  // must not be negative
                         .or(Err(Exception::IllegalInstruction(self.mem[self.ip])))?;
                     return self.maybe_yield();
                 }
             },
             Opcode::LessThan => {
-                *self.store(3)? = if self.load(1)? < self.load(2)? { 1 } else { 0 };
+                let value = if self.load_decoded(decoded, 0)? < self.load_decoded(decoded, 1)? { 1 } else { 0 };
+                self.store_decoded(decoded, 2, value)?;
             },
             Opcode::Equal => {
-                *self.store(3)? = if self.load(1)? == self.load(2)? { 1 } else { 0 };
+                let value = if self.load_decoded(decoded, 0)? == self.load_decoded(decoded, 1)? { 1 } else { 0 };
+                self.store_decoded(decoded, 2, value)?;
             },
             Opcode::SetRBOffset => {
-                self.relbase += self.load(1)?;
-            }
+                self.relbase += self.load_decoded(decoded, 0)?;
+            },
             Opcode::Halt => return Ok(()),
         };
-        self.ip += self.decoded_instruction.op.nparams() + 1;
+        self.ip += decoded.len();
 
         self.maybe_yield()
     }
@@ -233,6 +593,90 @@ impl IntcodeEmulator {
         }
     }
 
+    /// Run using the decoded instruction cache while `predicate` holds
+    ///
+    /// This must behave exactly the same as `step`, including any exceptions.
+    fn run_cached<F: FnMut(&Self) -> bool>(&mut self, mut predicate: F) -> Result<(), Exception> {
+        loop {
+            let decoded = self.fetch();
+            if matches!(decoded, Ok(Decoded { op: Opcode::Halt, .. })) || !predicate(self) {
+                return Ok(());
+            }
+
+            self.execute_decoded(&decoded?)?;
+        }
+    }
+
+    /// Fetch the decoded instruction at `ip`, decoding it if it is not cached
+    fn fetch(&mut self) -> Result<Decoded, Exception> {
+        if let Some(decoded) = self.cache.get(self.ip) {
+            return Ok(decoded);
+        }
+
+        let decoded = self.decode()?;
+        self.cache.insert(self.ip, decoded);
+
+        Ok(decoded)
+    }
+
+    /// Decode the instruction at `ip`, including its parameters
+    fn decode(&self) -> Result<Decoded, Exception> {
+        if self.ip >= self.mem.len() {
+            return Err(Exception::SegmentationFault(self.ip));
+        }
+
+        let instruction = self.current_instruction().map_err(|_| Exception::IllegalInstruction(self.mem[self.ip]))?;
+        let nparams = instruction.op.nparams();
+        if self.ip + nparams >= self.mem.len() {
+            return Err(Exception::SegmentationFault(self.ip));
+        }
+
+        let mut decoded = Decoded { op: instruction.op, modes: [0; 3], params: [0; 3] };
+        for n in 0..nparams {
+            decoded.modes[n] = instruction.mode_for(n + 1);
+            decoded.params[n] = self.mem[self.ip + n + 1];
+        }
+
+        Ok(decoded)
+    }
+
+    /// Load the value of parameter `n` (starting from 0) of a decoded instruction
+    fn load_decoded(&self, decoded: &Decoded, n: usize) -> Result<Word, Exception> {
+        let value = decoded.params[n];
+        let addr = match decoded.modes[n] {
+            MODE_POSITION => value,
+            MODE_IMMEDIATE => return Ok(value),
+            MODE_RELATIVE => self.relbase + value,
+            _ => return Err(Exception::IllegalInstruction(self.mem[self.ip])),
+        };
+
+        // Must not be negative
+        let addr = addr.try_into().map_err(|_| Exception::IllegalInstruction(self.mem[self.ip]))?;
+        self.mem.get(addr).copied().ok_or(Exception::SegmentationFault(addr))
+    }
+
+    /// Store to parameter `n` (starting from 0) of a decoded instruction
+    fn store_decoded(&mut self, decoded: &Decoded, n: usize, word: Word) -> Result<(), Exception> {
+        let value = decoded.params[n];
+        let addr = match decoded.modes[n] {
+            MODE_POSITION => value,
+            MODE_RELATIVE => self.relbase + value,
+            // NOTE: Immediate mode is invalid for store
+            _ => return Err(Exception::IllegalInstruction(self.mem[self.ip])),
+        };
+
+        // Must not be negative
+        let addr = addr.try_into().map_err(|_| Exception::IllegalInstruction(self.mem[self.ip]))?;
+        let old = self.mem.get_mut(addr).ok_or(Exception::SegmentationFault(addr))?;
+        if let Some(undo) = &mut self.undo {
+            undo.writes.push((addr, *old));
+        }
+        *old = word;
+        self.cache.invalidate(addr);
+
+        Ok(())
+    }
+
     /// Dump registers to console
     pub fn dump_registers(&self) {
         eprintln!("ip:0x{:08x} rb:{}", self.ip, self.relbase);
@@ -240,10 +684,22 @@ impl IntcodeEmulator {
 
     /// Dump memory to console
     pub fn dump_memory(&self) {
-        eprintln!("Dumping memory...");
-        for addr in (0..self.mem.len()).step_by(8) {
-            let flag = if addr == (self.ip & (!0 - 0b111)) { '>' } else { ' ' };
-            let mem = &self.mem[addr..self.mem.len().min(addr+8)];
+        let allocated = self.mem.allocated();
+        let ranges: Vec<_> = allocated.iter().map(|r| format!("{:08x}-{:08x}", r.start, r.end)).collect();
+        eprintln!("Dumping {} memory ({} words allocated: {})...",
+                  self.mem.name(), allocated.iter().map(|r| r.len()).sum::<usize>(), ranges.join(", "));
+
+        let ip_block = self.ip & (!0 - 0b111);
+        let blocks = allocated.iter()
+            .flat_map(|r| (r.start & (!0 - 0b111)..r.end).step_by(8))
+            .chain(if allocated.iter().any(|r| r.contains(&self.ip)) { None } else { Some(ip_block) });
+        let mut blocks: Vec<_> = blocks.collect();
+        blocks.sort_unstable();
+        blocks.dedup();
+
+        for addr in blocks {
+            let flag = if addr == ip_block { '>' } else { ' ' };
+            let mem: Vec<Word> = (addr..addr+8).map_while(|a| self.mem.get(a).copied()).collect();
             if mem.iter().all(|&v| v == 0) && flag == ' ' {
                 // Don't print empty blocks of memory
                 continue;
@@ -266,11 +722,8 @@ impl IntcodeEmulator {
     /// Disassemble the current instruction
     pub fn disassemble(&self) -> Result<String, String> {
         let instruction = self.current_instruction().map_err(|err| format!("Failed to decode instruction: {}", err))?;
-        let params: Vec<_> = self.mem[self.ip+1..].iter()
-            .chain([0].iter().cycle())
-            .take(instruction.op().nparams())
-            .enumerate()
-            .map(|(n, &p)| (instruction.mode_for(n + 1), p))
+        let params: Vec<_> = (1..=instruction.op().nparams())
+            .map(|n| (instruction.mode_for(n), self.mem.get(self.ip + n).copied().unwrap_or(0)))
             .collect();
 
         let params_str: Vec<_> = params.iter().map(|&(m, p)| {
@@ -284,48 +737,76 @@ impl IntcodeEmulator {
 
         Ok(format!("{} {}", instruction.op(), params_str.join(" ")))
     }
+}
 
-    /// Load a value from memory
-    fn load(&self, param: usize) -> Result<Word, Exception> {
-        assert!(param >= 1);
-        let mode = self.decoded_instruction.mode_for(param);
-        let addr = self.ip + param;
-        let value = self.mem.get(addr).copied().ok_or(Exception::SegmentationFault(addr))?;
-        match mode {
-            MODE_POSITION => {
-                // Must not be negative
-                let addr = value.try_into().or_else(|_| Err(Exception::IllegalInstruction(self.mem[self.ip])))?;
-                self.mem.get(addr).copied().ok_or(Exception::SegmentationFault(addr))
-            },
-            MODE_IMMEDIATE => Ok(value),
-            MODE_RELATIVE => {
-                let addr = (self.relbase + value).try_into().or_else(|_| Err(Exception::IllegalInstruction(self.mem[self.ip])))?;
-                self.mem.get(addr).copied().ok_or(Exception::SegmentationFault(addr))
-            },
-            _ => Err(Exception::IllegalInstruction(self.mem[self.ip]))
-        }
+/// Saved CPU state
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    ip: usize,
+    relbase: Word,
+    mem: Box<dyn Memory>,
+}
+
+impl Snapshot {
+    /// Create a snapshot from saved state
+    pub fn new(ip: usize, rb: Word, mem: Box<dyn Memory>) -> Snapshot {
+        Snapshot { ip, relbase: rb, mem }
     }
 
-    /// Store a value to memory
-    fn store(&mut self, param: usize) -> Result<&mut Word, Exception> {
-        assert!(param >= 1);
-        let mode = self.decoded_instruction.mode_for(param);
-        let addr = self.ip + param;
-        let value = self.mem.get(addr).copied().ok_or(Exception::SegmentationFault(addr))?;
-        match mode {
-            MODE_POSITION => {
-                // Must not be negative
-                let addr = value.try_into().or_else(|_| Err(Exception::IllegalInstruction(self.mem[self.ip])))?;
-                self.mem.get_mut(addr).ok_or(Exception::SegmentationFault(addr))
-            },
-            MODE_RELATIVE => {
-                let addr = (self.relbase + value).try_into().or_else(|_| Err(Exception::IllegalInstruction(self.mem[self.ip])))?;
-                self.mem.get_mut(addr).ok_or(Exception::SegmentationFault(addr))
-            },
-            // NOTE: Immediate mode is invalid for store
-            _ => Err(Exception::IllegalInstruction(self.mem[self.ip])),
+    /// Instruction pointer at the time of the snapshot
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Relative base at the time of the snapshot
+    pub fn rb(&self) -> Word {
+        self.relbase
+    }
+
+    /// Memory contents at the time of the snapshot
+    pub fn mem(&self) -> &dyn Memory {
+        self.mem.as_ref()
+    }
+}
+
+/// Changes made by a single step
+struct Undo {
+    ip: usize,
+    relbase: Word,
+    writes: Vec<(usize, Word)>,  // Previous values
+}
+
+/// Bounded log of steps
+struct History {
+    steps: VecDeque<Undo>,
+    limit: usize,
+}
+
+impl History {
+    fn new(limit: usize) -> Self {
+        History { steps: VecDeque::new(), limit }
+    }
+
+    fn push(&mut self, undo: Undo) {
+        if self.steps.len() >= self.limit {
+            self.steps.pop_front();
+        }
+        if self.limit > 0 {
+            self.steps.push_back(undo);
         }
     }
+
+    fn pop(&mut self) -> Option<Undo> {
+        self.steps.pop_back()
+    }
+
+    fn last(&self) -> Option<&Undo> {
+        self.steps.back()
+    }
+
+    fn clear(&mut self) {
+        self.steps.clear();
+    }
 }
 
 impl Default for IntcodeEmulator {
@@ -352,7 +833,9 @@ impl Context {
 
 pub fn default_input_handler(_: &mut Context) -> io::Result<Word> {
     let mut inbuf = String::new();
-    io::stdin().read_line(&mut inbuf)?;
+    if io::stdin().read_line(&mut inbuf)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "End of input"));
+    }
     let input = inbuf.trim().parse::<Word>()
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
@@ -363,38 +846,195 @@ pub fn default_output_handler(_: &mut Context, word: i64) -> io::Result<()> {
     writeln!(&mut io::stdout(), "{}", word)
 }
 
+/// Shared state of an `AsciiIOHandler`
+struct AsciiState {
+    /// Characters of the current input line not yet consumed by the program
+    input: VecDeque<Word>,
+    /// Scripted input lines, read before falling through to `reader`
+    script: VecDeque<String>,
+    reader: Box<dyn BufRead>,
+    transcript: Option<Box<dyn Write>>,
+    /// Output since the last newline
+    line: String,
+    results: Vec<Word>,
+}
+
+impl AsciiState {
+    /// Read the next line of input, returning the line and whether it came from a script
+    fn read_line(&mut self) -> io::Result<Option<(String, bool)>> {
+        if let Some(line) = self.script.pop_front() {
+            return Ok(Some((line, true)));
+        }
+
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some((line, false)))
+    }
+
+    /// Write the current output line to the transcript
+    fn end_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        if let Some(transcript) = &mut self.transcript {
+            writeln!(transcript, "{}", format!("# {}", line).trim_end())?;
+        }
+
+        Ok(())
+    }
+
+    /// Write any partial output line to the transcript
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.end_line()?;
+        }
+        if let Some(transcript) = &mut self.transcript {
+            transcript.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Apply line editing (backspace and delete) and strip the line terminator
+fn edit_line(line: &str) -> String {
+    let mut edited = String::with_capacity(line.len());
+    for c in line.trim_end_matches(&['\r', '\n'][..]).chars() {
+        match c {
+            '\x08' | '\x7F' => { edited.pop(); },
+            '\r' => (),
+            c => edited.push(c),
+        }
+    }
+
+    edited
+}
+
+/// Reads input a line at a time and prints output as ASCII
+///
+/// Input comes from any scripts that have been added and then from stdin, so a session can be
+/// started from a script and carried on interactively. Lines starting with `#` are ignored.
+/// Output outside the ASCII range (e.g. a puzzle answer) is printed on its own line as a result.
+///
+/// The transcript records input lines as they were sent and output lines as `#` comments,
+/// so a transcript can be used as a script to replay the session.
 pub struct AsciiIOHandler {
-    input_buffer: Rc<RefCell<VecDeque<Word>>>,
+    state: Rc<RefCell<AsciiState>>,
 }
 
 impl AsciiIOHandler {
     pub fn new() -> Self {
-        AsciiIOHandler { input_buffer: Rc::new(RefCell::new(VecDeque::new())) }
+        AsciiIOHandler::with_reader(Box::new(io::BufReader::new(io::stdin())))
+    }
+
+    /// Read interactive input from `reader` instead of stdin
+    pub fn with_reader(reader: Box<dyn BufRead>) -> Self {
+        let state = AsciiState {
+            input: VecDeque::new(),
+            script: VecDeque::new(),
+            reader,
+            transcript: None,
+            line: String::new(),
+            results: Vec::new(),
+        };
+
+        AsciiIOHandler { state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// Queue lines of scripted input (after any previous scripts)
+    pub fn add_script(&self, script: &str) {
+        self.state.borrow_mut().script.extend(script.lines().map(String::from));
+    }
+
+    /// Queue lines of scripted input from a file
+    pub fn load_script<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let script = fs::read_to_string(path)?;
+        self.add_script(&script);
+
+        Ok(())
+    }
+
+    /// Record input and output to `writer`
+    pub fn set_transcript(&self, writer: Box<dyn Write>) {
+        self.state.borrow_mut().transcript = Some(writer);
+    }
+
+    /// Non-ASCII values output by the program
+    pub fn results(&self) -> Vec<Word> {
+        self.state.borrow().results.clone()
+    }
+
+    /// Write any remaining output to the transcript
+    pub fn finish(&self) -> io::Result<()> {
+        self.state.borrow_mut().flush()
     }
 
     pub fn input_handler(&mut self) -> Box<InputHandler> {
-        let input_buffer = Rc::clone(&self.input_buffer);
+        let state = Rc::clone(&self.state);
 
         Box::new(move |_| {
-            let mut input_buffer = input_buffer.borrow_mut();
-            while input_buffer.is_empty() {
-                let mut line = String::new();
-                io::stdin().read_line(&mut line)?;
-                if !line.starts_with('#') {
-                    input_buffer.extend(line.chars().map(|c| c as Word));
+            let mut state = state.borrow_mut();
+            while state.input.is_empty() {
+                let (line, scripted) = match state.read_line()? {
+                    Some((line, scripted)) => (edit_line(&line), scripted),
+                    None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "End of input")),
+                };
+                if line.starts_with('#') {
+                    continue;
+                }
+                if scripted {
+                    // Echo scripted input as if it had been typed
+                    println!("{}", line);
+                }
+
+                state.flush()?;
+                if let Some(transcript) = &mut state.transcript {
+                    writeln!(transcript, "{}", line)?;
                 }
+                state.input.extend(line.chars().chain(Some('\n')).map(|c| c as Word));
             }
-            input_buffer.pop_front().ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "No more input"))
+            state.input.pop_front().ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "No more input"))
         })
     }
 
+    /// Input that has been read but not yet consumed by the program
+    ///
+    /// Unread script lines aren't included, since scripts are passed again when restoring.
+    pub fn pending_input(&self) -> Vec<Word> {
+        self.state.borrow().input.iter().copied().collect()
+    }
+
+    /// Replace any pending input (which is read before any scripts)
+    pub fn set_pending_input(&self, input: &[Word]) {
+        let mut state = self.state.borrow_mut();
+        state.input.clear();
+        state.input.extend(input);
+    }
+
     pub fn output_handler(&self) -> Box<OutputHandler> {
-        Box::new(|_, word| {
+        let state = Rc::clone(&self.state);
+
+        Box::new(move |_, word| {
+            let mut state = state.borrow_mut();
             if (0x00..=0x7F).contains(&word) {
                 let c = word as u8 as char;
                 print!("{}", c);
+                if c == '\n' {
+                    state.end_line()?;
+                } else {
+                    state.line.push(c);
+                }
             } else {
-                eprintln!("WARN: Non-ASCII output: {}", word);
+                if !state.line.is_empty() {
+                    println!();
+                    state.end_line()?;
+                }
+                println!("RESULT: {}", word);
+                state.line = format!("RESULT: {}", word);
+                state.end_line()?;
+                state.results.push(word);
             }
 
             Ok(())
@@ -410,7 +1050,7 @@ pub struct Instruction {
 }
 
 impl Instruction {
-    fn new(instruction: Word) -> Result<Instruction, Exception> {
+    pub(crate) fn new(instruction: Word) -> Result<Instruction, Exception> {
         let op = (instruction % 100).try_into().map_err(|_| Exception::IllegalInstruction(instruction))?;  // Lower 2 digits
         let modes = instruction / 100;  // Upper digits
 
@@ -439,7 +1079,7 @@ impl From<Instruction> for Word {
 }
 
 /// Opcodes
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Opcode {
     Add,  // 1: [p3] = [p1] + [p2]
     Mul,  // 2: [p3] = [p1] * [p2]
@@ -497,6 +1137,27 @@ impl fmt::Display for Opcode {
     }
 }
 
+impl FromStr for Opcode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Opcode::*;
+        match s.to_ascii_uppercase().as_str() {
+            "ADD" => Ok(Add),
+            "MUL" => Ok(Mul),
+            "INPUT" => Ok(Input),
+            "OUTPUT" => Ok(Output),
+            "JMPTRUE" => Ok(JumpIfTrue),
+            "JMPFALSE" => Ok(JumpIfFalse),
+            "CMPLT" => Ok(LessThan),
+            "CMPEQ" => Ok(Equal),
+            "RBOFFSET" => Ok(SetRBOffset),
+            "HALT" => Ok(Halt),
+            _ => Err(format!("Unknown mnemonic {:?}", s)),
+        }
+    }
+}
+
 impl TryFrom<Word> for Opcode {
     type Error = ();
 
@@ -536,10 +1197,171 @@ impl From<Opcode> for Word {
     }
 }
 
+/// Memory access type
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    /// Does an access of type `other` trigger a watchpoint of this type
+    pub fn matches(self, other: Access) -> bool {
+        self == Access::ReadWrite || self == other
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_str(match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::ReadWrite => "read/write",
+        })
+    }
+}
+
+/// Breakpoint condition (e.g. `$rb == 100` or `[0x10] != 0`)
+#[derive(Clone, Debug)]
+pub struct Condition {
+    lhs: Operand,
+    cmp: Comparison,
+    rhs: Word,
+}
+
+/// Left-hand side of a condition
+#[derive(Copy, Clone, Debug)]
+enum Operand {
+    Ip,
+    Rb,
+    Mem(usize),
+}
+
+/// Comparison operator of a condition
+#[derive(Copy, Clone, Debug)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Condition {
+    /// Evaluate condition against the current CPU state
+    pub fn eval(&self, cpu: &IntcodeEmulator) -> bool {
+        let lhs = match self.lhs {
+            Operand::Ip => cpu.ip as Word,
+            Operand::Rb => cpu.relbase,
+            Operand::Mem(addr) => match cpu.mem.get(addr) {
+                Some(&value) => value,
+                None => return false,
+            },
+        };
+
+        match self.cmp {
+            Comparison::Eq => lhs == self.rhs,
+            Comparison::Ne => lhs != self.rhs,
+            Comparison::Lt => lhs < self.rhs,
+            Comparison::Le => lhs <= self.rhs,
+            Comparison::Gt => lhs > self.rhs,
+            Comparison::Ge => lhs >= self.rhs,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<_> = s.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return Err(format!("Expected condition of the form `$rb == VALUE` or `[ADDR] == VALUE`, got {:?}", s));
+        }
+
+        let lhs = match tokens[0] {
+            "$ip" => Operand::Ip,
+            "$rb" => Operand::Rb,
+            lhs if lhs.starts_with('[') && lhs.ends_with(']') => {
+                let addr = &lhs[1..lhs.len()-1];
+                let addr = match addr.strip_prefix("0x") {
+                    Some(hex) => usize::from_str_radix(hex, 16),
+                    None => addr.parse(),
+                }.map_err(|err| format!("Could not parse address {:?}: {}", addr, err))?;
+                Operand::Mem(addr)
+            },
+            lhs => return Err(format!("Unknown operand {:?}", lhs)),
+        };
+
+        let cmp = match tokens[1] {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            cmp => return Err(format!("Unknown comparison {:?}", cmp)),
+        };
+
+        let rhs = tokens[2].parse().map_err(|err| format!("Could not parse value {:?}: {}", tokens[2], err))?;
+
+        Ok(Condition { lhs, cmp, rhs })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self.lhs {
+            Operand::Ip => f.write_str("$ip")?,
+            Operand::Rb => f.write_str("$rb")?,
+            Operand::Mem(addr) => write!(f, "[0x{:08x}]", addr)?,
+        }
+
+        let cmp = match self.cmp {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+
+        write!(f, " {} {}", cmp, self.rhs)
+    }
+}
+
+/// Execution engine used when running a program
+///
+/// `Cached` is opt-in, and is only used when no debugging features are enabled,
+/// otherwise `Interpreter` is used.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Engine {
+    /// Decode each instruction every time it is executed
+    #[default]
+    Interpreter,
+    /// Cache decoded instructions, invalidating them when they are modified
+    Cached,
+}
+
+/// Reason `IntcodeEmulator::resume` returned
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// An `INPUT` instruction is waiting for input
+    NeedInput,
+    /// An `OUTPUT` instruction produced a word
+    Output(Word),
+    /// The program halted
+    Halted,
+}
+
 /// Exception status
 #[derive(Debug)]
 pub enum Exception {
     Yield,
+    Breakpoint(usize),
+    Watchpoint(usize, Access),
     IllegalInstruction(Word),
     SegmentationFault(usize),
     IOError(io::Error),
@@ -550,6 +1372,8 @@ impl fmt::Display for Exception {
         use Exception::*;
         f.write_str(&match &self {
             Yield => String::from("Yield"),
+            Breakpoint(addr) => format!("Breakpoint at {:08x}", addr),
+            Watchpoint(addr, access) => format!("Watchpoint at {:08x} ({})", addr, access),
             IllegalInstruction(word) => format!("Illegal instruction {}", word),
             SegmentationFault(addr) => format!("Segmentation fault at {:08x}", addr),
             IOError(error) => format!("IO error: {}", error),
@@ -560,6 +1384,8 @@ impl fmt::Display for Exception {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::assembler::assemble;
+    use crate::test_util::Buffer;
     use std::collections::VecDeque;
     use std::rc::Rc;
     use std::cell::RefCell;
@@ -577,6 +1403,17 @@ mod tests {
         assert_eq!(cpu.mem()[0], 4714701);
     }
 
+    #[test]
+    fn test_mem_slice() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&Program::new(&[1, 0, 0, 0, 99]));
+        cpu.mem_mut()[1..3].copy_from_slice(&[4, 4]);
+        assert!(cpu.run().is_ok());
+
+        assert_eq!(&cpu.mem()[0..5], &[198, 4, 4, 0, 99]);
+        assert_eq!(cpu.mem().iter().take(6).sum::<Word>(), 305);
+    }
+
     #[test]
     fn test_day2_part2() {
         let mut cpu = IntcodeEmulator::default();
@@ -608,6 +1445,343 @@ mod tests {
         assert_run(&program, VecDeque::from(vec![1]), &[3335138414]);
     }
 
+    /// Counts `count` up to 3
+    const COUNTER: &str = "\
+        start:  ADD count $1 count
+                CMPLT count $3 flag
+                JMPTRUE flag $start
+                HALT
+        count:  .data 0
+        flag:   .data 0
+    ";
+
+    #[test]
+    fn test_breakpoint() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+        cpu.add_breakpoint(4, None);
+
+        for expected in 1..=3 {
+            assert!(matches!(cpu.run(), Err(Exception::Breakpoint(4))));
+            assert_eq!(cpu.mem()[12], expected);
+        }
+
+        assert!(cpu.run().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+        cpu.add_breakpoint(0, Some("[12] == 2".parse().unwrap()));
+
+        assert!(matches!(cpu.run(), Err(Exception::Breakpoint(0))));
+        assert_eq!(cpu.mem()[12], 2);
+
+        assert!(cpu.remove_breakpoint(0));
+        assert!(cpu.run().is_ok());
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+
+        cpu.add_watchpoint(13, Access::Write);
+        assert!(matches!(cpu.run(), Err(Exception::Watchpoint(13, Access::Write))));
+        assert_eq!(cpu.ip(), 8);  // Stops after the write
+
+        cpu.remove_watchpoint(13);
+        cpu.add_watchpoint(13, Access::Read);
+        assert!(matches!(cpu.run(), Err(Exception::Watchpoint(13, Access::Read))));
+        assert_eq!(cpu.ip(), 0);  // Jump taken
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+
+        assert!(cpu.run_until(8).is_ok());
+        assert_eq!(cpu.ip(), 8);
+
+        // Runs until the address is reached again
+        assert!(cpu.run_until(8).is_ok());
+        assert_eq!(cpu.ip(), 8);
+        assert_eq!(cpu.mem()[12], 2);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+        assert!(!cpu.step_back());
+
+        cpu.enable_history(100);
+        let initial = cpu.mem().to_vec();
+        assert!(cpu.run().is_ok());
+        assert_eq!(cpu.mem()[12], 3);
+
+        while cpu.step_back() {}
+        assert_eq!(cpu.ip(), 0);
+        assert_eq!(cpu.mem().to_vec(), initial);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+        cpu.enable_history(2);
+        assert!(cpu.run().is_ok());
+
+        assert!(cpu.step_back());
+        assert!(cpu.step_back());
+        assert!(!cpu.step_back());
+        assert_eq!(cpu.ip(), 4);
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+        cpu.enable_history(100);
+        assert!(cpu.run().is_ok());
+
+        cpu.add_breakpoint(0, None);
+        assert!(matches!(cpu.reverse_continue(), Err(Exception::Breakpoint(0))));
+        assert_eq!(cpu.mem()[12], 2);
+
+        cpu.remove_breakpoint(0);
+        cpu.add_watchpoint(12, Access::Write);
+        assert!(matches!(cpu.reverse_continue(), Err(Exception::Watchpoint(12, Access::Write))));
+        assert_eq!(cpu.mem()[12], 1);
+        assert_eq!(cpu.ip(), 0);
+
+        // Continuing forward again doesn't immediately stop
+        cpu.remove_watchpoint(12);
+        assert!(cpu.run().is_ok());
+        assert_eq!(cpu.mem()[12], 3);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&assemble(COUNTER).unwrap());
+        assert!(cpu.run_until(8).is_ok());
+
+        let snapshot = cpu.snapshot();
+        cpu.save_snapshot("loop");
+        assert!(cpu.run().is_ok());
+        assert_eq!(cpu.mem()[12], 3);
+
+        cpu.restore(&snapshot);
+        assert_eq!(cpu.ip(), 8);
+        assert_eq!(cpu.mem()[12], 1);
+
+        assert!(cpu.run().is_ok());
+        assert!(cpu.restore_snapshot("loop"));
+        assert_eq!(cpu.mem()[12], 1);
+        assert!(!cpu.restore_snapshot("missing"));
+    }
+
+    #[test]
+    fn test_condition_parse() {
+        assert_eq!("$rb >= 100".parse::<Condition>().unwrap().to_string(), "$rb >= 100");
+        assert_eq!("[16] != -1".parse::<Condition>().unwrap().to_string(), "[0x00000010] != -1");
+        assert!("$rb".parse::<Condition>().is_err());
+        assert!("$rb ~ 1".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_resume() {
+        let program = Program::from_file("../day05/input.txt").expect("Failed to read input");
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&program);
+
+        assert_eq!(cpu.resume(None).unwrap(), Event::NeedInput);
+        assert_eq!(cpu.resume(Some(5)).unwrap(), Event::Output(15486302));
+        assert_eq!(cpu.resume(None).unwrap(), Event::Halted);
+        assert_eq!(cpu.resume(None).unwrap(), Event::Halted);
+    }
+
+    #[test]
+    fn test_resume_queued_input() {
+        // Output before reading input
+        let program = assemble("\
+                    OUTPUT $7
+                    INPUT value
+                    OUTPUT value
+                    HALT
+            value:  .data 0
+        ").unwrap();
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&program);
+
+        assert_eq!(cpu.resume(Some(42)).unwrap(), Event::Output(7));
+        assert_eq!(cpu.resume(None).unwrap(), Event::Output(42));
+        assert_eq!(cpu.resume(None).unwrap(), Event::Halted);
+    }
+
+    #[test]
+    fn test_resume_breakpoint() {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&Program::new(&[104, 1, 104, 2, 99]));
+        cpu.add_breakpoint(2, None);
+
+        assert_eq!(cpu.resume(None).unwrap(), Event::Output(1));
+        assert!(matches!(cpu.resume(None), Err(Exception::Breakpoint(2))));
+        assert_eq!(cpu.resume(None).unwrap(), Event::Output(2));
+        assert_eq!(cpu.resume(None).unwrap(), Event::Halted);
+    }
+
+    /// Run a program with `resume`, returning output and the final state
+    fn run_engine(program: &Program, engine: Engine, input: &[Word]) -> (Vec<Word>, String, usize, Word) {
+        let mut cpu = IntcodeEmulator::default();
+        cpu.set_engine(engine);
+        cpu.load_program(program);
+
+        let mut input = input.iter().copied();
+        let mut output = Vec::new();
+        let mut next = None;
+        let result = loop {
+            match cpu.resume(next.take()) {
+                Ok(Event::NeedInput) => match input.next() {
+                    Some(word) => next = Some(word),
+                    None => break String::from("Input exhausted"),
+                },
+                Ok(Event::Output(word)) => output.push(word),
+                Ok(Event::Halted) => break String::from("Halted"),
+                Err(exception) => break exception.to_string(),
+            }
+        };
+
+        (output, result, cpu.ip(), cpu.rb())
+    }
+
+    fn assert_engines_identical(program: &Program, input: &[Word]) {
+        assert_eq!(run_engine(program, Engine::Cached, input), run_engine(program, Engine::Interpreter, input));
+    }
+
+    #[test]
+    fn test_engines() {
+        for (day, inputs) in &[("02", &[][..]), ("05", &[1, 5][..]), ("09", &[1][..])] {
+            let program = Program::from_file(format!("../day{}/input.txt", day)).expect("Failed to read input");
+            for &input in inputs.iter() {
+                assert_engines_identical(&program, &[input]);
+            }
+            assert_engines_identical(&program, &[]);
+        }
+    }
+
+    #[test]
+    fn test_engines_self_modifying() {
+        let program = assemble("\
+            start:  OUTPUT $0
+                    ADD start+1 $1 start+1
+                    CMPLT start+1 $3 flag
+                    JMPTRUE flag $start
+                    HALT
+            flag:   .data 0
+        ").unwrap();
+
+        assert_eq!(run_engine(&program, Engine::Cached, &[]).0, vec![0, 1, 2]);
+        assert_engines_identical(&program, &[]);
+    }
+
+    #[test]
+    fn test_engines_exceptions() {
+        let programs: &[&[Word]] = &[
+            &[42],  // invalid opcode
+            &[-1],  // negative instruction
+            &[301, 0, 0, 0, 99],  // invalid mode
+            &[11101, 1, 1, 5, 99],  // immediate store
+            &[1, -1, 0, 0, 99],  // negative address
+            &[1, 1 << 40, 0, 0, 99],  // out of range
+            &[1105, 1, -1],  // negative jump
+            &[109, -10, 204, 0, 99],  // negative relative address
+            &[1101, 1, 1],  // instruction runs off the end of memory
+        ];
+
+        for &words in programs {
+            let program = Program::new(words);
+            let (_, result, _, _) = run_engine(&program, Engine::Cached, &[]);
+            assert_ne!(result, "Halted", "{:?}", words);
+            assert_engines_identical(&program, &[]);
+        }
+    }
+
+    /// Echoes 8 characters of input, then outputs a non-ASCII value
+    const ECHO: &str = "\
+        start:  INPUT char
+                OUTPUT char
+                ADD count $-1 count
+                JMPTRUE count $start
+                OUTPUT $1000
+                HALT
+        char:   .data 0
+        count:  .data 8
+    ";
+
+    #[test]
+    fn test_ascii_script() {
+        // Script comes first, then falls through to interactive input (with backspaces applied)
+        let mut ascii = AsciiIOHandler::with_reader(Box::new(&b"# comment\nnx\x08o\r\n"[..]));
+        ascii.add_script("# Say hello\nhi\nok");
+        let transcript = Buffer::default();
+        ascii.set_transcript(Box::new(transcript.clone()));
+
+        let mut cpu = IntcodeEmulator::new(ascii.input_handler(), ascii.output_handler());
+        cpu.load_program(&assemble(ECHO).unwrap());
+        assert!(cpu.run().is_ok());
+        ascii.finish().unwrap();
+
+        assert_eq!(ascii.results(), vec![1000]);
+        assert_eq!(ascii.pending_input(), vec![Word::from(b'\n')]);
+        assert_eq!(transcript.contents(), "hi\n# hi\nok\n# ok\nno\n# no\n# RESULT: 1000\n");
+    }
+
+    #[test]
+    fn test_ascii_pending_input() {
+        let mut ascii = AsciiIOHandler::with_reader(Box::new(&b""[..]));
+        ascii.add_script("abcdef\nghi\n# comment\njkl");
+
+        let mut cpu = IntcodeEmulator::new(ascii.input_handler(), ascii.output_handler());
+        cpu.load_program(&assemble(ECHO).unwrap());
+        assert!(cpu.run().is_ok());
+
+        // Rest of the current line, but not the unread script
+        assert_eq!(ascii.pending_input(), "hi\n".chars().map(|c| c as Word).collect::<Vec<_>>());
+
+        // Input ran out
+        cpu.load_program(&assemble(ECHO).unwrap());
+        assert!(matches!(cpu.run(), Err(Exception::IOError(err)) if err.kind() == io::ErrorKind::UnexpectedEof));
+        assert!(ascii.pending_input().is_empty());
+    }
+
+    #[test]
+    fn test_ascii_pending_input_round_trip() {
+        let mut ascii = AsciiIOHandler::with_reader(Box::new(&b""[..]));
+        ascii.add_script("abcdef\nghi");
+
+        let mut cpu = IntcodeEmulator::new(ascii.input_handler(), ascii.output_handler());
+        cpu.load_program(&assemble(ECHO).unwrap());
+        assert!(cpu.run().is_ok());
+        let pending = ascii.pending_input();
+
+        // Restore the pending input, with the same script passed again
+        let mut restored = AsciiIOHandler::with_reader(Box::new(&b""[..]));
+        restored.set_pending_input(&pending);
+        restored.add_script("abcdef\nghi");
+        assert_eq!(restored.pending_input(), pending);
+
+        // Pending input is read first, and the script is only read once
+        let mut cpu = IntcodeEmulator::new(restored.input_handler(), restored.output_handler());
+        cpu.load_program(&assemble(ECHO).unwrap());
+        assert!(cpu.run().is_ok());
+        assert_eq!(restored.pending_input(), "f\n".chars().map(|c| c as Word).collect::<Vec<_>>());
+    }
+
     fn assert_run(program: &Program, input: VecDeque<Word>, expected_output: &[Word]) {
         let input = Rc::new(RefCell::new(input));
         let output = Rc::new(RefCell::new(Vec::new()));
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use crate::emulator::{Word, Snapshot};
+use crate::memory::{self, DenseMemory, Memory};
+
+/// First line of an image file
+const MAGIC: &str = "INTCODE-IMAGE";
+
+/// Current image format version
+pub const VERSION: u32 = 2;
+
+/// Number of memory words per line
+const WORDS_PER_LINE: usize = 16;
+
+/// A saved machine image
+///
+/// Images are stored as text, one field per line:
+///
+/// ```text
+/// INTCODE-IMAGE 2
+/// ip 0000000a
+/// rb 2050
+/// memory dense 16777216
+/// input 110,111,114,116,104,10
+/// label 0000000d L000d
+/// mem 00000000 109,2050,21101,0,966,1,21102,13,1,0,1105,1,1378,21101,20,0
+/// ```
+///
+/// `memory` gives the memory backend and size of the address space.
+/// Memory is stored in lines of up to 16 words, omitting lines that are all zero.
+///
+/// Version 1 images, which used `memsize` rather than `memory`, can still be loaded.
+#[derive(Clone, Debug)]
+pub struct Image {
+    /// CPU state
+    pub snapshot: Snapshot,
+    /// Pending input (e.g. the rest of a line read by `AsciiIOHandler`)
+    pub input: Vec<Word>,
+    /// Labels for addresses
+    pub labels: BTreeMap<usize, String>,
+}
+
+impl Image {
+    /// Create an image of a CPU state
+    pub fn new(snapshot: Snapshot) -> Image {
+        Image { snapshot, input: Vec::new(), labels: BTreeMap::new() }
+    }
+
+    /// Load an image from a file
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<Image, String> {
+        let image = fs::read_to_string(&path).map_err(|err| format!("Failed to read image: {}", err))?;
+
+        image.parse()
+    }
+
+    /// Save an image to a file
+    pub fn save<T: AsRef<Path>>(&self, path: T) -> Result<(), String> {
+        fs::write(&path, self.to_string()).map_err(|err| format!("Failed to write image: {}", err))
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let mem = self.snapshot.mem();
+
+        writeln!(f, "{} {}", MAGIC, VERSION)?;
+        writeln!(f, "ip {:08x}", self.snapshot.ip())?;
+        writeln!(f, "rb {}", self.snapshot.rb())?;
+        writeln!(f, "memory {} {}", mem.name(), mem.len())?;
+
+        if !self.input.is_empty() {
+            writeln!(f, "input {}", join(&self.input))?;
+        }
+
+        for (addr, label) in &self.labels {
+            writeln!(f, "label {:08x} {}", addr, label)?;
+        }
+
+        for range in mem.allocated() {
+            for addr in range.clone().step_by(WORDS_PER_LINE) {
+                let chunk: Vec<_> = (addr..range.end.min(addr + WORDS_PER_LINE)).map(|a| mem[a]).collect();
+                if chunk.iter().any(|&word| word != 0) {
+                    writeln!(f, "mem {:08x} {}", addr, join(&chunk))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Image {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate();
+
+        let header = lines.next().map(|(_, line)| line).unwrap_or("");
+        let version = match header.split_once(' ') {
+            Some((MAGIC, version)) => version.parse::<u32>().map_err(|_| format!("Invalid image version {:?}", version))?,
+            _ => return Err(String::from("Not an Intcode image")),
+        };
+        if version != 1 && version != VERSION {
+            return Err(format!("Unsupported image version {} (expected {})", version, VERSION));
+        }
+
+        let mut ip = None;
+        let mut rb = None;
+        let mut mem: Option<Box<dyn Memory>> = None;
+        let mut input = Vec::new();
+        let mut labels = BTreeMap::new();
+
+        for (n, line) in lines {
+            let lineno = n + 1;
+            let err = |msg: &str| format!("Line {}: {}", lineno, msg);
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').ok_or_else(|| err("Missing value"))?;
+            match key {
+                "ip" => ip = Some(parse_addr(value).ok_or_else(|| err("Invalid ip"))?),
+                "rb" => rb = Some(value.parse::<Word>().map_err(|_| err("Invalid rb"))?),
+                "memsize" if version == 1 => {
+                    value.parse::<usize>().map_err(|_| err("Invalid memsize"))?;
+                    mem = Some(Box::new(DenseMemory::default()));
+                },
+                "memory" => {
+                    let (name, size) = value.split_once(' ').ok_or_else(|| err("Missing memory size"))?;
+                    let size = size.parse::<usize>().map_err(|_| err("Invalid memory size"))?;
+                    mem = Some(memory::from_name(name, size).ok_or_else(|| err(&format!("Unknown memory type {:?}", name)))?);
+                },
+                "input" => input = parse_words(value).ok_or_else(|| err("Invalid input"))?,
+                "label" => {
+                    let (addr, label) = value.split_once(' ').ok_or_else(|| err("Missing label name"))?;
+                    let addr = parse_addr(addr).ok_or_else(|| err("Invalid label address"))?;
+                    labels.insert(addr, label.trim().to_owned());
+                },
+                "mem" => {
+                    let mem = mem.as_mut().ok_or_else(|| err("mem before memory"))?;
+                    let (addr, words) = value.split_once(' ').ok_or_else(|| err("Missing memory contents"))?;
+                    let addr = parse_addr(addr).ok_or_else(|| err("Invalid memory address"))?;
+                    let words = parse_words(words).ok_or_else(|| err("Invalid memory contents"))?;
+                    for (offset, word) in words.into_iter().enumerate() {
+                        *mem.get_mut(addr + offset).ok_or_else(|| err("Memory contents exceed memory size"))? = word;
+                    }
+                },
+                key => return Err(err(&format!("Unknown field {:?}", key))),
+            }
+        }
+
+        let ip = ip.ok_or("Missing ip")?;
+        let rb = rb.ok_or("Missing rb")?;
+        let mem = mem.ok_or("Missing memory")?;
+
+        Ok(Image { snapshot: Snapshot::new(ip, rb, mem), input, labels })
+    }
+}
+
+fn join(words: &[Word]) -> String {
+    let mut s = String::new();
+    for (n, word) in words.iter().enumerate() {
+        if n > 0 {
+            s.push(',');
+        }
+        write!(s, "{}", word).unwrap();
+    }
+
+    s
+}
+
+fn parse_addr(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn parse_words(s: &str) -> Option<Vec<Word>> {
+    s.split(',').map(|word| word.trim().parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{IntcodeEmulator, Program};
+    use crate::memory::SparseMemory;
+
+    #[test]
+    fn test_round_trip() {
+        let mut mem: Box<dyn Memory> = Box::new(DenseMemory::new(64));
+        mem[0] = 109;
+        mem[1] = -5;
+        mem[40] = 99;
+
+        let mut image = Image::new(Snapshot::new(40, 17, mem.clone()));
+        image.input = vec![110, 10];
+        image.labels.insert(40, String::from("end"));
+
+        let loaded: Image = image.to_string().parse().unwrap();
+        assert_eq!(loaded.snapshot.ip(), 40);
+        assert_eq!(loaded.snapshot.rb(), 17);
+        assert_eq!(loaded.snapshot.mem().name(), "dense");
+        assert_eq!(loaded.snapshot.mem().len(), 64);
+        assert_eq!(loaded.snapshot.mem().to_vec(), mem.to_vec());
+        assert_eq!(loaded.input, vec![110, 10]);
+        assert_eq!(loaded.labels[&40], "end");
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut mem: Box<dyn Memory> = Box::new(SparseMemory::default());
+        mem[0] = 99;
+        mem[1 << 40] = 7;
+
+        let loaded: Image = Image::new(Snapshot::new(0, 0, mem)).to_string().parse().unwrap();
+        assert_eq!(loaded.snapshot.mem().name(), "sparse");
+        assert_eq!(loaded.snapshot.mem()[0], 99);
+        assert_eq!(loaded.snapshot.mem()[1 << 40], 7);
+        assert_eq!(loaded.snapshot.mem().allocated().len(), 2);
+    }
+
+    #[test]
+    fn test_version1() {
+        let image: Image = "INTCODE-IMAGE 1\nip 00000002\nrb 0\nmemsize 32768\nmem 00000000 3,16,99\n".parse().unwrap();
+        assert_eq!(image.snapshot.ip(), 2);
+        assert_eq!(image.snapshot.mem().to_vec(), vec![3, 16, 99]);
+    }
+
+    #[test]
+    fn test_resume() {
+        // Day 2 part 1, interrupted part way through
+        let program = Program::from_file("../day02/input.txt").expect("Failed to read input");
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&program);
+        cpu.mem_mut()[1] = 12;
+        cpu.mem_mut()[2] = 2;
+        for _ in 0..10 {
+            cpu.step().unwrap();
+        }
+
+        let image: Image = Image::new(cpu.snapshot()).to_string().parse().unwrap();
+        let mut restored = IntcodeEmulator::default();
+        restored.restore(&image.snapshot);
+
+        assert_eq!(restored.ip(), cpu.ip());
+        assert_eq!(restored.rb(), cpu.rb());
+        assert_eq!(restored.mem().to_vec(), cpu.mem().to_vec());
+
+        assert!(restored.run().is_ok());
+        assert_eq!(restored.mem()[0], 4714701);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!("".parse::<Image>().is_err());
+        assert!("INTCODE-IMAGE 99\nip 0\nrb 0\nmemory dense 1\n".parse::<Image>().is_err());
+        assert!("INTCODE-IMAGE 2\nip 0\nrb 0\n".parse::<Image>().is_err());
+        assert!("INTCODE-IMAGE 2\nip 0\nrb 0\nmemory dense 1\nmem 00000000 1,2\n".parse::<Image>().is_err());
+        assert!("INTCODE-IMAGE 2\nip 0\nrb 0\nmemory dense 1\nfoo bar\n".parse::<Image>().is_err());
+        assert!("INTCODE-IMAGE 2\nip 0\nrb 0\nmemory bogus 1\n".parse::<Image>().is_err());
+        assert!("INTCODE-IMAGE 2\nip 0\nrb 0\nmemsize 1\n".parse::<Image>().is_err());
+    }
+}
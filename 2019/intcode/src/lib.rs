@@ -1,2 +1,12 @@
 pub mod emulator;
+pub mod memory;
+mod cache;
+pub mod profiler;
+pub mod network;
+pub mod assembler;
+pub mod disassembler;
+pub mod image;
+pub mod terminal;
 
+#[cfg(test)]
+mod test_util;
@@ -1,138 +1,383 @@
 use std::{fs, env, process, io};
-use intcode::emulator::{Program, IntcodeEmulator, Exception, AsciiIOHandler};
+use intcode::emulator::{Program, IntcodeEmulator, Exception, AsciiIOHandler, Access, Condition, Opcode};
+use intcode::emulator::{default_input_handler, default_output_handler};
+use intcode::assembler;
+use intcode::disassembler::Disassembly;
+use intcode::image::Image;
+use intcode::memory::{Memory, DenseMemory, SparseMemory};
+use intcode::profiler::Profiler;
 use std::io::BufRead;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 
 fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("asm") => return asm(),
+        Some("disasm") => return disasm(),
+        _ => (),
+    }
+
     let args = parse_args();
 
-    let program = match Program::from_file(args.program) {
+    let start = match (&args.program, &args.restore) {
+        (Some(program), None) => Program::from_file(program).map(Start::Program),
+        (None, Some(image)) => Image::load(image).map(Start::Image),
+        _ => {
+            print_usage();
+            process::exit(2);
+        },
+    };
+
+    let start = match start {
         Err(err) => {
             eprintln!("ERROR: {}", err);
             process::exit(1);
         },
-        Ok(program) => program,
+        Ok(start) => start,
     };
 
-    run(&program, args.ascii, args.debug, args.break_at_start, args.dump);
+    run(start, &args);
+}
+
+/// Initial machine state
+enum Start {
+    Program(Program),
+    Image(Image),
 }
 
 fn parse_args() -> Args {
     let mut ascii = false;
+    let mut scripts = Vec::new();
+    let mut transcript = None;
     let mut debug = false;
     let mut break_at_start = false;
     let mut dump = false;
+    let mut history = None;
+    let mut memory = None;
+    let mut profile = false;
+    let mut trace = None;
+    let mut save_on_exit = None;
+    let mut restore = None;
     let mut posargs = VecDeque::new();
 
-    let args: Vec<_> = env::args().collect();
-    for arg in &args[1..] {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
         match arg.as_str() {
             "-A" | "--ascii" => ascii = true,
+            "-s" | "--script" => match args.next() {
+                Some(path) => { scripts.push(path); ascii = true },
+                None => { print_usage(); process::exit(2) },
+            },
+            "-t" | "--transcript" => match args.next() {
+                Some(path) => { transcript = Some(path); ascii = true },
+                None => { print_usage(); process::exit(2) },
+            },
             "-d" | "--debug" => debug = true,
             "-B" | "--break" => break_at_start = true,
             "-D" | "--dump" => dump = true,
+            "-H" | "--history" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => history = Some(n),
+                _ => { print_usage(); process::exit(2) },
+            },
+            "-M" | "--memory" => match args.next().as_deref() {
+                Some("dense") => memory = Some(Box::new(DenseMemory::default()) as Box<dyn Memory>),
+                Some("sparse") => memory = Some(Box::new(SparseMemory::default()) as Box<dyn Memory>),
+                _ => { print_usage(); process::exit(2) },
+            },
+            "-P" | "--profile" => profile = true,
+            "-T" | "--trace" => match args.next() {
+                Some(path) => trace = Some(path),
+                None => { print_usage(); process::exit(2) },
+            },
+            "-S" | "--save-on-exit" => match args.next() {
+                Some(path) => save_on_exit = Some(path),
+                None => { print_usage(); process::exit(2) },
+            },
+            "-R" | "--restore" => match args.next() {
+                Some(path) => restore = Some(path),
+                None => { print_usage(); process::exit(2) },
+            },
             "-h" | "--help" => { print_usage(); process::exit(0) },
             arg if arg.starts_with('-') => {
                 eprintln!("ERROR: Unknown argument '{}'", arg);
                 print_usage();
                 process::exit(2);
             },
-            arg => posargs.push_back(arg),
+            _ => posargs.push_back(arg),
         }
     }
 
-    let program = if let Some(arg) = posargs.pop_front() {
-        arg.to_owned()
-    } else {
-        print_usage();
-        process::exit(2);
-    };
+    let program = posargs.pop_front();
 
     if !posargs.is_empty() {
         print_usage();
         process::exit(2)
     }
 
-    Args { ascii, debug, break_at_start, dump, program }
+    Args { ascii, scripts, transcript, debug, break_at_start, dump, history, memory, profile, trace, save_on_exit, restore, program }
 }
 
 fn print_usage() {
     eprintln!("\
-USAGE: intcode [-A | --ascii ] [-d | --debug] [-B | --break] [-D | --dump] PROGRAM
-Run Intcode PROGRAM in the interpreter.
+USAGE: intcode [-A | --ascii ] [-s | --script FILE]... [-t | --transcript FILE]
+               [-d | --debug] [-B | --break] [-D | --dump] [-H | --history N]
+               [-M | --memory dense|sparse] [-P | --profile] [-T | --trace FILE]
+               [-S | --save-on-exit IMAGE] ( PROGRAM | -R | --restore IMAGE )
+       intcode asm [-o OUTPUT] SOURCE
+       intcode disasm [--dot] PROGRAM
+Run Intcode PROGRAM in the interpreter, assemble SOURCE into an Intcode program
+or disassemble PROGRAM into a listing.
 
 -A, --ascii    use ASCII input/output
+-s, --script   send lines from FILE as input before reading stdin (implies --ascii)
+-t, --transcript
+               record ASCII input and output to FILE (implies --ascii)
 -d, --debug    enable debugging mode (traces execution and break into debugger on exceptions)
--B, --break    immediately break into debugger")
+-B, --break    immediately break into debugger
+-D, --dump     dump memory on exit
+-H, --history  record the last N steps to allow stepping backwards in the debugger
+-M, --memory   memory backend: dense (default) or sparse (ignored with --restore)
+-P, --profile  print a profile of executed instructions, loops and I/O on exit
+-T, --trace    write a trace of every instruction and I/O event to FILE (JSON lines)
+-S, --save-on-exit
+               save a machine image to IMAGE when the program exits (including crashes)
+-R, --restore  resume from a machine image instead of running PROGRAM
+-o, --output   write assembled program to OUTPUT (default: stdout)
+--dot          print control-flow graph in Graphviz DOT format")
 }
 
-fn run(program: &Program, ascii: bool, debug: bool, break_at_start: bool, dump: bool) {
+/// Assemble a source file (`intcode asm`)
+fn asm() {
+    let mut output = None;
+    let mut posargs = VecDeque::new();
+
+    let mut args = env::args().skip(2);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(path),
+                None => { print_usage(); process::exit(2) },
+            },
+            "-h" | "--help" => { print_usage(); process::exit(0) },
+            arg if arg.starts_with('-') => {
+                eprintln!("ERROR: Unknown argument '{}'", arg);
+                print_usage();
+                process::exit(2);
+            },
+            _ => posargs.push_back(arg),
+        }
+    }
+
+    if posargs.len() != 1 {
+        print_usage();
+        process::exit(2);
+    }
+    let source = posargs.pop_front().unwrap();
+
+    let program = match assembler::assemble_file(&source) {
+        Err(err) => {
+            eprintln!("ERROR: {}: {}", source, err);
+            process::exit(1);
+        },
+        Ok(program) => program,
+    };
+
+    let result = match output {
+        Some(path) => fs::write(&path, format!("{}\n", program)),
+        None => { println!("{}", program); Ok(()) },
+    };
+
+    if let Err(err) = result {
+        eprintln!("ERROR: Failed to write output: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Disassemble a program (`intcode disasm`)
+fn disasm() {
+    let mut dot = false;
+    let mut posargs = VecDeque::new();
+
+    for arg in env::args().skip(2) {
+        match arg.as_str() {
+            "--dot" => dot = true,
+            "-h" | "--help" => { print_usage(); process::exit(0) },
+            arg if arg.starts_with('-') => {
+                eprintln!("ERROR: Unknown argument '{}'", arg);
+                print_usage();
+                process::exit(2);
+            },
+            _ => posargs.push_back(arg),
+        }
+    }
+
+    if posargs.len() != 1 {
+        print_usage();
+        process::exit(2);
+    }
+
+    let program = match Program::from_file(posargs.pop_front().unwrap()) {
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            process::exit(1);
+        },
+        Ok(program) => program,
+    };
+
+    let disassembly = Disassembly::new(&program);
+    if dot {
+        print!("{}", disassembly.to_dot());
+    } else {
+        print!("{}", disassembly.listing());
+    }
+}
+
+fn run(start: Start, args: &Args) {
+    let debug = args.debug;
     let mut ascii_handler = AsciiIOHandler::new();
-    let mut cpu = if ascii {
-        IntcodeEmulator::new(ascii_handler.input_handler(), ascii_handler.output_handler())
+    for path in &args.scripts {
+        if let Err(err) = ascii_handler.load_script(path) {
+            eprintln!("ERROR: Failed to read script {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+    if let Some(path) = &args.transcript {
+        match fs::File::create(path) {
+            Ok(file) => ascii_handler.set_transcript(Box::new(io::BufWriter::new(file))),
+            Err(err) => {
+                eprintln!("ERROR: Failed to create transcript: {}", err);
+                process::exit(1);
+            },
+        }
+    }
+    let mem = args.memory.clone().unwrap_or_else(|| Box::new(DenseMemory::default()));
+    let mut cpu = if args.ascii {
+        IntcodeEmulator::with_memory(ascii_handler.input_handler(), ascii_handler.output_handler(), mem)
     } else {
-        IntcodeEmulator::default()
+        IntcodeEmulator::with_memory(Box::new(default_input_handler), Box::new(default_output_handler), mem)
     };
-    cpu.load_program(&program);
+
+    let mut labels = BTreeMap::new();
+    match start {
+        Start::Program(program) => cpu.load_program(&program),
+        Start::Image(image) => {
+            cpu.restore(&image.snapshot);
+            ascii_handler.set_pending_input(&image.input);
+            labels = image.labels;
+        },
+    }
+
     cpu.set_debug(debug);
+    if let Some(limit) = args.history {
+        cpu.enable_history(limit);
+    }
+
+    if let Some(path) = &args.trace {
+        match fs::File::create(path) {
+            Ok(file) => cpu.enable_profiler(Profiler::with_trace(Box::new(io::BufWriter::new(file)))),
+            Err(err) => {
+                eprintln!("ERROR: Failed to create trace: {}", err);
+                process::exit(1);
+            },
+        }
+    } else if args.profile {
+        cpu.enable_profiler(Profiler::new());
+    }
+
+    // Save machine image and profile before exiting
+    let exit = |cpu: &mut IntcodeEmulator, labels: &BTreeMap<usize, String>, code: i32| {
+        if let Some(mut profiler) = cpu.disable_profiler() {
+            if let Err(err) = profiler.finish() {
+                eprintln!("ERROR: Failed to write trace: {}", err);
+            }
+            if args.profile {
+                eprint!("{}", profiler);
+            }
+        }
+
+        if let Err(err) = ascii_handler.finish() {
+            eprintln!("ERROR: Failed to write transcript: {}", err);
+        }
 
-    if break_at_start {
-        attach_debugger(&mut cpu);
+        if let Some(path) = &args.save_on_exit {
+            let mut image = Image::new(cpu.snapshot());
+            image.input = ascii_handler.pending_input();
+            image.labels = labels.clone();
+            if let Err(err) = image.save(path) {
+                eprintln!("ERROR: {}", err);
+            }
+        }
+        process::exit(code);
+    };
+
+    if args.break_at_start && attach_debugger(&mut cpu, &mut labels) {
+        exit(&mut cpu, &labels, 0);
     }
 
     loop {
         match cpu.run() {
             Ok(()) => break,
+            Err(exception @ Exception::Breakpoint(_)) | Err(exception @ Exception::Watchpoint(_, _)) => {
+                eprintln!("{}", exception);
+                if attach_debugger(&mut cpu, &mut labels) {
+                    exit(&mut cpu, &labels, 0);
+                }
+            },
             Err(Exception::IllegalInstruction(opcode)) => {
                 eprintln!("Illegal instruction {}", opcode);
                 if debug {
-                    attach_debugger(&mut cpu);
+                    attach_debugger(&mut cpu, &mut labels);
                 } else {
                     cpu.dump_registers();
                     cpu.print_disassembled();
                     cpu.dump_memory();
                 }
-                process::exit(4);
+                exit(&mut cpu, &labels, 4);
             },
             Err(Exception::SegmentationFault(addr)) => {
                 eprintln!("Segmentation fault at 0x{:08x}", addr);
                 if debug {
-                    attach_debugger(&mut cpu);
+                    attach_debugger(&mut cpu, &mut labels);
                 } else {
                     cpu.dump_registers();
                     cpu.print_disassembled();
                     cpu.dump_memory();
                 }
-                process::exit(11);
+                exit(&mut cpu, &labels, 11);
             },
             Err(Exception::IOError(err)) => {
                 eprintln!("IO error: {}", err);
                 if debug {
-                    attach_debugger(&mut cpu);
+                    attach_debugger(&mut cpu, &mut labels);
                 }
-                process::exit(29);
+                exit(&mut cpu, &labels, 29);
             }
             Err(exception) => {
                 eprintln!("{}", exception.to_string());
                 if debug {
-                    attach_debugger(&mut cpu);
+                    attach_debugger(&mut cpu, &mut labels);
                 }
-                process::exit(1);
+                exit(&mut cpu, &labels, 1);
             }
         }
     }
 
-    if dump {
+    if args.dump {
         cpu.dump_memory();
     }
+
+    exit(&mut cpu, &labels, 0);
 }
 
-fn attach_debugger(cpu: &mut IntcodeEmulator) {
+/// Interactive debugger
+///
+/// Returns `true` if the user asked to quit, so the caller can exit normally
+/// (saving any machine image and flushing traces).
+fn attach_debugger(cpu: &mut IntcodeEmulator, labels: &mut BTreeMap<usize, String>) -> bool {
     // Read from TTY, even if stdin is redirected
     let mut tty = match fs::File::open("/dev/tty") {
         Err(err) => {
             eprintln!("ERROR: Could not open TTY: {}", err);
-            return;
+            return false;
         },
         Ok(file) => io::BufReader::new(file),
     };
@@ -144,6 +389,7 @@ fn attach_debugger(cpu: &mut IntcodeEmulator) {
     // Disassemble first instruction
     cpu.print_disassembled();
 
+    let mut quit = false;
     let mut last_line = String::new();
     loop {
         eprint!("debug> ");
@@ -170,31 +416,70 @@ fn attach_debugger(cpu: &mut IntcodeEmulator) {
         }
 
         let result = match args[0] {
-            "p" | "print" => print(&cpu, &args),
+            "p" | "print" => print(&cpu, &args, labels),
             "c" | "continue" => break,
             "j" | "jump" => {
-                read_param(&args, 1)
+                read_addr(&args, 1, labels)
                     .map(|addr| cpu.set_ip(addr))
                     .map(|_| cpu.print_disassembled())
             },
             "r" | "relbase" => read_param(&args, 1).map(|word| cpu.set_rb(word)),
-            "q" | "quit" => process::exit(0),
+            "q" | "quit" => { quit = true; break },
             "d" | "disassemble" => { cpu.print_disassembled(); Ok(()) },
             "s" | "step" => {
                 cpu.step().map(|_| cpu.print_disassembled())
                     .map_err(|e| e.to_string())
             },
+            "sb" | "stepback" => step_back(cpu),
+            "rc" | "reverse-continue" => reverse_continue(cpu),
+            "save" => read_param::<String>(&args, 1).map(|name| cpu.save_snapshot(&name)),
+            "restore" => {
+                read_param::<String>(&args, 1)
+                    .and_then(|name| if cpu.restore_snapshot(&name) { Ok(()) } else { Err(format!("No snapshot named '{}'", name)) })
+                    .map(|_| cpu.print_disassembled())
+            },
+            "snapshots" => {
+                for (name, snapshot) in cpu.snapshots() {
+                    eprintln!("{:16} ip:0x{:08x} rb:{}", name, snapshot.ip(), snapshot.rb());
+                }
+                Ok(())
+            },
+            "u" | "until" => read_addr(&args, 1, labels).and_then(|addr| resume(cpu, |cpu| cpu.run_until(addr))),
+            "f" | "finish" => finish(cpu),
+            "b" | "break" => add_breakpoint(cpu, &args, labels),
+            "w" | "watch" => read_addr(&args, 1, labels).map(|addr| cpu.add_watchpoint(addr, Access::Write)),
+            "rwatch" => read_addr(&args, 1, labels).map(|addr| cpu.add_watchpoint(addr, Access::Read)),
+            "awatch" => read_addr(&args, 1, labels).map(|addr| cpu.add_watchpoint(addr, Access::ReadWrite)),
+            "delete" => delete(cpu, &args, labels),
+            "l" | "label" => add_label(cpu, &args, labels),
+            "i" | "info" => { info(cpu, labels); Ok(()) },
             "D" | "dump" => { cpu.dump_memory(); Ok(()) },
             "h" | "help" => {
                 eprintln!("p|print [ ADDR | $ip | $rb ]");
                 eprintln!("                Print contents of address");
                 eprintln!("c|continue      Continue execution");
-                eprintln!("j|jump          Jump to address");
-                eprintln!("c|continue      Continue execution");
+                eprintln!("j|jump ADDR     Jump to address");
+                eprintln!("u|until ADDR    Run until address is reached");
+                eprintln!("f|finish        Run until current function returns ($rb decreases)");
+                eprintln!("b|break ADDR [if COND]");
+                eprintln!("                Set breakpoint (e.g. `b 0x10 if $rb == 100` or `b 16 if [0x20] != 0`)");
+                eprintln!("w|watch ADDR    Set watchpoint on write to address");
+                eprintln!("rwatch ADDR     Set watchpoint on read from address");
+                eprintln!("awatch ADDR     Set watchpoint on read from or write to address");
+                eprintln!("delete [ADDR]   Delete breakpoints and watchpoints at address (default: all)");
+                eprintln!("l|label NAME [ADDR]");
+                eprintln!("                Label an address (default: $ip), saved in machine images");
+                eprintln!("i|info          List labels, breakpoints and watchpoints");
                 eprintln!("r|relbase       Set the relative base register");
                 eprintln!("q|quit          Exit debugger and terminate program");
                 eprintln!("d|disassemble   Disassemble current instruction");
                 eprintln!("s|step          Step to the next instruction");
+                eprintln!("sb|stepback     Step back to the previous instruction (requires --history)");
+                eprintln!("rc|reverse-continue");
+                eprintln!("                Run backwards until a breakpoint or write watchpoint (requires --history)");
+                eprintln!("save NAME       Save a snapshot of the CPU state");
+                eprintln!("restore NAME    Restore a saved snapshot");
+                eprintln!("snapshots       List saved snapshots");
                 eprintln!("D|dump          Dump memory to console");
                 eprintln!("h|help          Print this help");
                 Ok(())
@@ -209,6 +494,8 @@ fn attach_debugger(cpu: &mut IntcodeEmulator) {
 
     // Re-enable debug-tracing if it was previously enabled
     cpu.set_debug(last_debug);
+
+    quit
 }
 
 fn read_param<T: std::str::FromStr>(args: &[&str], param: usize) -> Result<T, String> {
@@ -217,7 +504,147 @@ fn read_param<T: std::str::FromStr>(args: &[&str], param: usize) -> Result<T, St
     arg.parse::<T>().map_err(|_| format!("Failed to parse parameter {}", param))
 }
 
-fn print(cpu: &IntcodeEmulator, args: &[&str]) -> Result<(), String> {
+/// Read an address parameter (decimal or `0x` prefixed hexadecimal)
+fn read_addr(args: &[&str], param: usize, labels: &BTreeMap<usize, String>) -> Result<usize, String> {
+    let arg = args.get(param).ok_or_else(|| String::from("Missing parameter"))?;
+
+    parse_addr(arg, labels)
+}
+
+/// Parse an address (decimal, `0x` prefixed hexadecimal or a label)
+fn parse_addr(arg: &str, labels: &BTreeMap<usize, String>) -> Result<usize, String> {
+    if let Some((&addr, _)) = labels.iter().find(|(_, label)| label.as_str() == arg) {
+        return Ok(addr);
+    }
+
+    match arg.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => arg.parse::<usize>(),
+    }.map_err(|err| format!("Could not parse address: {}", err))
+}
+
+/// Resume execution from the debugger, reporting why execution stopped
+fn resume<F: FnMut(&mut IntcodeEmulator) -> Result<(), Exception>>(cpu: &mut IntcodeEmulator, mut run: F) -> Result<(), String> {
+    loop {
+        match run(cpu) {
+            Err(Exception::Yield) => continue,
+            Ok(()) if cpu.is_halted() => eprintln!("Program halted"),
+            Ok(()) => (),
+            Err(exception @ Exception::Breakpoint(_)) | Err(exception @ Exception::Watchpoint(_, _)) => {
+                eprintln!("{}", exception);
+            },
+            Err(exception) => return Err(exception.to_string()),
+        }
+        break;
+    }
+
+    cpu.print_disassembled();
+    Ok(())
+}
+
+/// Run until the current function returns
+///
+/// Functions allocate their stack frame by increasing `$rb`, so the function has
+/// returned once `$rb` drops below its current value and the return jump is taken.
+fn finish(cpu: &mut IntcodeEmulator) -> Result<(), String> {
+    let rb = cpu.rb();
+    resume(cpu, |cpu| {
+        cpu.run_while(|cpu| cpu.rb() >= rb)?;
+
+        // Take the return jump
+        let is_jump = cpu.current_instruction()
+            .map(|i| matches!(i.op(), Opcode::JumpIfTrue | Opcode::JumpIfFalse))
+            .unwrap_or(false);
+        if cpu.rb() < rb && is_jump {
+            cpu.step()?;
+        }
+
+        Ok(())
+    })
+}
+
+fn step_back(cpu: &mut IntcodeEmulator) -> Result<(), String> {
+    if !cpu.has_history() {
+        return Err(String::from("History is not being recorded (use --history)"));
+    }
+
+    if !cpu.step_back() {
+        return Err(String::from("No more history"));
+    }
+
+    cpu.print_disassembled();
+    Ok(())
+}
+
+fn reverse_continue(cpu: &mut IntcodeEmulator) -> Result<(), String> {
+    if !cpu.has_history() {
+        return Err(String::from("History is not being recorded (use --history)"));
+    }
+
+    match cpu.reverse_continue() {
+        Ok(()) => eprintln!("Reached start of history"),
+        Err(exception) => eprintln!("{}", exception),
+    }
+
+    cpu.print_disassembled();
+    Ok(())
+}
+
+fn add_breakpoint(cpu: &mut IntcodeEmulator, args: &[&str], labels: &BTreeMap<usize, String>) -> Result<(), String> {
+    let addr = read_addr(args, 1, labels)?;
+    let condition = match args.get(2) {
+        None => None,
+        Some(&"if") => Some(args[3..].join(" ").parse::<Condition>()?),
+        Some(arg) => return Err(format!("Unexpected argument '{}'", arg)),
+    };
+
+    cpu.add_breakpoint(addr, condition);
+    Ok(())
+}
+
+fn delete(cpu: &mut IntcodeEmulator, args: &[&str], labels: &BTreeMap<usize, String>) -> Result<(), String> {
+    let addrs: Vec<usize> = if args.len() > 1 {
+        vec![read_addr(args, 1, labels)?]
+    } else {
+        cpu.breakpoints().keys().chain(cpu.watchpoints().keys()).copied().collect()
+    };
+
+    let mut found = false;
+    for addr in addrs {
+        found |= cpu.remove_breakpoint(addr);
+        found |= cpu.remove_watchpoint(addr);
+    }
+
+    if found { Ok(()) } else { Err(String::from("No breakpoints or watchpoints to delete")) }
+}
+
+fn add_label(cpu: &IntcodeEmulator, args: &[&str], labels: &mut BTreeMap<usize, String>) -> Result<(), String> {
+    let name = args.get(1).ok_or_else(|| String::from("Missing label name"))?;
+    let addr = if args.len() > 2 { read_addr(args, 2, labels)? } else { cpu.ip() };
+
+    labels.retain(|_, label| label != name);
+    labels.insert(addr, name.to_string());
+    Ok(())
+}
+
+fn info(cpu: &IntcodeEmulator, labels: &BTreeMap<usize, String>) {
+    for (addr, label) in labels {
+        eprintln!("label 0x{:08x} {}", addr, label);
+    }
+
+    for (addr, condition) in cpu.breakpoints() {
+        match condition {
+            Some(condition) => eprintln!("breakpoint 0x{:08x} if {}", addr, condition),
+            None => eprintln!("breakpoint 0x{:08x}", addr),
+        }
+    }
+
+    for (addr, access) in cpu.watchpoints() {
+        eprintln!("watchpoint 0x{:08x} ({})", addr, access);
+    }
+}
+
+fn print(cpu: &IntcodeEmulator, args: &[&str], labels: &BTreeMap<usize, String>) -> Result<(), String> {
     if args.len() > 2 {
         return Err(String::from("Too many arguments"));
     }
@@ -234,14 +661,9 @@ fn print(cpu: &IntcodeEmulator, args: &[&str]) -> Result<(), String> {
     } else {
         // p [addr]
         let addr = match arg1 {
-            arg if arg.is_empty() => Ok(cpu.ip()),  // Default to $ip
-            arg => {
-                match arg {
-                    arg if arg.starts_with("0x") => usize::from_str_radix(&arg[2..], 16),
-                    arg => arg.parse::<usize>(),
-                }.map_err(|err| format!("Could not parse address: {}", err))
-            },
-        }?;
+            arg if arg.is_empty() => cpu.ip(),  // Default to $ip
+            arg => parse_addr(arg, labels)?,
+        };
 
         let value = cpu.mem().get(addr).ok_or_else(|| String::from("Address out of range"))?;
         eprintln!("{}", value);
@@ -252,8 +674,16 @@ fn print(cpu: &IntcodeEmulator, args: &[&str]) -> Result<(), String> {
 
 struct Args {
     ascii: bool,
+    scripts: Vec<String>,
+    transcript: Option<String>,
     debug: bool,
     break_at_start: bool,
     dump: bool,
-    program: String,
+    history: Option<usize>,
+    memory: Option<Box<dyn Memory>>,
+    profile: bool,
+    trace: Option<String>,
+    save_on_exit: Option<String>,
+    restore: Option<String>,
+    program: Option<String>,
 }
@@ -0,0 +1,417 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{self, Range};
+use crate::emulator::Word;
+
+/// Address space size for large dense memory (128 MiB), see `DenseMemory::new`
+pub const DENSE_LIMIT: usize = 1 << 24;
+
+/// Words of dense memory allocated up-front, and the default address space size (the original fixed memory size)
+pub const DENSE_INITIAL: usize = 1 << 15;
+
+/// Number of words per page of sparse memory
+pub const PAGE_SIZE: usize = 1 << 10;
+
+/// Value of memory that hasn't been written to
+static ZERO: Word = 0;
+
+/// Page of memory that hasn't been written to
+static ZERO_PAGE: [Word; PAGE_SIZE] = [0; PAGE_SIZE];
+
+/// Memory backend for `IntcodeEmulator`
+///
+/// All addresses below `len()` are valid and read as zero until written to.
+pub trait Memory: fmt::Debug {
+    /// Name of this backend (e.g. `dense`)
+    fn name(&self) -> &'static str;
+
+    /// Size of the address space
+    fn len(&self) -> usize;
+
+    /// Is the address space empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get a reference to the word at `addr`, or `None` if it is outside the address space
+    fn get(&self, addr: usize) -> Option<&Word>;
+
+    /// Get a mutable reference to the word at `addr`, allocating it if required
+    fn get_mut(&mut self, addr: usize) -> Option<&mut Word>;
+
+    /// Get a contiguous slice of words, or `None` if the backend doesn't store `range` contiguously
+    fn slice(&self, range: Range<usize>) -> Option<&[Word]>;
+
+    /// Get a contiguous mutable slice of words, allocating them if required
+    fn slice_mut(&mut self, range: Range<usize>) -> Option<&mut [Word]>;
+
+    /// Address ranges that have been allocated
+    fn allocated(&self) -> Vec<Range<usize>>;
+
+    /// Reset all memory to zero, freeing any allocations
+    fn clear(&mut self);
+
+    /// Clone into a new box
+    fn box_clone(&self) -> Box<dyn Memory>;
+}
+
+impl dyn Memory + '_ {
+    /// Copy of memory up to the end of the last allocation
+    pub fn to_vec(&self) -> Vec<Word> {
+        let end = self.allocated().last().map(|range| range.end).unwrap_or(0);
+
+        (0..end).map(|addr| self[addr]).collect()
+    }
+
+    /// Iterate over every word in the address space
+    pub fn iter(&self) -> impl Iterator<Item=&Word> {
+        (0..self.len()).map(move |addr| &self[addr])
+    }
+}
+
+impl ops::Index<usize> for dyn Memory + '_ {
+    type Output = Word;
+
+    fn index(&self, addr: usize) -> &Self::Output {
+        match self.get(addr) {
+            Some(word) => word,
+            None => panic!("address {:#x} out of range for memory of size {:#x}", addr, self.len()),
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for dyn Memory + '_ {
+    fn index_mut(&mut self, addr: usize) -> &mut Self::Output {
+        let len = self.len();
+        match self.get_mut(addr) {
+            Some(word) => word,
+            None => panic!("address {:#x} out of range for memory of size {:#x}", addr, len),
+        }
+    }
+}
+
+impl ops::Index<Range<usize>> for dyn Memory + '_ {
+    type Output = [Word];
+
+    fn index(&self, range: Range<usize>) -> &Self::Output {
+        match self.slice(range.clone()) {
+            Some(words) => words,
+            None => panic!("range {:#x?} not contiguous in {} memory of size {:#x}", range, self.name(), self.len()),
+        }
+    }
+}
+
+impl ops::IndexMut<Range<usize>> for dyn Memory + '_ {
+    fn index_mut(&mut self, range: Range<usize>) -> &mut Self::Output {
+        let (name, len) = (self.name(), self.len());
+        match self.slice_mut(range.clone()) {
+            Some(words) => words,
+            None => panic!("range {:#x?} not contiguous in {} memory of size {:#x}", range, name, len),
+        }
+    }
+}
+
+impl Clone for Box<dyn Memory> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Create a memory backend by name
+pub fn from_name(name: &str, limit: usize) -> Option<Box<dyn Memory>> {
+    match name {
+        "dense" => Some(Box::new(DenseMemory::new(limit))),
+        "sparse" => Some(Box::new(SparseMemory::new(limit))),
+        _ => None,
+    }
+}
+
+/// Contiguous memory that grows as high addresses are written
+///
+/// The first `DENSE_INITIAL` words are allocated up-front, so any range within them
+/// can be sliced (as with the original fixed-size memory).
+/// By default that is the whole address space; use `DenseMemory::new(DENSE_LIMIT)` for a larger one.
+/// Best for programs that use a compact region of memory starting at address 0.
+#[derive(Clone, Debug)]
+pub struct DenseMemory {
+    words: Vec<Word>,
+    /// One past the highest address written
+    used: usize,
+    limit: usize,
+}
+
+impl DenseMemory {
+    /// Create dense memory with an address space of `limit` words
+    pub fn new(limit: usize) -> Self {
+        DenseMemory { words: vec![0; limit.min(DENSE_INITIAL)], used: 0, limit }
+    }
+
+    /// Words currently backed by storage (at least `DENSE_INITIAL` words, unless the address space is smaller)
+    pub fn as_slice(&self) -> &[Word] {
+        &self.words
+    }
+
+    /// Mutable words currently backed by storage
+    pub fn as_mut_slice(&mut self) -> &mut [Word] {
+        // Any word may be written
+        self.used = self.words.len();
+        &mut self.words
+    }
+
+    /// Make sure the first `end` words are backed by storage
+    fn grow(&mut self, end: usize) {
+        if end > self.words.len() {
+            self.words.resize(end, 0);
+        }
+        self.used = self.used.max(end);
+    }
+}
+
+impl Default for DenseMemory {
+    fn default() -> Self {
+        DenseMemory::new(DENSE_INITIAL)
+    }
+}
+
+impl Memory for DenseMemory {
+    fn name(&self) -> &'static str {
+        "dense"
+    }
+
+    fn len(&self) -> usize {
+        self.limit
+    }
+
+    fn get(&self, addr: usize) -> Option<&Word> {
+        match self.words.get(addr) {
+            Some(word) => Some(word),
+            None if addr < self.limit => Some(&ZERO),
+            None => None,
+        }
+    }
+
+    fn get_mut(&mut self, addr: usize) -> Option<&mut Word> {
+        if addr >= self.limit {
+            return None;
+        }
+
+        self.grow(addr + 1);
+        self.words.get_mut(addr)
+    }
+
+    fn slice(&self, range: Range<usize>) -> Option<&[Word]> {
+        self.words.get(range)
+    }
+
+    fn slice_mut(&mut self, range: Range<usize>) -> Option<&mut [Word]> {
+        if range.start > range.end || range.end > self.limit {
+            return None;
+        }
+
+        self.grow(range.end);
+        self.words.get_mut(range)
+    }
+
+    fn allocated(&self) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        if self.used > 0 {
+            ranges.push(0..self.used);
+        }
+
+        ranges
+    }
+
+    fn clear(&mut self) {
+        self.words = vec![0; self.limit.min(DENSE_INITIAL)];
+        self.used = 0;
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory> {
+        Box::new(self.clone())
+    }
+}
+
+/// Memory allocated in pages of `PAGE_SIZE` words on first write
+///
+/// Best for programs that use widely scattered addresses.
+#[derive(Clone, Debug)]
+pub struct SparseMemory {
+    pages: BTreeMap<usize, Box<[Word]>>,
+    limit: usize,
+}
+
+impl SparseMemory {
+    /// Create sparse memory with an address space of `limit` words
+    pub fn new(limit: usize) -> Self {
+        SparseMemory { pages: BTreeMap::new(), limit }
+    }
+}
+
+impl Default for SparseMemory {
+    fn default() -> Self {
+        // Any non-negative address
+        SparseMemory::new(Word::MAX as usize)
+    }
+}
+
+impl Memory for SparseMemory {
+    fn name(&self) -> &'static str {
+        "sparse"
+    }
+
+    fn len(&self) -> usize {
+        self.limit
+    }
+
+    fn get(&self, addr: usize) -> Option<&Word> {
+        if addr >= self.limit {
+            return None;
+        }
+
+        match self.pages.get(&(addr / PAGE_SIZE)) {
+            Some(page) => Some(&page[addr % PAGE_SIZE]),
+            None => Some(&ZERO),
+        }
+    }
+
+    fn get_mut(&mut self, addr: usize) -> Option<&mut Word> {
+        if addr >= self.limit {
+            return None;
+        }
+
+        let page = self.pages.entry(addr / PAGE_SIZE)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+
+        Some(&mut page[addr % PAGE_SIZE])
+    }
+
+    /// Only ranges within a single page are contiguous
+    fn slice(&self, range: Range<usize>) -> Option<&[Word]> {
+        if range.end > self.limit {
+            return None;
+        }
+        let (page, offset) = page_range(&range)?;
+
+        match self.pages.get(&page) {
+            Some(page) => Some(&page[offset]),
+            None => Some(&ZERO_PAGE[offset]),
+        }
+    }
+
+    fn slice_mut(&mut self, range: Range<usize>) -> Option<&mut [Word]> {
+        if range.end > self.limit {
+            return None;
+        }
+        let (page, offset) = page_range(&range)?;
+
+        let page = self.pages.entry(page)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+
+        Some(&mut page[offset])
+    }
+
+    fn allocated(&self) -> Vec<Range<usize>> {
+        self.pages.keys()
+            .map(|&page| page * PAGE_SIZE..(page + 1) * PAGE_SIZE)
+            .collect()
+    }
+
+    fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory> {
+        Box::new(self.clone())
+    }
+}
+
+/// Page number and offsets within that page, if `range` lies within a single page
+fn page_range(range: &Range<usize>) -> Option<(usize, Range<usize>)> {
+    if range.start > range.end {
+        return None;
+    }
+
+    let page = range.start / PAGE_SIZE;
+    let (start, end) = (range.start - page * PAGE_SIZE, range.end - page * PAGE_SIZE);
+
+    (end <= PAGE_SIZE).then_some((page, start..end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_semantics(mut mem: Box<dyn Memory>) {
+        assert_eq!(mem[0], 0);
+        assert_eq!(mem.get(1 << 20), Some(&0));
+        assert!(mem.allocated().is_empty());
+
+        mem[3] = 42;
+        mem[1 << 20] = -1;
+        assert_eq!(mem[3], 42);
+        assert_eq!(mem[1 << 20], -1);
+        assert_eq!(mem.get(4), Some(&0));
+
+        let copy = mem.clone();
+        mem[3] = 0;
+        assert_eq!(copy[3], 42);
+
+        assert_eq!(mem.get(mem.len()), None);
+        assert_eq!(mem.get_mut(mem.len()), None);
+
+        mem.clear();
+        assert_eq!(mem[1 << 20], 0);
+        assert!(mem.allocated().is_empty());
+    }
+
+    #[test]
+    fn test_dense() {
+        check_semantics(Box::new(DenseMemory::new(DENSE_LIMIT)));
+
+        // Same size as the original fixed-size memory
+        let mem: Box<dyn Memory> = Box::new(DenseMemory::default());
+        assert_eq!(mem.len(), DENSE_INITIAL);
+        assert_eq!(mem.iter().count(), DENSE_INITIAL);
+
+        let mut mem: Box<dyn Memory> = Box::new(DenseMemory::new(16));
+        mem[10] = 1;
+        let range = 0..11;
+        assert_eq!(mem.allocated(), vec![range]);
+        assert_eq!(mem.to_vec(), vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_sparse() {
+        check_semantics(Box::new(SparseMemory::default()));
+
+        let mut mem: Box<dyn Memory> = Box::new(SparseMemory::default());
+        mem[PAGE_SIZE * 1000 + 1] = 1;
+        let page = PAGE_SIZE * 1000..PAGE_SIZE * 1001;
+        assert_eq!(mem.allocated(), vec![page]);
+    }
+
+    #[test]
+    fn test_slices() {
+        let mut mem: Box<dyn Memory> = Box::new(DenseMemory::default());
+        mem[0..3].copy_from_slice(&[1, 2, 3]);
+        assert_eq!(&mem[0..4], &[1, 2, 3, 0]);
+        assert_eq!(mem[DENSE_INITIAL - 1..DENSE_INITIAL].len(), 1);
+        assert_eq!(mem.slice(DENSE_INITIAL..DENSE_INITIAL + 1), None);
+        assert_eq!(mem.allocated(), vec![0..3]);
+        assert_eq!(mem.iter().take(4).copied().collect::<Vec<_>>(), vec![1, 2, 3, 0]);
+
+        let mut mem: Box<dyn Memory> = Box::new(SparseMemory::default());
+        assert_eq!(&mem[PAGE_SIZE..PAGE_SIZE + 2], &[0, 0]);
+        mem[PAGE_SIZE + 1..PAGE_SIZE + 3].copy_from_slice(&[4, 5]);
+        assert_eq!(&mem[PAGE_SIZE..PAGE_SIZE + 4], &[0, 4, 5, 0]);
+        assert_eq!(mem.slice(PAGE_SIZE - 1..PAGE_SIZE + 1), None);
+        assert_eq!(mem.allocated(), vec![PAGE_SIZE..2 * PAGE_SIZE]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_range() {
+        let mem: Box<dyn Memory> = Box::new(DenseMemory::new(16));
+        let _ = mem[16];
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io;
+use crate::emulator::{IntcodeEmulator, Event, Exception, Word};
+
+/// Number of consecutive reads of `Router::empty_input` before a machine is considered idle
+const IDLE_POLLS: u32 = 2;
+
+/// How machines take turns running
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Schedule {
+    /// Each machine runs until its next output before the next machine gets a turn
+    #[default]
+    RoundRobin,
+    /// Each machine runs until it is blocked waiting for input before the next machine gets a turn
+    UntilBlocked,
+}
+
+/// Routes output from one machine to the inputs of others
+pub trait Router {
+    /// Handle `word` output by machine `from`
+    ///
+    /// An error is raised as an `Exception::IOError` by the machine that output `word`.
+    fn route(&mut self, from: usize, word: Word, channels: &mut Channels) -> io::Result<()>;
+
+    /// Input for machine `id` when its channel is empty, or `None` to block until input arrives
+    ///
+    /// A machine that reads this input twice in a row without receiving anything else is idle.
+    fn empty_input(&mut self, _id: usize) -> Option<Word> {
+        None
+    }
+
+    /// Called when every machine is idle, blocked or halted
+    ///
+    /// Return `true` to keep running (e.g. after sending more input) or `false` to stop the network.
+    fn idle(&mut self, _channels: &mut Channels) -> bool {
+        false
+    }
+}
+
+/// Input channels for each machine, and output from the network
+#[derive(Clone, Debug, Default)]
+pub struct Channels {
+    inputs: Vec<VecDeque<Word>>,
+    output: Vec<Word>,
+}
+
+impl Channels {
+    /// Send `word` to machine `to`
+    ///
+    /// Panics if there is no such machine.
+    pub fn send(&mut self, to: usize, word: Word) {
+        self.inputs[to].push_back(word);
+    }
+
+    /// Output `word` from the network
+    pub fn output(&mut self, word: Word) {
+        self.output.push(word);
+    }
+
+    /// Number of machines
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Are there no machines
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+/// A machine in the network
+struct Machine {
+    cpu: IntcodeEmulator,
+    halted: bool,
+    blocked: bool,
+    polls: u32,
+}
+
+/// A network of Intcode computers
+pub struct Network<R: Router> {
+    machines: Vec<Machine>,
+    channels: Channels,
+    router: R,
+    schedule: Schedule,
+}
+
+impl<R: Router> Network<R> {
+    /// Create an empty network using `router`
+    pub fn new(router: R) -> Self {
+        Network { machines: Vec::new(), channels: Channels::default(), router, schedule: Schedule::default() }
+    }
+
+    /// Set how machines are scheduled
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+
+    /// Add a machine to the network, returning its ID
+    ///
+    /// Machines are driven using `IntcodeEmulator::resume`, so input and output handlers are not used.
+    pub fn add_machine(&mut self, cpu: IntcodeEmulator) -> usize {
+        self.machines.push(Machine { cpu, halted: false, blocked: false, polls: 0 });
+        self.channels.inputs.push(VecDeque::new());
+
+        self.machines.len() - 1
+    }
+
+    /// Send `word` to machine `to`
+    pub fn send(&mut self, to: usize, word: Word) {
+        self.channels.send(to, word);
+    }
+
+    /// The machine with ID `id`
+    pub fn machine(&self, id: usize) -> &IntcodeEmulator {
+        &self.machines[id].cpu
+    }
+
+    /// The router
+    pub fn router(&self) -> &R {
+        &self.router
+    }
+
+    /// Words output from the network
+    pub fn output(&self) -> &[Word] {
+        &self.channels.output
+    }
+
+    /// Have all machines halted
+    pub fn is_halted(&self) -> bool {
+        self.machines.iter().all(|m| m.halted)
+    }
+
+    /// Run until every machine is halted or idle and the router stops the network
+    ///
+    /// If a machine raises an exception, it's returned along with the ID of the machine.
+    pub fn run(&mut self) -> Result<(), (usize, Exception)> {
+        loop {
+            let runnable: Vec<_> = (0..self.machines.len()).filter(|&id| self.is_runnable(id)).collect();
+            if runnable.is_empty() {
+                if self.router.idle(&mut self.channels) {
+                    continue;
+                }
+                return Ok(());
+            }
+
+            for id in runnable {
+                match self.schedule {
+                    Schedule::RoundRobin => self.turn(id)?,
+                    Schedule::UntilBlocked => while self.is_runnable(id) {
+                        self.turn(id)?
+                    },
+                }
+            }
+        }
+    }
+
+    /// Can machine `id` make progress
+    fn is_runnable(&self, id: usize) -> bool {
+        let machine = &self.machines[id];
+
+        !machine.halted && (!machine.blocked || !self.channels.inputs[id].is_empty())
+    }
+
+    /// Run machine `id` until it outputs, halts or blocks
+    fn turn(&mut self, id: usize) -> Result<(), (usize, Exception)> {
+        let machine = &mut self.machines[id];
+        machine.blocked = false;
+
+        let mut input = None;
+        loop {
+            match machine.cpu.resume(input.take()).map_err(|err| (id, err))? {
+                Event::NeedInput => {
+                    if let Some(word) = self.channels.inputs[id].pop_front() {
+                        machine.polls = 0;
+                        input = Some(word);
+                    } else if machine.polls < IDLE_POLLS {
+                        machine.polls += 1;
+                        match self.router.empty_input(id) {
+                            Some(word) => input = Some(word),
+                            None => machine.blocked = true,
+                        }
+                    } else {
+                        machine.blocked = true;
+                    }
+
+                    if machine.blocked {
+                        return Ok(());
+                    }
+                },
+                Event::Output(word) => {
+                    // Sending counts as activity, so the machine is no longer idle
+                    machine.polls = 0;
+                    self.router.route(id, word, &mut self.channels).map_err(|err| (id, Exception::IOError(err)))?;
+                    return Ok(());
+                },
+                Event::Halted => {
+                    machine.halted = true;
+                    return Ok(());
+                },
+            }
+        }
+    }
+}
+
+/// Sends the output of each machine to the next, with the last machine's output leaving the network
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Pipeline;
+
+impl Router for Pipeline {
+    fn route(&mut self, from: usize, word: Word, channels: &mut Channels) -> io::Result<()> {
+        if from + 1 < channels.len() {
+            channels.send(from + 1, word);
+        } else {
+            channels.output(word);
+        }
+
+        Ok(())
+    }
+}
+
+/// Like `Pipeline`, but the last machine's output is also fed back into the first
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Ring;
+
+impl Router for Ring {
+    fn route(&mut self, from: usize, word: Word, channels: &mut Channels) -> io::Result<()> {
+        if from + 1 == channels.len() {
+            channels.output(word);
+        }
+        channels.send((from + 1) % channels.len(), word);
+
+        Ok(())
+    }
+}
+
+/// A packet sent between machines
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub source: usize,
+    pub destination: usize,
+    pub payload: Vec<Word>,
+}
+
+/// Handles packets sent outside the network and wakes the network when it is idle
+pub trait Nat {
+    /// Receive a packet sent to an address with no machine
+    fn receive(&mut self, packet: Packet);
+
+    /// The network is idle. Return a packet to send or `None` to stop the network.
+    fn idle(&mut self) -> Option<Packet>;
+}
+
+/// Routes addressed packets between machines
+///
+/// Machines send packets as a destination address followed by the payload.
+/// Each machine is given its address (ID) as its first input,
+/// and receives `-1` when it tries to read and there are no packets waiting.
+pub struct PacketRouter<N: Nat> {
+    payload_len: usize,
+    buffers: Vec<Vec<Word>>,
+    nat: N,
+}
+
+impl<N: Nat> PacketRouter<N> {
+    /// Create a router for packets with `payload_len` words of payload
+    pub fn new(payload_len: usize, nat: N) -> Self {
+        PacketRouter { payload_len, buffers: Vec::new(), nat }
+    }
+
+    /// The NAT
+    pub fn nat(&self) -> &N {
+        &self.nat
+    }
+
+    /// Deliver a packet to a machine, or the NAT if there is no such machine
+    fn deliver(&mut self, packet: Packet, channels: &mut Channels) {
+        if packet.destination < channels.len() {
+            for &word in &packet.payload {
+                channels.send(packet.destination, word);
+            }
+        } else {
+            self.nat.receive(packet);
+        }
+    }
+}
+
+impl<N: Nat> Router for PacketRouter<N> {
+    fn route(&mut self, from: usize, word: Word, channels: &mut Channels) -> io::Result<()> {
+        if self.buffers.len() <= from {
+            self.buffers.resize(from + 1, Vec::new());
+        }
+
+        let buffer = &mut self.buffers[from];
+        buffer.push(word);
+        if buffer.len() < self.payload_len + 1 {
+            return Ok(());
+        }
+
+        let address = buffer[0];
+        let payload = buffer.split_off(1);
+        buffer.clear();
+
+        // Negative addresses are rejected, rather than being delivered to the NAT
+        let destination = usize::try_from(address)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid packet address {}", address)))?;
+        self.deliver(Packet { source: from, destination, payload }, channels);
+
+        Ok(())
+    }
+
+    fn empty_input(&mut self, _id: usize) -> Option<Word> {
+        Some(-1)
+    }
+
+    fn idle(&mut self, channels: &mut Channels) -> bool {
+        match self.nat.idle() {
+            Some(packet) => {
+                self.deliver(packet, channels);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Program;
+
+    fn amplifiers<R: Router>(router: R, program: &Program, phases: &[Word]) -> Network<R> {
+        let mut network = Network::new(router);
+        for &phase in phases {
+            let mut cpu = IntcodeEmulator::default();
+            cpu.load_program(program);
+            let id = network.add_machine(cpu);
+            network.send(id, phase);
+        }
+        network.send(0, 0);
+
+        network
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let program = Program::new(&[3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0]);
+        for &schedule in &[Schedule::RoundRobin, Schedule::UntilBlocked] {
+            let mut network = amplifiers(Pipeline, &program, &[4, 3, 2, 1, 0]);
+            network.set_schedule(schedule);
+            network.run().unwrap();
+
+            assert!(network.is_halted());
+            assert_eq!(network.output(), &[43210]);
+        }
+    }
+
+    #[test]
+    fn test_ring() {
+        let program = Program::new(&[3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26,
+                                     27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5]);
+        for &schedule in &[Schedule::RoundRobin, Schedule::UntilBlocked] {
+            let mut network = amplifiers(Ring, &program, &[9, 8, 7, 6, 5]);
+            network.set_schedule(schedule);
+            network.run().unwrap();
+
+            assert!(network.is_halted());
+            assert_eq!(network.output().last(), Some(&139629729));
+        }
+    }
+
+    /// Stops the network at the first packet it receives
+    #[derive(Default)]
+    struct FirstPacket(Option<Packet>);
+
+    impl Nat for FirstPacket {
+        fn receive(&mut self, packet: Packet) {
+            self.0.get_or_insert(packet);
+        }
+
+        fn idle(&mut self) -> Option<Packet> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_packets() {
+        // Machine 0 sends (7, 8) to machine 1, which adds them and sends the sum to address 255
+        let program = crate::assembler::assemble("\
+                    INPUT addr
+                    JMPTRUE addr $recv
+                    OUTPUT $1
+                    OUTPUT $7
+                    OUTPUT $8
+                    HALT
+            recv:   INPUT x
+                    CMPEQ x $-1 flag
+                    JMPTRUE flag $recv
+                    INPUT y
+                    ADD x y x
+                    OUTPUT $255
+                    OUTPUT x
+                    OUTPUT $0
+                    HALT
+            addr:   .data 0
+            x:      .data 0
+            y:      .data 0
+            flag:   .data 0
+        ").unwrap();
+
+        let mut network = Network::new(PacketRouter::new(2, FirstPacket::default()));
+        for addr in 0..2 {
+            let mut cpu = IntcodeEmulator::default();
+            cpu.load_program(&program);
+            network.add_machine(cpu);
+            network.send(addr, addr as Word);
+        }
+
+        assert!(network.run().is_ok());
+        assert!(network.is_halted());
+
+        let packet = network.router().nat().0.clone().unwrap();
+        assert_eq!(packet, Packet { source: 1, destination: 255, payload: vec![15, 0] });
+    }
+
+    /// Collects every packet it receives
+    #[derive(Default)]
+    struct AllPackets(Vec<Packet>);
+
+    impl Nat for AllPackets {
+        fn receive(&mut self, packet: Packet) {
+            self.0.push(packet);
+        }
+
+        fn idle(&mut self) -> Option<Packet> {
+            None
+        }
+    }
+
+    fn packet_network(source: &str) -> Network<PacketRouter<AllPackets>> {
+        let program = crate::assembler::assemble(source).unwrap();
+        let mut network = Network::new(PacketRouter::new(2, AllPackets::default()));
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&program);
+        network.add_machine(cpu);
+        network.send(0, 0);
+
+        network
+    }
+
+    #[test]
+    fn test_sending_is_not_idle() {
+        // Polls twice before each send, which must not make the machine idle
+        let mut network = packet_network("\
+                    INPUT x
+                    INPUT x
+                    INPUT x
+                    OUTPUT $255
+                    OUTPUT $1
+                    OUTPUT $0
+                    INPUT x
+                    INPUT x
+                    OUTPUT $255
+                    OUTPUT $2
+                    OUTPUT $0
+                    HALT
+            x:      .data 0
+        ");
+
+        assert!(network.run().is_ok());
+        assert!(network.is_halted());
+
+        let payloads: Vec<_> = network.router().nat().0.iter().map(|p| p.payload.clone()).collect();
+        assert_eq!(payloads, vec![vec![1, 0], vec![2, 0]]);
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let mut network = packet_network("\
+                    INPUT x
+                    OUTPUT $-3
+                    OUTPUT $1
+                    OUTPUT $2
+                    HALT
+            x:      .data 0
+        ");
+
+        assert!(matches!(network.run(), Err((0, Exception::IOError(_)))));
+        assert!(network.router().nat().0.is_empty());
+    }
+}
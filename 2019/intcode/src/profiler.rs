@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use crate::emulator::{Word, Opcode};
+
+/// Number of rows shown in each table of the summary
+const SUMMARY_ROWS: usize = 10;
+
+/// Records where a program spends its cycles
+///
+/// Each executed instruction takes one cycle.
+/// A loop is any backward jump that is taken, spanning from the jump target to the jump instruction.
+///
+/// If a trace writer is provided, every instruction and I/O event is written as it happens,
+/// followed by the profile when `finish` is called.
+/// The trace is in JSON lines format, with a `type` field on every line:
+///
+/// ```text
+/// {"type":"step","cycle":0,"ip":0,"op":"RBOFFSET"}
+/// {"type":"input","cycle":1,"ip":2,"word":1}
+/// {"type":"output","cycle":9,"ip":30,"word":3335138414}
+/// {"type":"address","ip":0,"count":1}
+/// {"type":"opcode","op":"ADD","count":4}
+/// {"type":"loop","start":12,"end":20,"iterations":100,"cycles":500}
+/// {"type":"summary","cycles":10,"inputs":1,"outputs":1}
+/// ```
+#[derive(Default)]
+pub struct Profiler {
+    cycles: u64,
+    addresses: HashMap<usize, u64>,
+    opcodes: HashMap<Opcode, u64>,
+    loops: HashMap<(usize, usize), u64>,
+    io: Vec<IoEvent>,
+    trace: Option<Box<dyn Write>>,
+    trace_error: Option<io::Error>,
+}
+
+impl Profiler {
+    /// Create a new profiler
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Create a new profiler that writes a trace to `writer`
+    pub fn with_trace(writer: Box<dyn Write>) -> Self {
+        Profiler { trace: Some(writer), ..Profiler::default() }
+    }
+
+    /// Record execution of the instruction at `ip`, which left the CPU at `next_ip`
+    pub(crate) fn record_step(&mut self, ip: usize, op: Opcode, next_ip: usize) {
+        let cycle = self.cycles;
+        self.trace(|| format!(r#"{{"type":"step","cycle":{},"ip":{},"op":"{}"}}"#, cycle, ip, op));
+
+        self.cycles += 1;
+        *self.addresses.entry(ip).or_insert(0) += 1;
+        *self.opcodes.entry(op).or_insert(0) += 1;
+
+        if matches!(op, Opcode::JumpIfTrue | Opcode::JumpIfFalse) && next_ip <= ip {
+            *self.loops.entry((next_ip, ip)).or_insert(0) += 1;
+        }
+    }
+
+    /// Record input or output by the instruction at `ip`
+    pub(crate) fn record_io(&mut self, ip: usize, kind: IoKind, word: Word) {
+        let event = IoEvent { cycle: self.cycles, ip, kind, word };
+        self.trace(|| event.to_json());
+        self.io.push(event);
+    }
+
+    /// Write a line to the trace (if any)
+    fn trace<F: FnOnce() -> String>(&mut self, line: F) {
+        if let Some(writer) = &mut self.trace {
+            if let Err(err) = writeln!(writer, "{}", line()) {
+                // Stop tracing, but report the error in `finish`
+                self.trace = None;
+                self.trace_error = Some(err);
+            }
+        }
+    }
+
+    /// Write the profile to the trace and flush it
+    ///
+    /// Returns the first error encountered while writing the trace.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.trace_error.take() {
+            return Err(err);
+        }
+
+        if let Some(mut writer) = self.trace.take() {
+            self.write_profile(&mut writer)?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the profile in JSON lines format
+    pub fn write_profile<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (ip, count) in self.addresses() {
+            writeln!(writer, r#"{{"type":"address","ip":{},"count":{}}}"#, ip, count)?;
+        }
+
+        for (op, count) in self.opcodes() {
+            writeln!(writer, r#"{{"type":"opcode","op":"{}","count":{}}}"#, op, count)?;
+        }
+
+        for l in self.hot_loops() {
+            writeln!(writer, r#"{{"type":"loop","start":{},"end":{},"iterations":{},"cycles":{}}}"#,
+                     l.start, l.end, l.iterations, l.cycles)?;
+        }
+
+        writeln!(writer, r#"{{"type":"summary","cycles":{},"inputs":{},"outputs":{}}}"#,
+                 self.cycles, self.count_io(IoKind::Input), self.count_io(IoKind::Output))
+    }
+
+    /// Total number of cycles executed
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Number of times each address was executed, most executed first
+    pub fn addresses(&self) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<_> = self.addresses.iter().map(|(&ip, &count)| (ip, count)).collect();
+        addresses.sort_by_key(|&(ip, count)| (u64::MAX - count, ip));
+
+        addresses
+    }
+
+    /// Number of times each opcode was executed, most executed first
+    pub fn opcodes(&self) -> Vec<(Opcode, u64)> {
+        let mut opcodes: Vec<_> = self.opcodes.iter().map(|(&op, &count)| (op, count)).collect();
+        opcodes.sort_by_key(|&(op, count)| (u64::MAX - count, Word::from(op)));
+
+        opcodes
+    }
+
+    /// Loops, most cycles first
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<_> = self.loops.iter().map(|(&(start, end), &iterations)| {
+            let cycles = (start..=end).filter_map(|ip| self.addresses.get(&ip)).sum();
+            HotLoop { start, end, iterations, cycles }
+        }).collect();
+        loops.sort_by_key(|l| (u64::MAX - l.cycles, l.start, l.end));
+
+        loops
+    }
+
+    /// Input and output, in the order it happened
+    pub fn io(&self) -> &[IoEvent] {
+        &self.io
+    }
+
+    fn count_io(&self, kind: IoKind) -> usize {
+        self.io.iter().filter(|event| event.kind == kind).count()
+    }
+}
+
+impl fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("Profiler")
+            .field("cycles", &self.cycles)
+            .field("tracing", &self.trace.is_some())
+            .finish()
+    }
+}
+
+impl fmt::Display for Profiler {
+    /// Summary table
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let percent = |count: u64| 100.0 * count as f64 / self.cycles.max(1) as f64;
+
+        writeln!(f, "{} cycles, {} inputs, {} outputs",
+                 self.cycles, self.count_io(IoKind::Input), self.count_io(IoKind::Output))?;
+
+        writeln!(f)?;
+        writeln!(f, "{:10} {:>12} {:>7}", "OPCODE", "COUNT", "%")?;
+        for (op, count) in self.opcodes() {
+            writeln!(f, "{:10} {:>12} {:>6.2}%", op.to_string(), count, percent(count))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:10} {:>12} {:>7}", "ADDRESS", "COUNT", "%")?;
+        for (ip, count) in self.addresses().into_iter().take(SUMMARY_ROWS) {
+            writeln!(f, "{:08x}   {:>12} {:>6.2}%", ip, count, percent(count))?;
+        }
+
+        let loops = self.hot_loops();
+        if !loops.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{:17} {:>12} {:>12} {:>7}", "LOOP", "ITERATIONS", "CYCLES", "%")?;
+            for l in loops.into_iter().take(SUMMARY_ROWS) {
+                writeln!(f, "{:08x}-{:08x} {:>12} {:>12} {:>6.2}%", l.start, l.end, l.iterations, l.cycles, percent(l.cycles))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Direction of an I/O event
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IoKind {
+    Input,
+    Output,
+}
+
+/// Input or output of a word
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IoEvent {
+    /// Cycle of the instruction that did the I/O
+    pub cycle: u64,
+    /// Address of the instruction that did the I/O
+    pub ip: usize,
+    pub kind: IoKind,
+    pub word: Word,
+}
+
+impl IoEvent {
+    fn to_json(self) -> String {
+        let kind = match self.kind {
+            IoKind::Input => "input",
+            IoKind::Output => "output",
+        };
+
+        format!(r#"{{"type":"{}","cycle":{},"ip":{},"word":{}}}"#, kind, self.cycle, self.ip, self.word)
+    }
+}
+
+/// A backward jump and the code it repeats
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HotLoop {
+    /// Address the loop jumps back to
+    pub start: usize,
+    /// Address of the jump instruction
+    pub end: usize,
+    /// Number of times the jump was taken
+    pub iterations: u64,
+    /// Number of cycles spent executing instructions between `start` and `end`
+    pub cycles: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::emulator::{IntcodeEmulator, Event};
+    use crate::test_util::Buffer;
+
+    #[test]
+    fn test_profile() {
+        let program = assemble("\
+            start:  ADD count $1 count
+                    CMPLT count $3 flag
+                    JMPTRUE flag $start
+                    OUTPUT count
+                    HALT
+            count:  .data 0
+            flag:   .data 0
+        ").unwrap();
+
+        let buffer = Buffer::default();
+        let mut cpu = IntcodeEmulator::default();
+        cpu.load_program(&program);
+        cpu.enable_profiler(Profiler::with_trace(Box::new(buffer.clone())));
+
+        assert_eq!(cpu.resume(None).unwrap(), Event::Output(3));
+        assert_eq!(cpu.resume(None).unwrap(), Event::Halted);
+
+        let mut profiler = cpu.disable_profiler().unwrap();
+        assert_eq!(profiler.cycles(), 10);
+        assert_eq!(profiler.addresses()[0], (0, 3));
+        assert_eq!(profiler.opcodes().last(), Some(&(Opcode::Output, 1)));
+        assert_eq!(profiler.hot_loops(), vec![HotLoop { start: 0, end: 8, iterations: 2, cycles: 9 }]);
+        assert_eq!(profiler.io(), &[IoEvent { cycle: 9, ip: 11, kind: IoKind::Output, word: 3 }]);
+
+        profiler.finish().unwrap();
+        let trace = buffer.contents();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(lines[0], r#"{"type":"step","cycle":0,"ip":0,"op":"ADD"}"#);
+        assert!(lines.contains(&r#"{"type":"output","cycle":9,"ip":11,"word":3}"#));
+        assert!(lines.contains(&r#"{"type":"loop","start":0,"end":8,"iterations":2,"cycles":9}"#));
+        assert_eq!(lines.last(), Some(&r#"{"type":"summary","cycles":10,"inputs":0,"outputs":1}"#));
+    }
+}
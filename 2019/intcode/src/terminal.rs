@@ -0,0 +1,186 @@
+use std::{env, fs, io, thread};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where frames are written
+enum Output {
+    Live(io::Stdout),
+    Record(Box<dyn Write>),
+}
+
+/// Terminal for drawing animations
+///
+/// Output is buffered into frames, which are either drawn live (rate-limited to the frame rate)
+/// or recorded to an [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/) file,
+/// with each frame timestamped as if it was played at the frame rate.
+pub struct Terminal {
+    width: u32,
+    height: u32,
+    fps: u64,
+    turbo: bool,
+    buffer: String,
+    output: Output,
+    frames: u64,
+}
+
+impl Terminal {
+    /// Create a terminal that draws live to stdout
+    pub fn new(width: u32, height: u32, fps: u64) -> Self {
+        Terminal::with_output(Output::Live(io::stdout()), width, height, fps)
+    }
+
+    /// Create a terminal that records to `writer` in asciicast v2 format
+    pub fn record(mut writer: Box<dyn Write>, width: u32, height: u32, fps: u64) -> io::Result<Self> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        writeln!(writer, r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {}}}"#, width, height, timestamp)?;
+
+        Ok(Terminal::with_output(Output::Record(writer), width, height, fps))
+    }
+
+    /// Create a terminal from the command-line arguments
+    ///
+    /// `--record FILE` records to `FILE` and `--turbo` draws live without delay between frames.
+    pub fn from_args(width: u32, height: u32, fps: u64) -> io::Result<Self> {
+        let args: Vec<_> = env::args().skip(1).collect();
+
+        let mut terminal = match args.iter().position(|arg| arg == "--record") {
+            Some(i) => {
+                let path = args.get(i + 1)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--record requires a FILE"))?;
+                let file = io::BufWriter::new(fs::File::create(path)?);
+                Terminal::record(Box::new(file), width, height, fps)?
+            },
+            None => Terminal::new(width, height, fps),
+        };
+        terminal.set_turbo(args.iter().any(|arg| arg == "--turbo"));
+
+        Ok(terminal)
+    }
+
+    fn with_output(output: Output, width: u32, height: u32, fps: u64) -> Self {
+        Terminal { width, height, fps, turbo: false, buffer: String::new(), output, frames: 0 }
+    }
+
+    /// Don't wait between frames when drawing live (recordings are always timed at the frame rate)
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    /// Is the terminal skipping the delay between frames
+    pub fn is_turbo(&self) -> bool {
+        self.turbo
+    }
+
+    /// Is the terminal being recorded
+    pub fn is_recording(&self) -> bool {
+        matches!(self.output, Output::Record(_))
+    }
+
+    /// Add output to the current frame
+    pub fn print(&mut self, s: &str) {
+        self.buffer.push_str(s);
+    }
+
+    /// Clear the screen, hide the cursor and resize the console
+    pub fn setup(&mut self) {
+        let resize = format!("\x1B[8;{};{}t", self.height, self.width);
+        self.print("\x1B[2J");  // Clear screen
+        self.print("\x1B[?25l");  // Hide cursor
+        self.print(&resize);  // Resize console
+    }
+
+    /// Draw the current frame
+    pub fn frame(&mut self) -> io::Result<()> {
+        let elapsed = self.frames as f64 / self.fps as f64;
+        let frame = std::mem::take(&mut self.buffer);
+        self.frames += 1;
+
+        match &mut self.output {
+            Output::Live(stdout) => {
+                stdout.write_all(frame.as_bytes())?;
+                stdout.flush()?;
+                if !self.turbo {
+                    thread::sleep(Duration::from_micros(1_000_000 / self.fps));
+                }
+            },
+            Output::Record(writer) => {
+                if !frame.is_empty() {
+                    write_event(writer, elapsed, &frame)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Reset the terminal and write any remaining output
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.print("\x1Bc");  // Reset the terminal
+
+        let frame = std::mem::take(&mut self.buffer);
+        match &mut self.output {
+            Output::Live(stdout) => {
+                stdout.write_all(frame.as_bytes())?;
+                stdout.flush()
+            },
+            Output::Record(writer) => {
+                write_event(writer, self.frames as f64 / self.fps as f64, &frame)?;
+                writer.flush()
+            },
+        }
+    }
+}
+
+/// Write an output event at `elapsed` seconds
+fn write_event(writer: &mut Box<dyn Write>, elapsed: f64, output: &str) -> io::Result<()> {
+    // A real terminal would translate newlines to CRLF
+    let output = output.replace('\n', "\r\n");
+    writeln!(writer, r#"[{:.6}, "o", "{}"]"#, elapsed, escape(&output))
+}
+
+/// Escape a string for JSON
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7F' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Buffer;
+
+    #[test]
+    fn test_record() {
+        let buffer = Buffer::default();
+        let mut terminal = Terminal::record(Box::new(buffer.clone()), 80, 24, 4).unwrap();
+        assert!(terminal.is_recording());
+
+        terminal.print("\x1B[2J");
+        terminal.print("\"Hello\"\n");
+        terminal.frame().unwrap();
+        terminal.frame().unwrap();  // Empty frames are skipped, but still take time
+        terminal.print("World");
+        terminal.frame().unwrap();
+        terminal.finish().unwrap();
+
+        let recording = buffer.contents();
+        let lines: Vec<_> = recording.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with(r#"{"version": 2, "width": 80, "height": 24, "timestamp": "#));
+        assert_eq!(lines[1], r#"[0.000000, "o", "\u001b[2J\"Hello\"\r\n"]"#);
+        assert_eq!(lines[2], r#"[0.500000, "o", "World"]"#);
+        assert_eq!(lines[3], r#"[0.750000, "o", "\u001bc"]"#);
+    }
+}
@@ -0,0 +1,251 @@
+// Handheld game console
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+pub type Program = Vec<Instruction>;
+
+pub fn read_program<T: AsRef<Path>>(path: T) -> Program {
+    fs::read_to_string(path).expect("Failed to read input")
+        .lines()
+        .map(|line| {
+            let mut it = line.split_whitespace();
+            let op = Operation::from_str(it.next().expect("Missing instruction op"))
+                .expect("Failed to parse operation");
+            let arg = it.next().expect("Missing instruction arg")
+                .parse().expect("Failed to parse instruction arg");
+
+            Instruction { op, arg }
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub op: Operation,
+    pub arg: i32,
+}
+
+impl Instruction {
+    /// Address of the instruction executed after this one at `addr`.
+    /// Returns `None` if it would be before the start of the program.
+    pub fn next(&self, addr: usize) -> Option<usize> {
+        match self.op {
+            Operation::JMP => {
+                let next = addr as i64 + self.arg as i64;
+                if next < 0 { None } else { Some(next as usize) }
+            },
+            Operation::NOP | Operation::ACC => Some(addr + 1),
+        }
+    }
+
+    /// Swap a NOP for a JMP (or vice versa).
+    pub fn flipped(&self) -> Option<Instruction> {
+        let op = match self.op {
+            Operation::NOP => Operation::JMP,
+            Operation::JMP => Operation::NOP,
+            Operation::ACC => return None,
+        };
+
+        Some(Instruction { op, arg: self.arg })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+}", self.op, self.arg)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operation {
+    NOP,
+    JMP,
+    ACC,
+}
+
+impl Operation {
+    pub fn from_str(s: &str) -> Option<Operation> {
+        match s {
+            "nop" => Some(Operation::NOP),
+            "jmp" => Some(Operation::JMP),
+            "acc" => Some(Operation::ACC),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Operation::NOP => "nop",
+            Operation::JMP => "jmp",
+            Operation::ACC => "acc",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+pub struct CPU<'a> {
+    pub pc: usize,
+    pub acc: i32,
+    program: &'a [Instruction],
+}
+
+impl<'a> CPU<'a> {
+    pub fn from_program(program: &'a [Instruction]) -> Self {
+        CPU { pc: 0, acc: 0, program }
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.pc == self.program.len()
+    }
+
+    pub fn step(&mut self) -> Result<(), &'static str> {
+        if self.pc >= self.program.len() {
+            return Err("Attempt to execute out of bounds");
+        }
+
+        let instr = self.program[self.pc];
+        if instr.op == Operation::ACC {
+            self.acc += instr.arg;
+        }
+        self.pc = instr.next(self.pc).ok_or("Jump before start of program")?;
+
+        Ok(())
+    }
+}
+
+/// Record of the instructions executed by a CPU.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    /// Address of each instruction executed, along with the accumulator after it
+    pub steps: Vec<(usize, i32)>,
+    /// Address of the instruction that would have been executed a second time
+    pub loop_start: Option<usize>,
+}
+
+impl Trace {
+    /// Instructions that make up the infinite loop (in the order they're executed).
+    pub fn loop_steps(&self) -> &[(usize, i32)] {
+        let start = self.loop_start
+            .and_then(|addr| self.steps.iter().position(|&(a, _)| a == addr))
+            .unwrap_or(self.steps.len());
+
+        &self.steps[start..]
+    }
+
+    /// Listing of the infinite loop, with the accumulator after each instruction.
+    /// Instructions in `marked` are annotated with the change that would break the loop.
+    pub fn loop_listing(&self, program: &[Instruction], marked: Option<usize>) -> String {
+        let mut listing = String::new();
+        for &(addr, acc) in self.loop_steps() {
+            let instr = program[addr];
+            write!(listing, "{:4}: {:<8} acc={}", addr, instr.to_string(), acc).unwrap();
+            if let (true, Some(flipped)) = (marked == Some(addr), instr.flipped()) {
+                write!(listing, "  <- change to {} to terminate", flipped.op).unwrap();
+            }
+            if let Some(start) = self.loop_start.filter(|&start| instr.next(addr) == Some(start)) {
+                write!(listing, "  <- loops back to {}", start).unwrap();
+            }
+            writeln!(listing).unwrap();
+        }
+
+        listing
+    }
+}
+
+/// Step through program instructions until EOF or program would re-execute an instruction.
+pub fn run_until_loop(cpu: &mut CPU) -> Trace {
+    let mut trace = Trace::default();
+    let mut seen = HashSet::new();
+    while !cpu.is_eof() {
+        if !seen.insert(cpu.pc) {
+            trace.loop_start = Some(cpu.pc);
+            break;
+        }
+        let pc = cpu.pc;
+        cpu.step().expect("Execution failed");
+        trace.steps.push((pc, cpu.acc));
+    }
+
+    trace
+}
+
+/// Which addresses eventually lead to the program terminating
+/// (reaching the address just past the last instruction).
+pub fn reaches_end(program: &[Instruction]) -> Vec<bool> {
+    let end = program.len();
+    let mut predecessors = vec![Vec::new(); end + 1];
+    for (addr, instr) in program.iter().enumerate() {
+        if let Some(next) = instr.next(addr).filter(|&next| next <= end) {
+            predecessors[next].push(addr);
+        }
+    }
+
+    let mut reached = vec![false; end + 1];
+    reached[end] = true;
+    let mut queue: VecDeque<_> = [end].iter().copied().collect();
+    while let Some(addr) = queue.pop_front() {
+        for &pred in &predecessors[addr] {
+            if !reached[pred] {
+                reached[pred] = true;
+                queue.push_back(pred);
+            }
+        }
+    }
+
+    reached
+}
+
+/// Find the single NOP/JMP that when flipped lets the program terminate.
+///
+/// Only instructions executed before the loop matter. Flipping one of those is enough
+/// if it leads to an address that already reaches the end
+/// (that path can't go through the flipped instruction, otherwise the program would have terminated).
+pub fn find_corruption(program: &[Instruction]) -> Option<usize> {
+    let reached = reaches_end(program);
+    let trace = run_until_loop(&mut CPU::from_program(program));
+
+    trace.steps.iter()
+        .map(|&(addr, _)| addr)
+        .find(|&addr| {
+            program[addr].flipped()
+                .and_then(|instr| instr.next(addr))
+                .is_some_and(|next| next < reached.len() && reached[next])
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        let program = read_program("sample1.txt");
+        let trace = run_until_loop(&mut CPU::from_program(&program));
+
+        assert_eq!(trace.loop_start, Some(1));
+        assert_eq!(trace.loop_steps(), &[(1, 1), (2, 1), (6, 2), (7, 2), (3, 5), (4, 5)]);
+        assert_eq!(trace.loop_listing(&program, Some(7)).lines().collect::<Vec<_>>(), vec![
+            "   1: acc +1   acc=1",
+            "   2: jmp +4   acc=1",
+            "   6: acc +1   acc=2",
+            "   7: jmp -4   acc=2  <- change to nop to terminate",
+            "   3: acc +3   acc=5",
+            "   4: jmp -3   acc=5  <- loops back to 1",
+        ]);
+    }
+
+    #[test]
+    fn test_reaches_end() {
+        let program = read_program("sample1.txt");
+        let reached = reaches_end(&program);
+
+        assert_eq!(reached, vec![false, false, false, false, false, false, false, false, true, true]);
+        assert_eq!(find_corruption(&program), Some(7));
+    }
+}
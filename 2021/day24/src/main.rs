@@ -6,38 +6,34 @@ use std::fs;
 use std::io;
 use std::path::Path;
 
+mod symbolic;
+
 fn main() {
     let input = Program::from_file("day24/input.txt").expect("failed to read input");
 
-    // I did this by hand-decoding the instructions constraints:
-    //
-    //   input_14 = input_1 + 8  - 11
-    //   input_13 = input_2 + 16 - 13
-    //   input_4  = input_3 + 4  - 11
-    //   input_12 = input_5 + 13 - 13
-    //   input_11 = input_6 + 5  - 11
-    //   input_8  = input_7 + 0  - 5
-    //   input_10 = input_9 + 7  + 0
-    //
-    // (Where input must be between 1 to 9)
-    //
-    // Therefore:
-    //
-    //   1: 4-9 ~ 14: 1-6
-    //   2: 1-6 ~ 13: 4-9
-    //   3: 8-9 ~  4: 1-2
-    //   5: 1-9 ~ 12: 1-9
-    //   6: 7-9 ~ 11: 1-3
-    //   7: 6-9 ~  8: 1-4
-    //   9: 1-2 ~ 10: 8-9
+    // Track the registers symbolically to find the constraints on the digits
+    let paths = symbolic::solve(&input);
+    for path in &paths {
+        println!("Constraints:");
+        for constraint in &path.constraints {
+            println!("  {}", constraint);
+        }
+        for (n, (lo, hi)) in path.bounds.iter().enumerate() {
+            println!("  d{}: {}-{}", n + 1, lo, hi);
+        }
+    }
 
     // Part 1
-    let part1 = [9, 6, 9, 2, 9, 9, 9, 4, 2, 9, 3, 9, 9, 6];
-    println!("Part 1: {:?} is {}", part1, if run(&input, part1) == 0 { "valid" } else { "invalid" });
+    let part1 = paths.iter().filter_map(|path| path.model_number(true)).max().expect("no valid model number");
+    println!("Part 1: {} is {}", model_number(part1), if run(&input, part1) == 0 { "valid" } else { "invalid" });
 
     // Part 2
-    let part2 = [4, 1, 8, 1, 1, 7, 6, 1, 1, 8, 1, 1, 4, 1];
-    println!("Part 2: {:?} is {}", part2, if run(&input, part2) == 0 { "valid" } else { "invalid" });
+    let part2 = paths.iter().filter_map(|path| path.model_number(false)).min().expect("no valid model number");
+    println!("Part 2: {} is {}", model_number(part2), if run(&input, part2) == 0 { "valid" } else { "invalid" });
+}
+
+fn model_number(digits: [i64; symbolic::DIGITS]) -> String {
+    digits.iter().map(|d| d.to_string()).collect()
 }
 
 fn run(input: &Program, stream: impl IntoIterator<Item=i64>) -> i64 {
@@ -0,0 +1,420 @@
+//! Symbolic execution of the ALU
+//!
+//! Each register is tracked as an expression over the input digits.
+//! Whenever an `eql` can't be decided from the bounds of its operands,
+//! execution forks into a path where they are equal and one where they aren't.
+
+use std::fmt;
+
+use crate::{Op, Operand, Program};
+
+/// Number of digits in a model number
+pub const DIGITS: usize = 14;
+
+/// Inclusive bounds of each input digit
+pub type Bounds = [(i64, i64); DIGITS];
+
+/// Expression over the input digits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    Digit(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    Divide(Box<Expr>, Box<Expr>),
+    Modulo(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Inclusive bounds of the value of this expression
+    pub fn bounds(&self, digits: &Bounds) -> (i64, i64) {
+        match self {
+            &Expr::Const(x) => (x, x),
+            &Expr::Digit(n) => digits[n],
+            Expr::Add(a, b) => {
+                let (a, b) = (a.bounds(digits), b.bounds(digits));
+                (a.0.saturating_add(b.0), a.1.saturating_add(b.1))
+            },
+            Expr::Multiply(a, b) => {
+                let (a, b) = (a.bounds(digits), b.bounds(digits));
+                let products = [a.0.saturating_mul(b.0), a.0.saturating_mul(b.1), a.1.saturating_mul(b.0), a.1.saturating_mul(b.1)];
+                (*products.iter().min().unwrap(), *products.iter().max().unwrap())
+            },
+            Expr::Divide(a, b) => match (a.bounds(digits), b.as_ref()) {
+                (a, &Expr::Const(k)) if k > 0 => (a.0 / k, a.1 / k),
+                _ => (i64::MIN, i64::MAX),
+            },
+            Expr::Modulo(a, b) => match (a.bounds(digits), b.as_ref()) {
+                (a, &Expr::Const(k)) if k > 0 && a.0 >= 0 && a.1 < k => a,
+                (_, &Expr::Const(k)) if k > 0 => (0, k - 1),
+                _ => (i64::MIN, i64::MAX),
+            },
+        }
+    }
+
+    /// Value of this expression for a model number
+    pub fn eval(&self, digits: &[i64]) -> i64 {
+        match self {
+            &Expr::Const(x) => x,
+            &Expr::Digit(n) => digits[n],
+            Expr::Add(a, b) => a.eval(digits) + b.eval(digits),
+            Expr::Multiply(a, b) => a.eval(digits) * b.eval(digits),
+            Expr::Divide(a, b) => a.eval(digits) / b.eval(digits),
+            Expr::Modulo(a, b) => a.eval(digits) % b.eval(digits),
+        }
+    }
+
+    /// Highest digit used by this expression
+    fn last_digit(&self) -> Option<usize> {
+        match self {
+            Expr::Const(_) => None,
+            &Expr::Digit(n) => Some(n),
+            Expr::Add(a, b) | Expr::Multiply(a, b) | Expr::Divide(a, b) | Expr::Modulo(a, b) => {
+                a.last_digit().max(b.last_digit())
+            },
+        }
+    }
+
+    /// Expressions of the form `digit + c`
+    fn linear(&self) -> Option<(usize, i64)> {
+        match self {
+            &Expr::Digit(n) => Some((n, 0)),
+            Expr::Add(a, b) => match (a.as_ref(), b.as_ref()) {
+                (&Expr::Digit(n), &Expr::Const(c)) => Some((n, c)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Const(_) | Expr::Digit(_) => 3,
+            Expr::Multiply(_, _) | Expr::Divide(_, _) | Expr::Modulo(_, _) => 2,
+            Expr::Add(_, _) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter<'_>, expr: &Expr, min_precedence: u8| {
+            if expr.precedence() < min_precedence {
+                write!(f, "({})", expr)
+            } else {
+                write!(f, "{}", expr)
+            }
+        };
+
+        let (a, b, op) = match self {
+            Expr::Const(x) => return write!(f, "{}", x),
+            Expr::Digit(n) => return write!(f, "d{}", n + 1),
+            Expr::Add(a, b) => match b.as_ref() {
+                &Expr::Const(x) if x < 0 => {
+                    operand(f, a, 1)?;
+                    return write!(f, " - {}", -x);
+                },
+                _ => (a, b, "+"),
+            },
+            Expr::Multiply(a, b) => (a, b, "*"),
+            Expr::Divide(a, b) => (a, b, "/"),
+            Expr::Modulo(a, b) => (a, b, "%"),
+        };
+
+        operand(f, a, self.precedence())?;
+        write!(f, " {} ", op)?;
+        operand(f, b, self.precedence() + 1)
+    }
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Const(x), Expr::Const(y)) => Expr::Const(x + y),
+        (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+        (Expr::Const(x), e) | (e, Expr::Const(x)) => match e {
+            Expr::Add(a, b) if matches!(*b, Expr::Const(_)) => add(*a, add(*b, Expr::Const(x))),
+            e => Expr::Add(Box::new(e), Box::new(Expr::Const(x))),
+        },
+        (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+    }
+}
+
+fn multiply(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Const(x), Expr::Const(y)) => Expr::Const(x * y),
+        (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+        (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+        (Expr::Const(x), e) => Expr::Multiply(Box::new(e), Box::new(Expr::Const(x))),
+        (a, b) => Expr::Multiply(Box::new(a), Box::new(b)),
+    }
+}
+
+/// Split `q * k + r` (where `0 <= r < k`) into `(q, r)`
+fn split(e: &Expr, k: i64, digits: &Bounds) -> Option<(Expr, Expr)> {
+    let quotient = |e: &Expr| match e {
+        Expr::Multiply(q, m) if **m == Expr::Const(k) && q.bounds(digits).0 >= 0 => Some(*q.clone()),
+        _ => None,
+    };
+
+    if let Some(q) = quotient(e) {
+        return Some((q, Expr::Const(0)));
+    }
+
+    match e {
+        Expr::Add(a, r) => {
+            let (lo, hi) = r.bounds(digits);
+            if lo < 0 || hi >= k {
+                return None;
+            }
+            quotient(a).map(|q| (q, *r.clone()))
+        },
+        _ => None,
+    }
+}
+
+fn divide(a: Expr, b: Expr, digits: &Bounds) -> Expr {
+    match (a, b) {
+        (Expr::Const(x), Expr::Const(y)) => Expr::Const(x / y),
+        (e, Expr::Const(1)) => e,
+        (e, Expr::Const(k)) if k > 0 => {
+            let (lo, hi) = e.bounds(digits);
+            if lo >= 0 && hi < k {
+                Expr::Const(0)
+            } else if let Some((q, _)) = split(&e, k, digits) {
+                q
+            } else {
+                Expr::Divide(Box::new(e), Box::new(Expr::Const(k)))
+            }
+        },
+        (a, b) => Expr::Divide(Box::new(a), Box::new(b)),
+    }
+}
+
+fn modulo(a: Expr, b: Expr, digits: &Bounds) -> Expr {
+    match (a, b) {
+        (Expr::Const(x), Expr::Const(y)) => Expr::Const(x % y),
+        (e, Expr::Const(k)) if k > 0 => {
+            let (lo, hi) = e.bounds(digits);
+            if lo >= 0 && hi < k {
+                e
+            } else if let Some((_, r)) = split(&e, k, digits) {
+                r
+            } else {
+                Expr::Modulo(Box::new(e), Box::new(Expr::Const(k)))
+            }
+        },
+        (a, b) => Expr::Modulo(Box::new(a), Box::new(b)),
+    }
+}
+
+/// Result of `eql a b`, if it can be decided
+fn equal(a: &Expr, b: &Expr, digits: &Bounds) -> Option<bool> {
+    if a == b {
+        return Some(true);
+    }
+
+    let (a, b) = (a.bounds(digits), b.bounds(digits));
+    if a.1 < b.0 || b.1 < a.0 {
+        Some(false)
+    } else if a.0 == a.1 && a == b {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Assumption made when forking on an `eql`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub a: Expr,
+    pub b: Expr,
+    pub equal: bool,
+}
+
+impl Constraint {
+    fn holds(&self, digits: &[i64]) -> bool {
+        (self.a.eval(digits) == self.b.eval(digits)) == self.equal
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.b, if self.equal { "==" } else { "!=" }, self.a)
+    }
+}
+
+/// A path through the program that ends with `z` being 0
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub constraints: Vec<Constraint>,
+    pub bounds: Bounds,
+}
+
+impl Path {
+    /// Largest (or smallest) model number that follows this path
+    pub fn model_number(&self, largest: bool) -> Option<[i64; DIGITS]> {
+        let mut digits = [0; DIGITS];
+        if self.search(&mut digits, 0, largest) { Some(digits) } else { None }
+    }
+
+    fn search(&self, digits: &mut [i64; DIGITS], n: usize, largest: bool) -> bool {
+        if n == DIGITS {
+            return true;
+        }
+
+        let (lo, hi) = self.bounds[n];
+        let values: Vec<_> = if largest { (lo..=hi).rev().collect() } else { (lo..=hi).collect() };
+        for value in values {
+            digits[n] = value;
+            let valid = self.constraints.iter()
+                .filter(|c| c.a.last_digit().max(c.b.last_digit()) == Some(n))
+                .all(|c| c.holds(digits.as_slice()));
+
+            if valid && self.search(digits, n + 1, largest) {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    pc: usize,
+    vars: [Expr; 4],
+    input: usize,
+    path: Path,
+}
+
+impl State {
+    fn value(&self, operand: &Operand) -> Expr {
+        match *operand {
+            Operand::Var(v) => self.vars[var_index(v)].clone(),
+            Operand::Number(x) => Expr::Const(x),
+        }
+    }
+
+    /// Add a constraint, narrowing the bounds of the digits (returns `false` if it can't be met)
+    fn assume(&mut self, a: &Expr, b: &Expr, equal: bool) -> bool {
+        if equal {
+            if let (Some((i, c1)), Some((j, c2))) = (a.linear(), b.linear()) {
+                // digit_j = digit_i + k
+                let k = c1 - c2;
+                if i == j {
+                    return k == 0;
+                }
+
+                let bounds = &mut self.path.bounds;
+                bounds[j] = (bounds[j].0.max(bounds[i].0 + k), bounds[j].1.min(bounds[i].1 + k));
+                bounds[i] = (bounds[i].0.max(bounds[j].0 - k), bounds[i].1.min(bounds[j].1 - k));
+                if bounds[i].0 > bounds[i].1 || bounds[j].0 > bounds[j].1 {
+                    return false;
+                }
+            }
+        }
+
+        self.path.constraints.push(Constraint { a: a.clone(), b: b.clone(), equal });
+        true
+    }
+}
+
+fn var_index(v: char) -> usize {
+    match v {
+        'w' => 0,
+        'x' => 1,
+        'y' => 2,
+        'z' => 3,
+        _ => panic!("Unknown variable: {}", v),
+    }
+}
+
+/// Find every path through the program where `z` is known to end up as 0
+pub fn solve(program: &Program) -> Vec<Path> {
+    let initial = State {
+        pc: 0,
+        vars: [Expr::Const(0), Expr::Const(0), Expr::Const(0), Expr::Const(0)],
+        input: 0,
+        path: Path { constraints: Vec::new(), bounds: [(1, 9); DIGITS] },
+    };
+
+    let mut paths = Vec::new();
+    let mut states = vec![initial];
+    'states: while let Some(mut state) = states.pop() {
+        while let Some(op) = program.instructions.get(state.pc) {
+            state.pc += 1;
+
+            let (a, b) = match op {
+                Op::Input(a) => {
+                    assert!(state.input < DIGITS, "too many inputs");
+                    state.vars[var_index(a.var().unwrap())] = Expr::Digit(state.input);
+                    state.input += 1;
+                    continue;
+                },
+                Op::Add(a, b) | Op::Multiply(a, b) | Op::Divide(a, b) | Op::Modulo(a, b) | Op::Equal(a, b) => (a, b),
+            };
+
+            let index = var_index(a.var().unwrap());
+            let (x, y) = (state.value(a), state.value(b));
+            let digits = &state.path.bounds;
+            state.vars[index] = match op {
+                Op::Add(_, _) => add(x, y),
+                Op::Multiply(_, _) => multiply(x, y),
+                Op::Divide(_, _) => divide(x, y, digits),
+                Op::Modulo(_, _) => modulo(x, y, digits),
+                Op::Equal(_, _) => match equal(&x, &y, digits) {
+                    Some(result) => Expr::Const(result as i64),
+                    None => {
+                        // Fork, continuing down the path where they're not equal later
+                        let mut other = state.clone();
+                        if other.assume(&x, &y, false) {
+                            other.vars[index] = Expr::Const(0);
+                            states.push(other);
+                        }
+
+                        if !state.assume(&x, &y, true) {
+                            continue 'states;
+                        }
+                        Expr::Const(1)
+                    },
+                },
+                Op::Input(_) => unreachable!(),
+            };
+        }
+
+        if state.vars[var_index('z')] == Expr::Const(0) {
+            paths.push(state.path);
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simplify() {
+        let digits = [(1, 9); DIGITS];
+        let push = |z: Expr, n: usize, c: i64| add(multiply(z, Expr::Const(26)), add(Expr::Digit(n), Expr::Const(c)));
+        let z = push(push(Expr::Const(0), 0, 8), 1, 16);
+        assert_eq!(z.to_string(), "(d1 + 8) * 26 + (d2 + 16)");
+
+        assert_eq!(modulo(z.clone(), Expr::Const(26), &digits), add(Expr::Digit(1), Expr::Const(16)));
+        assert_eq!(divide(z, Expr::Const(26), &digits), add(Expr::Digit(0), Expr::Const(8)));
+        assert_eq!(equal(&add(Expr::Digit(0), Expr::Const(10)), &Expr::Digit(1), &digits), Some(false));
+        assert_eq!(equal(&add(Expr::Digit(0), Expr::Const(-3)), &Expr::Digit(1), &digits), None);
+    }
+
+    #[test]
+    fn test_solve() {
+        let input = Program::from_file("input.txt").expect("failed to read input");
+        let paths = solve(&input);
+
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].constraints.len(), 7);
+        assert_eq!(paths[0].model_number(true), Some([9, 6, 9, 2, 9, 9, 9, 4, 2, 9, 3, 9, 9, 6]));
+        assert_eq!(paths[0].model_number(false), Some([4, 1, 8, 1, 1, 7, 6, 1, 1, 8, 1, 1, 4, 1]));
+    }
+}
@@ -2,17 +2,15 @@
 //! https://adventofcode.com/2024/day/10
 
 use std::{fs, io};
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::Path;
-use lib::vector::Vector;
+use lib::grid::{Grid, Pos};
 
-type Vec2 = Vector<i32, 2>;
-
-const UP: Vec2 = Vec2::new([0, -1]);
-const DOWN: Vec2 = Vec2::new([0, 1]);
-const LEFT: Vec2 = Vec2::new([-1, 0]);
-const RIGHT: Vec2 = Vec2::new([1, 0]);
+const UP: Pos = Pos::new([0, -1]);
+const DOWN: Pos = Pos::new([0, 1]);
+const LEFT: Pos = Pos::new([-1, 0]);
+const RIGHT: Pos = Pos::new([1, 0]);
 
 fn main() {
     let input = Input::from_file(format!("{}/input.txt", env!("CARGO_MANIFEST_DIR"))).expect("failed to read input");
@@ -27,20 +25,20 @@ fn main() {
 }
 
 fn part1(input: &Input) -> usize {
-    let trailheads: BTreeSet<Vec2> = input.heights.iter()
-        .filter_map(|(&p, &h)| (h == 0).then_some(p)).collect();
+    let trailheads: BTreeSet<Pos> = input.heights.iter()
+        .filter_map(|(p, &h)| (h == Some(0)).then_some(p)).collect();
 
     trailheads.into_iter().map(|head| score(head, &input.heights)).sum()
 }
 
-fn score(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
+fn score(trailhead: Pos, heights: &Grid<Option<u8>>) -> usize {
     let mut score = 0;
 
-    let mut visited: BTreeSet<Vec2> = [trailhead].into_iter().collect();
+    let mut visited: BTreeSet<Pos> = [trailhead].into_iter().collect();
     let mut edge = vec![trailhead];
 
     while let Some(pos) = edge.pop() {
-        let height = heights[&pos];
+        let height = heights[pos].expect("position has a height");
 
         if height == 9 {
             score += 1;
@@ -53,7 +51,7 @@ fn score(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
                 continue;
             }
 
-            if heights.get(&next_pos).copied() == Some(height + 1) {
+            if heights.get(next_pos).copied().flatten() == Some(height + 1) {
                 visited.insert(next_pos);
                 edge.push(next_pos);
             }
@@ -65,13 +63,13 @@ fn score(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
 
 
 fn part2(input: &Input) -> usize {
-    let trailheads: BTreeSet<Vec2> = input.heights.iter()
-        .filter_map(|(&p, &h)| (h == 0).then_some(p)).collect();
+    let trailheads: BTreeSet<Pos> = input.heights.iter()
+        .filter_map(|(p, &h)| (h == Some(0)).then_some(p)).collect();
 
     trailheads.into_iter().map(|head| rating(head, &input.heights)).sum()
 }
 
-fn rating(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
+fn rating(trailhead: Pos, heights: &Grid<Option<u8>>) -> usize {
     let mut rating = 0;
 
     let mut hasher = DefaultHasher::new();
@@ -82,7 +80,7 @@ fn rating(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
     let mut edge = vec![(trailhead, hasher)];
 
     while let Some((pos, hasher)) = edge.pop() {
-        let height = heights[&pos];
+        let height = heights[pos].expect("position has a height");
 
         if height == 9 {
             rating += 1;
@@ -102,7 +100,7 @@ fn rating(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
 
             visited.insert(hash);
 
-            if heights.get(&next_pos).copied() == Some(height + 1) {
+            if heights.get(next_pos).copied().flatten() == Some(height + 1) {
                 edge.push((next_pos, hasher));
             }
         }
@@ -113,27 +111,17 @@ fn rating(trailhead: Vec2, heights: &BTreeMap<Vec2, u8>) -> usize {
 
 #[derive(Debug, Clone)]
 struct Input {
-    heights: BTreeMap<Vec2, u8>,
+    /// Height of each position (or `None` if impassable)
+    heights: Grid<Option<u8>>,
 }
 
 impl Input {
     fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
         let input = fs::read_to_string(path)?;
-
-        let mut heights = BTreeMap::new();
-
-        for (x, line) in input.lines().enumerate() {
-            for (y, c) in line.trim().chars().enumerate() {
-                if c == '.' {
-                    continue;
-                }
-
-                let pos = Vec2::new([x as i32, y as i32]);
-                let height = c.to_digit(10).unwrap() as u8;
-
-                heights.insert(pos, height);
-            }
-        }
+        let heights = Grid::parse_with(&input, |c| match c {
+            '.' => Some(None),
+            c => c.to_digit(10).map(|h| Some(h as u8)),
+        })?;
 
         Ok(Self { heights })
     }
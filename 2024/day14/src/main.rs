@@ -68,14 +68,11 @@ fn part2(input: &Input, room_size: [i32; 2]) -> usize {
 
 fn simulate(robots: &[(Vec2, Vec2)], room_size: [i32; 2], condition: &dyn Fn(&[(Vec2, Vec2)]) -> bool, max_steps: usize) -> (usize, Vec<(Vec2, Vec2)>) {
     let mut robots= robots.to_vec();
+    let room_size = Vec2::from(room_size);
 
     for n in 0..max_steps {
         for robot in robots.iter_mut() {
-            robot.0 += robot.1;
-
-            for n in 0..2 {
-                robot.0[n] = robot.0[n].rem_euclid(room_size[n]);
-            }
+            robot.0 = (robot.0 + robot.1).rem_euclid(room_size);
         }
 
         if condition(&robots) {
@@ -2,10 +2,12 @@
 //! https://adventofcode.com/2024/day/16
 
 use std::{fs, io};
-use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::collections::HashSet;
 use std::path::Path;
 use std::str::FromStr;
+use lib::direction::Direction;
 use lib::grid::{Grid, Pos};
+use lib::search;
 
 const WALL: char = '#';
 const START: char = 'S';
@@ -24,132 +26,32 @@ fn main() {
 }
 
 fn find_start_end(grid: &Grid) -> (Pos, Pos) {
-    let mut start = Pos::default();
-    let mut end = Pos::default();
-
-    for pos in grid.positions() {
-        match grid[pos] {
-            START => start = pos,
-            END => end = pos,
-            _ => (),
-        }
-    }
+    let start = grid.find(START).expect("no start");
+    let end = grid.find(END).expect("no end");
 
     (start, end)
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-enum Direction {
-    East,
-    South,
-    West,
-    North,
-}
-
-impl Direction {
-    pub fn clockwise(self) -> Direction {
-        match self {
-            Direction::East => Direction::South,
-            Direction::South => Direction::West,
-            Direction::West => Direction::North,
-            Direction::North => Direction::East,
-        }
-    }
-
-    pub fn anticlockwise(self) -> Direction {
-        match self {
-            Direction::East => Direction::North,
-            Direction::North => Direction::West,
-            Direction::West => Direction::South,
-            Direction::South => Direction::East,
-        }
-    }
-}
-
-impl From<Direction> for Pos {
-    fn from(direction: Direction) -> Self {
-        match direction {
-            Direction::East => Pos::new([1, 0]),
-            Direction::South => Pos::new([0, 1]),
-            Direction::West => Pos::new([-1, 0]),
-            Direction::North => Pos::new([0, -1]),
-        }
-    }
+/// Step forward or turn 90° in place.
+fn successors(grid: &Grid, (pos, dir): (Pos, Direction)) -> impl Iterator<Item=((Pos, Direction), usize)> + '_ {
+    [((pos + dir.into(), dir), 1), ((pos, dir.clockwise()), 1000), ((pos, dir.anticlockwise()), 1000)].into_iter()
+        .filter(|&((pos, _), _)| grid[pos] != WALL)
 }
 
 fn part1(input: &Input) -> usize {
     let (start, end) = find_start_end(&input.grid);
 
-    let mut best: BTreeMap<(Pos, Direction), usize> = [((start, Direction::East), 0)].into_iter().collect();
-    let mut edge: BinaryHeap<(usize, (Pos, Direction), BTreeSet<Pos>)> = [(0, (start, Direction::East), BTreeSet::new())].into_iter().collect();
-
-    while let Some((_, cur, path)) = edge.pop() {
-        if cur.0 == end {
-            return best[&cur];
-        }
-
-        for next in [(cur.0 + cur.1.into(), cur.1), (cur.0, cur.1.clockwise()), (cur.0, cur.1.anticlockwise())] {
-            if input.grid[next.0] == WALL {
-                continue;
-            }
-
-            let new_score = best[&cur] + (next.1 != cur.1).then_some(1000).unwrap_or(1);
-            if best.contains_key(&next) && best[&next] < new_score {
-                continue;
-            }
-
-            let mut new_path: BTreeSet<Pos> = path.clone();
-            new_path.insert(next.0);
-
-            best.insert(next, new_score);
-            edge.push((usize::MAX - new_score, next, new_path));
-        }
-    }
-
-    0
+    search::dijkstra((start, Direction::East), |&state| successors(&input.grid, state), |&(pos, _)| pos == end)
+        .map(|(_, score)| score)
+        .unwrap_or(0)
 }
 
 fn part2(input: &Input) -> usize {
     let (start, end) = find_start_end(&input.grid);
 
-    let mut best: BTreeMap<(Pos, Direction), usize> = [((start, Direction::East), 0)].into_iter().collect();
-    let start_path: BTreeSet<Pos> = [start].into_iter().collect();
-    let mut edge: BinaryHeap<(usize, (Pos, Direction), BTreeSet<Pos>)> = [(0, (start, Direction::East), start_path)].into_iter().collect();
-
-    let mut best_score = None;
-    let mut all_path = BTreeSet::new();
-
-    while let Some((_, cur, path)) = edge.pop() {
-        if cur.0 == end {
-            all_path.extend(path.iter().copied());
-
-            if let Some(best_score) = best_score {
-                if best[&cur] > best_score {
-                    break;
-                }
-            }
-            best_score = Some(best[&cur]);
-        }
-
-        for next in [(cur.0 + cur.1.into(), cur.1), (cur.0, cur.1.clockwise()), (cur.0, cur.1.anticlockwise())] {
-            if input.grid[next.0] == WALL {
-                continue;
-            }
-
-            let new_score = best[&cur] + (next.1 != cur.1).then_some(1000).unwrap_or(1);
-            if best.contains_key(&next) && best[&next] < new_score {
-                continue;
-            }
-
-            let mut new_path: BTreeSet<Pos> = path.clone();
-            new_path.insert(next.0);
-
-            best.insert(next, new_score);
-            edge.push((usize::MAX - new_score, next, new_path));
-        }
-    }
-
-    all_path.len()
+    search::all_shortest_paths((start, Direction::East), |&state| successors(&input.grid, state), |&(pos, _)| pos == end)
+        .map(|paths| paths.states().into_iter().map(|(pos, _)| pos).collect::<HashSet<_>>().len())
+        .unwrap_or(0)
 }
 
 #[derive(Debug, Clone)]
@@ -2,9 +2,10 @@
 //! https://adventofcode.com/2024/day/18
 
 use std::{fs, io};
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
 use std::ops::Range;
 use std::path::Path;
+use lib::search;
 use lib::vector::Vector;
 
 type Vec2 = Vector<i32, 2>;
@@ -37,41 +38,14 @@ fn find_best_path(bounds: &[Range<i32>; 2], corrupted: &BTreeSet<Vec2>) -> Optio
     let start = Vec2::new([bounds[0].start, bounds[1].start]);
     let end = Vec2::new([bounds[0].end - 1, bounds[1].end - 1]);
 
-    let mut edge = vec![start];
-    let mut best: BTreeMap<Vec2, usize> = [(start, 0)].into_iter().collect();
+    let successors = |&pos: &Vec2| {
+        [UP, DOWN, LEFT, RIGHT].into_iter()
+            .map(move |dir| pos + dir)
+            .filter(|p| bounds[0].contains(&p[0]) && bounds[1].contains(&p[1]) && !corrupted.contains(p))
+            .map(|p| (p, 1))
+    };
 
-    while let Some(pos) = edge.pop() {
-        let cur_distance = best[&pos];
-
-        if pos == end {
-            return Some(cur_distance);
-        }
-
-        for dir in [UP, DOWN, LEFT, RIGHT] {
-            let new_pos = pos + dir;
-            if !bounds[0].contains(&new_pos[0]) || !bounds[1].contains(&new_pos[1]) {
-                continue;
-            }
-
-            if corrupted.contains(&new_pos) {
-                continue;
-            }
-
-            let new_distance = cur_distance + 1;
-            if let Some(&best_distance) = best.get(&new_pos) {
-                if best_distance <= new_distance {
-                    continue;
-                }
-            }
-
-            edge.push(new_pos);
-            best.insert(new_pos, new_distance);
-        }
-
-        edge.sort_by_key(|&p| usize::MAX - (best[&p] + distance(end, p)));
-    }
-
-    None
+    search::astar(start, successors, |&p| distance(end, p), |&p| p == end).map(|(_, distance)| distance)
 }
 
 fn distance(p2: Vec2, p1: Vec2) -> usize {
@@ -6,6 +6,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::str::FromStr;
 use lib::grid::{Grid, Pos};
+use lib::search;
 
 fn main() {
     //let input = Input::from_file(format!("{}/input.txt", env!("CARGO_MANIFEST_DIR"))).expect("failed to read input");
@@ -54,24 +55,13 @@ fn find_cheats(map: &Grid, max_cheat: usize) -> BTreeMap<usize, BTreeSet<(Pos, P
 }
 
 fn find_track(map: &Grid) -> (Vec<Pos>, BTreeMap<Pos, usize>) {
-    let start = map.positions().find(|&p| map[p] == 'S').unwrap();
-    let end = map.positions().find(|&p| map[p] == 'E').unwrap();
+    let start = map.find('S').unwrap();
+    let end = map.find('E').unwrap();
 
-    let mut track = vec![start];
-    let mut distance: BTreeMap<Pos, usize> = [(start, 0)].into_iter().collect();
-
-    let mut pos = start;
-    loop {
-        let cur_distance = distance[&pos];
-        pos = map.adjacent(pos).into_iter().find(|&p| map[p] != '#' && !distance.contains_key(&p)).unwrap();
-
-        track.push(pos);
-        distance.insert(pos, cur_distance + 1);
-
-        if pos == end {
-            break;
-        }
-    }
+    // There's only a single path through the track
+    let track = search::bfs(start, |&pos| map.adjacent(pos).into_iter().filter(|&p| map[p] != '#'), |&pos| pos == end)
+        .expect("no path to end");
+    let distance = track.iter().enumerate().map(|(n, &pos)| (pos, n)).collect();
 
     (track, distance)
 }
@@ -0,0 +1,223 @@
+//! Compass directions.
+//!
+//! Directions are in screen coordinates (as used by [`Grid`](crate::grid::Grid)),
+//! so north is towards `y = 0`.
+
+use crate::vector::Vector;
+
+/// One of the four cardinal directions.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    /// All directions, clockwise from north.
+    pub const ALL: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+    /// Iterate over all directions, clockwise from north.
+    pub fn iter() -> impl Iterator<Item=Direction> {
+        Self::ALL.into_iter()
+    }
+
+    /// Direction turned 90° clockwise.
+    pub fn clockwise(self) -> Direction {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+        }
+    }
+
+    /// Direction turned 90° anticlockwise.
+    pub fn anticlockwise(self) -> Direction {
+        match self {
+            Direction::North => Direction::West,
+            Direction::West => Direction::South,
+            Direction::South => Direction::East,
+            Direction::East => Direction::North,
+        }
+    }
+
+    /// Opposite direction.
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+
+    /// Unit vector pointing in this direction.
+    pub const fn vector(self) -> Vector<i64, 2> {
+        match self {
+            Direction::North => Vector::new([0, -1]),
+            Direction::East => Vector::new([1, 0]),
+            Direction::South => Vector::new([0, 1]),
+            Direction::West => Vector::new([-1, 0]),
+        }
+    }
+}
+
+/// Parses arrows (`^>v<`), compass points (`NESW`) or up/down/left/right (`UDLR`).
+impl TryFrom<char> for Direction {
+    type Error = char;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            '^' | 'N' | 'U' => Ok(Direction::North),
+            '>' | 'E' | 'R' => Ok(Direction::East),
+            'v' | 'S' | 'D' => Ok(Direction::South),
+            '<' | 'W' | 'L' => Ok(Direction::West),
+            c => Err(c),
+        }
+    }
+}
+
+impl From<Direction> for Vector<i64, 2> {
+    fn from(direction: Direction) -> Self {
+        direction.vector()
+    }
+}
+
+/// One of the four cardinal or four intercardinal directions.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Direction8 {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction8 {
+    /// All directions, clockwise from north.
+    pub const ALL: [Direction8; 8] = [
+        Direction8::North,
+        Direction8::NorthEast,
+        Direction8::East,
+        Direction8::SouthEast,
+        Direction8::South,
+        Direction8::SouthWest,
+        Direction8::West,
+        Direction8::NorthWest,
+    ];
+
+    /// Iterate over all directions, clockwise from north.
+    pub fn iter() -> impl Iterator<Item=Direction8> {
+        Self::ALL.into_iter()
+    }
+
+    /// Direction turned 45° clockwise.
+    pub fn clockwise(self) -> Direction8 {
+        Self::ALL[(self as usize + 1) % 8]
+    }
+
+    /// Direction turned 45° anticlockwise.
+    pub fn anticlockwise(self) -> Direction8 {
+        Self::ALL[(self as usize + 7) % 8]
+    }
+
+    /// Opposite direction.
+    pub fn reverse(self) -> Direction8 {
+        Self::ALL[(self as usize + 4) % 8]
+    }
+
+    /// Is this one of the four cardinal directions?
+    pub fn is_cardinal(self) -> bool {
+        (self as usize).is_multiple_of(2)
+    }
+
+    /// Vector pointing in this direction (diagonals have a component of 1 along each axis).
+    pub const fn vector(self) -> Vector<i64, 2> {
+        match self {
+            Direction8::North => Vector::new([0, -1]),
+            Direction8::NorthEast => Vector::new([1, -1]),
+            Direction8::East => Vector::new([1, 0]),
+            Direction8::SouthEast => Vector::new([1, 1]),
+            Direction8::South => Vector::new([0, 1]),
+            Direction8::SouthWest => Vector::new([-1, 1]),
+            Direction8::West => Vector::new([-1, 0]),
+            Direction8::NorthWest => Vector::new([-1, -1]),
+        }
+    }
+}
+
+impl From<Direction> for Direction8 {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::North => Direction8::North,
+            Direction::East => Direction8::East,
+            Direction::South => Direction8::South,
+            Direction::West => Direction8::West,
+        }
+    }
+}
+
+/// Only the cardinal directions can be converted.
+impl TryFrom<Direction8> for Direction {
+    type Error = Direction8;
+
+    fn try_from(direction: Direction8) -> Result<Self, Self::Error> {
+        match direction {
+            Direction8::North => Ok(Direction::North),
+            Direction8::East => Ok(Direction::East),
+            Direction8::South => Ok(Direction::South),
+            Direction8::West => Ok(Direction::West),
+            d => Err(d),
+        }
+    }
+}
+
+/// Parses the same characters as [`Direction`].
+impl TryFrom<char> for Direction8 {
+    type Error = char;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        Direction::try_from(c).map(Direction8::from)
+    }
+}
+
+impl From<Direction8> for Vector<i64, 2> {
+    fn from(direction: Direction8) -> Self {
+        direction.vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turns() {
+        for d in Direction::iter() {
+            assert_eq!(d.clockwise().anticlockwise(), d);
+            assert_eq!(d.clockwise().clockwise(), d.reverse());
+            assert_eq!(d.vector() + d.reverse().vector(), Vector::default());
+            assert_eq!(Direction8::from(d).clockwise().clockwise(), Direction8::from(d.clockwise()));
+        }
+
+        for d in Direction8::iter() {
+            assert_eq!(d.clockwise().anticlockwise(), d);
+            assert_eq!(d.vector() + d.reverse().vector(), Vector::default());
+            assert_eq!(Direction::try_from(d).is_ok(), d.is_cardinal());
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        for s in ["^>v<", "NESW", "URDL"] {
+            assert_eq!(s.chars().map(Direction::try_from).collect::<Result<Vec<_>, _>>(), Ok(Direction::ALL.to_vec()));
+        }
+        assert_eq!(Direction::try_from('x'), Err('x'));
+        assert_eq!(Direction8::try_from('<'), Ok(Direction8::West));
+    }
+}
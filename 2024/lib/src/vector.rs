@@ -1,6 +1,10 @@
 //! Vector type.
 
-use std::ops::{Add, AddAssign, Index, IndexMut, Neg, Sub, SubAssign};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign};
+use std::str::FromStr;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Vector<T: Copy + Default, const N: usize>([T; N]);
@@ -13,8 +17,126 @@ impl<T: Copy + Default, const N: usize> Vector<T, N> {
     pub const fn dimensions(self) -> usize {
         N
     }
+
+    /// Apply `f` to each component.
+    pub fn map<U: Copy + Default>(self, f: impl FnMut(T) -> U) -> Vector<U, N> {
+        Vector(self.0.map(f))
+    }
+
+    /// Combine the components of two vectors using `f`.
+    pub fn zip_with<U: Copy + Default, V: Copy + Default>(self, rhs: Vector<U, N>, mut f: impl FnMut(T, U) -> V) -> Vector<V, N> {
+        Vector(std::array::from_fn(|i| f(self.0[i], rhs.0[i])))
+    }
+}
+
+impl<T: Copy + Default + PartialOrd, const N: usize> Vector<T, N> {
+    /// Smallest value of each component.
+    pub fn component_min(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| if b < a { b } else { a })
+    }
+
+    /// Largest value of each component.
+    pub fn component_max(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| if b > a { b } else { a })
+    }
+}
+
+impl <T: Copy + Default + Add<Output=T> + Mul<Output=T> + Sum, const N: usize> Vector<T, N> {
+    pub fn magnitude_squared(&self) -> T {
+        self.0.iter().copied().map(|v| v * v).sum()
+    }
+
+    /// Dot (scalar) product.
+    pub fn dot(self, rhs: Self) -> T {
+        self.0.iter().zip(&rhs.0).map(|(&a, &b)| a * b).sum()
+    }
+}
+
+impl<T: Signed, const N: usize> Vector<T, N> {
+    /// Manhattan (taxicab) length: sum of the absolute components.
+    pub fn manhattan(self) -> T {
+        self.0.iter().map(|v| v.abs()).sum()
+    }
+
+    /// Chebyshev (chessboard) length: largest absolute component.
+    pub fn chebyshev(self) -> T {
+        self.0.iter().map(|v| v.abs()).fold(T::default(), |a, b| if b > a { b } else { a })
+    }
+
+    /// Sign (`-1`, `0` or `1`) of each component.
+    pub fn signum(self) -> Self {
+        self.map(T::signum)
+    }
+
+    /// Component-wise Euclidean remainder, for wrapping a position into `[0, rhs)`.
+    pub fn rem_euclid(self, rhs: Self) -> Self {
+        self.zip_with(rhs, T::rem_euclid)
+    }
+}
+
+impl<T: Copy + Default + Sub<Output=T> + Mul<Output=T>> Vector<T, 2> {
+    /// Z-component of the cross product of two vectors in the plane.
+    pub fn cross(self, rhs: Self) -> T {
+        self.0[0] * rhs.0[1] - self.0[1] * rhs.0[0]
+    }
+}
+
+impl<T: Copy + Default + Sub<Output=T> + Mul<Output=T>> Vector<T, 3> {
+    /// Cross (vector) product.
+    pub fn cross(self, rhs: Self) -> Self {
+        let [a1, a2, a3] = self.0;
+        let [b1, b2, b3] = rhs.0;
+
+        Vector([a2 * b3 - a3 * b2, a3 * b1 - a1 * b3, a1 * b2 - a2 * b1])
+    }
+}
+
+/// Rotations assume screen coordinates (`y` increases downwards), as used by `Grid`.
+impl<T: Copy + Default + Neg<Output=T>> Vector<T, 2> {
+    /// Rotate 90° clockwise.
+    pub fn rotate_cw(self) -> Self {
+        let [x, y] = self.0;
+
+        Vector([-y, x])
+    }
+
+    /// Rotate 90° anticlockwise.
+    pub fn rotate_ccw(self) -> Self {
+        let [x, y] = self.0;
+
+        Vector([y, -x])
+    }
 }
 
+/// Signed numbers, providing the operations that aren't covered by a `std::ops` trait.
+pub trait Signed: Copy + Default + PartialOrd + Sum + Neg<Output=Self> {
+    fn abs(self) -> Self;
+    fn signum(self) -> Self;
+    fn rem_euclid(self, rhs: Self) -> Self;
+}
+
+macro_rules! impl_signed {
+    ($($t:ty),*) => {
+        $(
+            impl Signed for $t {
+                fn abs(self) -> Self {
+                    <$t>::abs(self)
+                }
+
+                fn signum(self) -> Self {
+                    <$t>::signum(self)
+                }
+
+                fn rem_euclid(self, rhs: Self) -> Self {
+                    <$t>::rem_euclid(self, rhs)
+                }
+            }
+        )*
+    };
+}
+
+impl_signed!(i8, i16, i32, i64, i128, isize);
+
 impl<T: Copy + Default, const N: usize> Default for Vector<T, N> {
     fn default() -> Self {
         Vector([T::default(); N])
@@ -86,3 +208,165 @@ impl<T: Neg<Output=T> + Copy + Default, const N: usize> Neg for Vector<T, N> {
         self
     }
 }
+
+impl<T: Mul<Output=T> + Copy + Default, const N: usize> Mul<T> for Vector<T, N> {
+    type Output = Vector<T, N>;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        self.map(|v| v * rhs)
+    }
+}
+
+impl<T: Mul<Output=T> + Copy + Default, const N: usize> MulAssign<T> for Vector<T, N> {
+    fn mul_assign(&mut self, rhs: T) {
+        *self = *self * rhs;
+    }
+}
+
+impl<T: Div<Output=T> + Copy + Default, const N: usize> Div<T> for Vector<T, N> {
+    type Output = Vector<T, N>;
+
+    fn div(self, rhs: T) -> Self::Output {
+        self.map(|v| v / rhs)
+    }
+}
+
+impl<T: Div<Output=T> + Copy + Default, const N: usize> DivAssign<T> for Vector<T, N> {
+    fn div_assign(&mut self, rhs: T) {
+        *self = *self / rhs;
+    }
+}
+
+impl<T: Rem<Output=T> + Copy + Default, const N: usize> Rem<T> for Vector<T, N> {
+    type Output = Vector<T, N>;
+
+    fn rem(self, rhs: T) -> Self::Output {
+        self.map(|v| v % rhs)
+    }
+}
+
+impl<T: Rem<Output=T> + Copy + Default, const N: usize> RemAssign<T> for Vector<T, N> {
+    fn rem_assign(&mut self, rhs: T) {
+        *self = *self % rhs;
+    }
+}
+
+impl<T: Copy + Default, const N: usize> From<[T; N]> for Vector<T, N> {
+    fn from(vec: [T; N]) -> Self {
+        Vector(vec)
+    }
+}
+
+impl<T: Copy + Default, const N: usize> From<Vector<T, N>> for [T; N] {
+    fn from(vec: Vector<T, N>) -> Self {
+        vec.0
+    }
+}
+
+/// Formats as comma-separated components (e.g. `1,2,3`).
+impl<T: Display + Copy + Default, const N: usize> Display for Vector<T, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{v}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses comma-separated components (e.g. `1,2,3`), ignoring surrounding whitespace.
+impl<T: FromStr + Copy + Default, const N: usize> FromStr for Vector<T, N> {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components = s.trim().split(',')
+            .map(|v| v.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid component {v:?}"))))
+            .collect::<Result<Vec<T>, _>>()?;
+        let n = components.len();
+
+        components.try_into()
+            .map(Vector)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("expected {N} components, found {n}")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Vec2 = Vector<i64, 2>;
+    type Vec3 = Vector<i64, 3>;
+
+    #[test]
+    fn test_scalar() {
+        let v = Vec2::new([7, -3]);
+
+        assert_eq!(v * 2, Vec2::new([14, -6]));
+        assert_eq!(v / 2, Vec2::new([3, -1]));
+        assert_eq!(v % 2, Vec2::new([1, -1]));
+        assert_eq!(v.rem_euclid(Vec2::new([5, 5])), Vec2::new([2, 2]));
+
+        let mut u = v;
+        u *= 3;
+        u /= 2;
+        u %= 4;
+        assert_eq!(u, Vec2::new([2, 0]));
+    }
+
+    #[test]
+    fn test_products() {
+        let (x, y, z) = (Vec3::new([1, 0, 0]), Vec3::new([0, 1, 0]), Vec3::new([0, 0, 1]));
+
+        assert_eq!(x.cross(y), z);
+        assert_eq!(y.cross(x), -z);
+        assert_eq!(Vec3::new([1, 2, 3]).dot(Vec3::new([4, -5, 6])), 12);
+        assert_eq!(Vec2::new([2, 0]).cross(Vec2::new([0, 3])), 6);
+        assert_eq!(Vec3::new([1, 2, 3]).magnitude_squared(), 14);
+    }
+
+    #[test]
+    fn test_norms() {
+        let v = Vec3::new([3, -4, 1]);
+
+        assert_eq!(v.manhattan(), 8);
+        assert_eq!(v.chebyshev(), 4);
+        assert_eq!(v.signum(), Vec3::new([1, -1, 1]));
+        assert_eq!(Vec3::default().chebyshev(), 0);
+        assert_eq!(v.component_min(Vec3::new([0, 0, 0])), Vec3::new([0, -4, 0]));
+        assert_eq!(v.component_max(Vec3::new([0, 0, 0])), Vec3::new([3, 0, 1]));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let up = Vec2::new([0, -1]);
+
+        assert_eq!(up.rotate_cw(), Vec2::new([1, 0]));
+        assert_eq!(up.rotate_ccw(), Vec2::new([-1, 0]));
+        assert_eq!(up.rotate_cw().rotate_cw(), -up);
+        assert_eq!(up.rotate_cw().rotate_ccw(), up);
+    }
+
+    #[test]
+    fn test_map() {
+        let v = Vec2::new([1, 2]);
+
+        assert_eq!(v.map(|c| c as f64 / 2.0), Vector::new([0.5, 1.0]));
+        assert_eq!(v.zip_with(Vec2::new([3, 4]), |a, b| a * b), Vec2::new([3, 8]));
+        assert_eq!(Vec2::from([1, 2]), v);
+        assert_eq!(<[i64; 2]>::from(v), [1, 2]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let v: Vec3 = "162,817,-812".parse().unwrap();
+
+        assert_eq!(v, Vec3::new([162, 817, -812]));
+        assert_eq!(v.to_string(), "162,817,-812");
+        assert_eq!(" 1, 2 ".parse::<Vec2>().unwrap(), Vec2::new([1, 2]));
+        assert!("1,2".parse::<Vec3>().is_err());
+        assert!("1,x".parse::<Vec2>().is_err());
+    }
+}
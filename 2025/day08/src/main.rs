@@ -4,6 +4,7 @@
 use std::collections::BTreeSet;
 use std::{fs, io};
 use std::path::Path;
+use std::str::FromStr;
 
 use lib::vector::Vector;
 
@@ -95,15 +96,7 @@ struct Input {
 impl Input {
     fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
         let input = fs::read_to_string(path)?;
-        let values = input.lines().map(|line| {
-            let value: [i64; 3] = line.split(',')
-                .map(|val| val.parse().expect("should be valid integer"))
-                .collect::<Vec<_>>()
-                .try_into()
-                .expect("should be three values");
-
-            Vector3::new(value)
-        }).collect();
+        let values = input.lines().map(Vector3::from_str).collect::<io::Result<_>>()?;
 
         Ok(Self { values })
     }
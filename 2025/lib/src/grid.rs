@@ -1,138 +1,489 @@
-use std::collections::BTreeMap;
+//! Dense 2D grid.
+
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::ops::{Index, IndexMut, Range};
 use std::str::FromStr;
+use crate::direction::{Direction, Direction8};
 use crate::vector::Vector;
 
 pub type Pos = Vector<i64, 2>;
 
-const UP: Vector<i64, 2> = Vector::new([0, -1]);
-const DOWN: Vector<i64, 2> = Vector::new([0, 1]);
-const LEFT: Vector<i64, 2> = Vector::new([-1, 0]);
-const RIGHT: Vector<i64, 2> = Vector::new([1, 0]);
+/// Grid of characters.
+pub type CharGrid = Grid<char>;
 
-#[derive(Debug, Clone)]
-pub struct Grid {
-    data: BTreeMap<Pos, char>,
-    end: Pos,
+/// Grid of tiles, stored row-major.
+///
+/// Position `[x, y]` is column `x` of row `y`, with `[0, 0]` in the top left.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Grid<T = char> {
+    data: Vec<T>,
+    width: usize,
+    height: usize,
 }
 
-impl Grid {
-    pub fn x_range(&self) -> Range<i64> {
-        let width = self.end[0] + 1;
+impl<T> Grid<T> {
+    /// Create a grid from row-major tiles.
+    ///
+    /// Panics if the number of tiles isn't a multiple of `width`.
+    pub fn from_vec(width: usize, data: Vec<T>) -> Self {
+        let height = data.len().checked_div(width).unwrap_or(0);
+        assert_eq!(width * height, data.len(), "tiles do not fill grid");
 
-        0..width
+        Grid { data, width, height }
     }
 
-    pub fn y_range(&self) -> Range<i64> {
-        let height = self.end[1] + 1;
+    /// Parse a grid, converting each character to a tile with `f`.
+    ///
+    /// Returns an error if `f` returns `None` or the lines are not all the same length.
+    pub fn parse_with(s: &str, mut f: impl FnMut(char) -> Option<T>) -> io::Result<Self> {
+        let mut data = Vec::new();
+        let mut width = None;
+
+        for (y, line) in s.lines().map(str::trim).enumerate() {
+            let mut len = 0;
+            for (x, c) in line.chars().enumerate() {
+                let tile = f(c).ok_or_else(|| invalid_data(format!("invalid tile {c:?} at [{x}, {y}]")))?;
+                data.push(tile);
+                len += 1;
+            }
+
+            match width {
+                None => width = Some(len),
+                Some(width) if width != len => {
+                    return Err(invalid_data(format!("line {} has length {len}, expected {width}", y + 1)));
+                },
+                _ => (),
+            }
+        }
+
+        Ok(Self::from_vec(width.unwrap_or(0), data))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn x_range(&self) -> Range<i64> {
+        0..self.width as i64
+    }
 
-        0..height
+    pub fn y_range(&self) -> Range<i64> {
+        0..self.height as i64
     }
 
+    /// Every position in the grid, in row-major order.
     pub fn positions(&self) -> Positions {
-        Positions {
-            cur: Pos::new([0, 0]),
-            end: self.end,
-        }
+        Positions::new(self.x_range(), self.y_range())
+    }
+
+    /// Positions in the sub-rectangle `x` by `y` that are within the grid, in row-major order.
+    pub fn region(&self, x: Range<i64>, y: Range<i64>) -> Positions {
+        let (x_range, y_range) = (self.x_range(), self.y_range());
+
+        Positions::new(x.start.max(x_range.start)..x.end.min(x_range.end), y.start.max(y_range.start)..y.end.min(y_range.end))
     }
 
     pub fn adjacent4(&self, pos: Pos) -> Vec<Pos> {
-        [UP, DOWN, LEFT, RIGHT].into_iter()
-            .map(|d| pos + d)
+        Direction::iter()
+            .map(|d| pos + d.vector())
             .filter(|&p| self.valid(p))
             .collect()
     }
 
     pub fn adjacent8(&self, pos: Pos) -> Vec<Pos> {
-        [UP, DOWN, LEFT, RIGHT, UP + LEFT, UP + RIGHT, DOWN + LEFT, DOWN + RIGHT].into_iter()
-            .map(|d| pos + d)
+        Direction8::iter()
+            .map(|d| pos + d.vector())
             .filter(|&p| self.valid(p))
             .collect()
     }
 
     pub fn valid(&self, pos: Pos) -> bool {
-        self.x_range().contains(&pos[0]) && self.y_range().contains(&pos[1])
+        self.offset(pos).is_some()
     }
-}
 
-impl Display for Grid {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for y in 0..=self.end[1] {
-            for x in 0..=self.end[0] {
-                let pos = Pos::new([x, y]);
-                write!(f, "{}", *self.data.get(&pos).unwrap_or(&' '))?;
-            }
-            write!(f, "\n")?;
+    pub fn get(&self, pos: Pos) -> Option<&T> {
+        self.offset(pos).map(|i| &self.data[i])
+    }
+
+    pub fn get_mut(&mut self, pos: Pos) -> Option<&mut T> {
+        self.offset(pos).map(|i| &mut self.data[i])
+    }
+
+    /// Tiles with their positions, in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item=(Pos, &T)> {
+        let width = self.width;
+        self.data.iter().enumerate().map(move |(i, t)| (position(i, width), t))
+    }
+
+    /// Tiles at `positions` (skipping any outside the grid).
+    pub fn tiles(&self, positions: Positions) -> impl Iterator<Item=(Pos, &T)> {
+        positions.filter_map(move |pos| self.get(pos).map(|t| (pos, t)))
+    }
+
+    /// Mutable tiles with their positions, in row-major order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(Pos, &mut T)> {
+        let width = self.width;
+        self.data.iter_mut().enumerate().map(move |(i, t)| (position(i, width), t))
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item=&[T]> {
+        // `chunks` panics on a zero-width grid
+        self.data.chunks(self.width.max(1))
+    }
+
+    pub fn column(&self, x: usize) -> impl Iterator<Item=&T> {
+        assert!(x < self.width, "column out of bounds");
+        self.data.iter().skip(x).step_by(self.width)
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item=impl Iterator<Item=&T>> {
+        (0..self.width).map(move |x| self.column(x))
+    }
+
+    /// Orthogonally adjacent tiles that are within the grid, clockwise from north.
+    pub fn neighbours(&self, pos: Pos) -> impl Iterator<Item=(Pos, &T)> {
+        Direction::iter()
+            .map(move |d| pos + d.vector())
+            .filter_map(|p| self.get(p).map(|t| (p, t)))
+    }
+
+    /// Orthogonally and diagonally adjacent tiles that are within the grid, clockwise from north.
+    pub fn neighbours8(&self, pos: Pos) -> impl Iterator<Item=(Pos, &T)> {
+        Direction8::iter()
+            .map(move |d| pos + d.vector())
+            .filter_map(|p| self.get(p).map(|t| (p, t)))
+    }
+
+    /// Position of the first tile (in row-major order) that matches `predicate`.
+    pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<Pos> {
+        self.data.iter().position(predicate).map(|i| position(i, self.width))
+    }
+
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid { data: self.data.iter().map(f).collect(), width: self.width, height: self.height }
+    }
+
+    fn offset(&self, pos: Pos) -> Option<usize> {
+        let (x, y) = (pos[0], pos[1]);
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
         }
 
-        Ok(())
+        Some(y as usize * self.width + x as usize)
+    }
+
+    /// Build a grid of the given size, taking each tile from `f(x, y)`.
+    fn build(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let data = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| f(x, y)).collect();
+
+        Grid { data, width, height }
     }
 }
 
-impl FromStr for Grid {
-    type Err = io::Error;
+impl<T: PartialEq> Grid<T> {
+    /// Position of the first `tile` (in row-major order).
+    pub fn find(&self, tile: T) -> Option<Pos> {
+        self.position(|t| *t == tile)
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut data = BTreeMap::new();
+    /// Positions of every `tile` (in row-major order).
+    pub fn find_all(&self, tile: T) -> impl Iterator<Item=Pos> + '_ {
+        self.iter().filter_map(move |(pos, t)| (*t == tile).then_some(pos))
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: usize, height: usize, tile: T) -> Self {
+        Grid { data: vec![tile; width * height], width, height }
+    }
+
+    /// Swap rows and columns.
+    pub fn transpose(&self) -> Self {
+        Self::build(self.height, self.width, |x, y| self.data[x * self.width + y].clone())
+    }
+
+    /// Rotate 90° clockwise.
+    pub fn rotate_cw(&self) -> Self {
+        Self::build(self.height, self.width, |x, y| self.data[(self.height - 1 - x) * self.width + y].clone())
+    }
+
+    /// Rotate 90° counter-clockwise.
+    pub fn rotate_ccw(&self) -> Self {
+        Self::build(self.height, self.width, |x, y| self.data[x * self.width + (self.width - 1 - y)].clone())
+    }
+
+    /// Mirror left to right.
+    pub fn flip_horizontal(&self) -> Self {
+        Self::build(self.width, self.height, |x, y| self.data[y * self.width + (self.width - 1 - x)].clone())
+    }
 
-        let mut max_x = 0;
-        let mut max_y = 0;
+    /// Mirror top to bottom.
+    pub fn flip_vertical(&self) -> Self {
+        Self::build(self.width, self.height, |x, y| self.data[(self.height - 1 - y) * self.width + x].clone())
+    }
+}
 
-        for (y, line) in s.lines().enumerate() {
-            for (x, c) in line.trim().chars().enumerate() {
-                let pos = Pos::new([x as i64, y as i64]);
+fn position(i: usize, width: usize) -> Pos {
+    Pos::new([(i % width) as i64, (i / width) as i64])
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
 
-                data.insert(pos, c);
-                max_x = max_x.max(x as i64);
+impl<T: Display> Display for Grid<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for row in self.rows() {
+            for tile in row {
+                write!(f, "{tile}")?;
             }
-            max_y = max_y.max(y as i64);
+            writeln!(f)?;
         }
 
-        let end = Pos::new([max_x, max_y]);
+        Ok(())
+    }
+}
+
+/// Parses each character into a tile using `TryFrom<char>`.
+impl<T: TryFrom<char>> FromStr for Grid<T> {
+    type Err = io::Error;
 
-        Ok(Grid { data, end })
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s, |c| T::try_from(c).ok())
     }
 }
 
-impl Index<Pos> for Grid {
-    type Output = char;
+impl<T> Index<Pos> for Grid<T> {
+    type Output = T;
 
     fn index(&self, index: Pos) -> &Self::Output {
-        self.data.get(&index).unwrap()
+        self.get(index).unwrap_or_else(|| panic!("position {index:?} out of bounds"))
     }
 }
 
-impl IndexMut<Pos> for Grid {
+impl<T> IndexMut<Pos> for Grid<T> {
     fn index_mut(&mut self, index: Pos) -> &mut Self::Output {
-        self.data.get_mut(&index).unwrap()
+        self.get_mut(index).unwrap_or_else(|| panic!("position {index:?} out of bounds"))
     }
 }
 
+/// Order positions are visited in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Order {
+    /// Left to right, then top to bottom
+    RowMajor,
+    /// Top to bottom, then left to right
+    ColumnMajor,
+}
+
+/// Iterator over the positions in a rectangle.
+#[derive(Debug, Clone)]
 pub struct Positions {
-    cur: Pos,
-    end: Pos,
+    x: Range<i64>,
+    y: Range<i64>,
+    order: Order,
+    /// Indexes (in `order`) of the next positions from the front and back
+    front: usize,
+    back: usize,
+}
+
+impl Positions {
+    /// Positions in the rectangle `x` by `y`, in row-major order.
+    pub fn new(x: Range<i64>, y: Range<i64>) -> Self {
+        let len = range_len(&x) * range_len(&y);
+
+        Positions { x, y, order: Order::RowMajor, front: 0, back: len }
+    }
+
+    /// Visit positions in column-major order instead.
+    pub fn column_major(self) -> Self {
+        self.with_order(Order::ColumnMajor)
+    }
+
+    /// Visit positions in row-major order instead.
+    pub fn row_major(self) -> Self {
+        self.with_order(Order::RowMajor)
+    }
+
+    /// Visit positions in `order` (restarting the iterator).
+    pub fn with_order(self, order: Order) -> Self {
+        Positions { order, ..Positions::new(self.x, self.y) }
+    }
+
+    pub fn order(&self) -> Order {
+        self.order
+    }
+
+    fn at(&self, i: usize) -> Pos {
+        let (width, height) = (range_len(&self.x), range_len(&self.y));
+        let (dx, dy) = match self.order {
+            Order::RowMajor => (i % width, i / width),
+            Order::ColumnMajor => (i / height, i % height),
+        };
+
+        Pos::new([self.x.start + dx as i64, self.y.start + dy as i64])
+    }
+}
+
+fn range_len(range: &Range<i64>) -> usize {
+    (range.end - range.start).max(0) as usize
 }
 
 impl Iterator for Positions {
     type Item = Pos;
 
     fn next(&mut self) -> Option<Self::Item> {
-        panic!("FIXME: skips [0][0]");
+        if self.front >= self.back {
+            return None;
+        }
+
+        self.front += 1;
+        Some(self.at(self.front - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
 
-        if self.cur[0] < self.end[0] {
-            self.cur[0] += 1;
-            return Some(self.cur);
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for Positions {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
         }
 
-        self.cur[0] = 0;
+        self.back -= 1;
+        Some(self.at(self.back))
+    }
+}
+
+impl ExactSizeIterator for Positions {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    enum Tile {
+        Open,
+        Wall,
+    }
+
+    impl TryFrom<char> for Tile {
+        type Error = char;
 
-        if self.cur[1] < self.end[1] {
-            self.cur[1] += 1;
-            return Some(self.cur);
+        fn try_from(c: char) -> Result<Self, Self::Error> {
+            match c {
+                '.' => Ok(Tile::Open),
+                '#' => Ok(Tile::Wall),
+                c => Err(c),
+            }
         }
+    }
+
+    #[test]
+    fn test_parse() {
+        let grid = CharGrid::from_str("abc\ndef\n").unwrap();
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid[Pos::new([1, 1])], 'e');
+        assert_eq!(grid.get(Pos::new([3, 0])), None);
+        assert_eq!(grid.get(Pos::new([0, -1])), None);
+        assert_eq!(grid.to_string(), "abc\ndef\n");
+
+        assert!(CharGrid::from_str("abc\nde\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_enum() {
+        let grid: Grid<Tile> = "#.\n.#".parse().unwrap();
+        assert_eq!(grid.row(0), &[Tile::Wall, Tile::Open]);
+        assert_eq!(grid.find(Tile::Open), Some(Pos::new([1, 0])));
+        assert_eq!(grid.find_all(Tile::Wall).collect::<Vec<_>>(), [Pos::new([0, 0]), Pos::new([1, 1])]);
+
+        assert!("#x".parse::<Grid<Tile>>().is_err());
+    }
 
-        None
+    #[test]
+    fn test_iterators() {
+        let grid = CharGrid::from_str("abc\ndef\n").unwrap();
+        assert_eq!(grid.rows().map(|r| r.iter().collect::<String>()).collect::<Vec<_>>(), ["abc", "def"]);
+        assert_eq!(grid.columns().map(|c| c.collect::<String>()).collect::<Vec<_>>(), ["ad", "be", "cf"]);
+        assert_eq!(grid.neighbours(Pos::new([0, 0])).map(|(_, &c)| c).collect::<String>(), "bd");
+        assert_eq!(grid.neighbours8(Pos::new([0, 0])).map(|(_, &c)| c).collect::<String>(), "bed");
+        assert_eq!(grid.iter().last(), Some((Pos::new([2, 1]), &'f')));
+    }
+
+    #[test]
+    fn test_transform() {
+        let grid = CharGrid::from_str("abc\ndef\n").unwrap();
+        assert_eq!(grid.transpose().to_string(), "ad\nbe\ncf\n");
+        assert_eq!(grid.rotate_cw().to_string(), "da\neb\nfc\n");
+        assert_eq!(grid.rotate_ccw().to_string(), "cf\nbe\nad\n");
+        assert_eq!(grid.flip_horizontal().to_string(), "cba\nfed\n");
+        assert_eq!(grid.flip_vertical().to_string(), "def\nabc\n");
+        assert_eq!(grid.rotate_cw().rotate_cw(), grid.flip_horizontal().flip_vertical());
+    }
+
+    #[test]
+    fn test_positions() {
+        let grid = CharGrid::from_str("abc\ndef\n").unwrap();
+        let p = |x, y| Pos::new([x, y]);
+
+        assert_eq!(grid.positions().collect::<Vec<_>>(), [p(0, 0), p(1, 0), p(2, 0), p(0, 1), p(1, 1), p(2, 1)]);
+        assert_eq!(grid.positions().column_major().collect::<Vec<_>>(), [p(0, 0), p(0, 1), p(1, 0), p(1, 1), p(2, 0), p(2, 1)]);
+        assert_eq!(grid.positions().next_back(), Some(p(2, 1)));
+        assert_eq!(grid.region(1..5, -1..1).collect::<Vec<_>>(), [p(1, 0), p(2, 0)]);
+        assert_eq!(grid.tiles(grid.region(1..3, 0..2).column_major()).map(|(_, &c)| c).collect::<String>(), "becf");
+
+        assert_eq!(CharGrid::from_str("abc").unwrap().positions().len(), 3);
+        assert_eq!(CharGrid::from_str("a\nb\nc").unwrap().positions().column_major().len(), 3);
+        assert_eq!(CharGrid::from_str("").unwrap().positions().next(), None);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_positions_visit_every_cell_once(width in 0..12usize, height in 0..12usize, column_major: bool) {
+            let grid = Grid::new(width, height, 0u8);
+            let positions = if column_major { grid.positions().column_major() } else { grid.positions() };
+            proptest::prop_assert_eq!(positions.len(), width * height);
+
+            let mut visited = Grid::new(width, height, 0);
+            for pos in positions {
+                visited[pos] += 1;
+            }
+            proptest::prop_assert!(visited.iter().all(|(_, &n)| n == 1));
+        }
+
+        #[test]
+        fn prop_iter_matches_positions(width in 0..12usize, height in 0..12usize) {
+            let grid = Grid::from_vec(width, (0..width * height).collect());
+            let positions: Vec<_> = grid.positions().collect();
+
+            proptest::prop_assert_eq!(grid.iter().map(|(pos, _)| pos).collect::<Vec<_>>(), positions.clone());
+            proptest::prop_assert!(grid.iter().all(|(pos, &t)| grid[pos] == t));
+            proptest::prop_assert_eq!(grid.positions().rev().collect::<Vec<_>>(), positions.into_iter().rev().collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn prop_region_is_clipped(width in 0..8usize, height in 0..8usize, x in -4..12i64, y in -4..12i64, w in 0..8i64, h in 0..8i64) {
+            let grid = Grid::new(width, height, ());
+            let region: Vec<_> = grid.region(x..x + w, y..y + h).collect();
+            let expected: Vec<_> = grid.positions()
+                .filter(|p| (x..x + w).contains(&p[0]) && (y..y + h).contains(&p[1]))
+                .collect();
+
+            proptest::prop_assert_eq!(region, expected);
+        }
     }
 }
@@ -0,0 +1,353 @@
+//! Graph search.
+//!
+//! Searches are generic over a state type `S` and a successor function
+//! that returns the neighbouring states (along with the cost of moving to them for weighted searches).
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Breadth-first search for the path with the fewest steps from `start` to a state matching `goal`.
+///
+/// The returned path includes both `start` and the goal.
+pub fn bfs<S, I>(start: S, mut successors: impl FnMut(&S) -> I, mut goal: impl FnMut(&S) -> bool) -> Option<Vec<S>>
+where
+    S: Clone + Eq + Hash,
+    I: IntoIterator<Item=S>,
+{
+    let mut predecessor: HashMap<S, S> = HashMap::new();
+    let mut seen: HashSet<S> = [start.clone()].into_iter().collect();
+    let mut queue: VecDeque<S> = [start].into_iter().collect();
+
+    while let Some(cur) = queue.pop_front() {
+        if goal(&cur) {
+            let mut path = vec![cur];
+            while let Some(prev) = predecessor.get(path.last().unwrap()) {
+                path.push(prev.clone());
+            }
+            path.reverse();
+
+            return Some(path);
+        }
+
+        for next in successors(&cur) {
+            if seen.insert(next.clone()) {
+                predecessor.insert(next.clone(), cur.clone());
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+/// Number of steps from `start` to every reachable state.
+pub fn bfs_distances<S, I>(start: S, mut successors: impl FnMut(&S) -> I) -> HashMap<S, usize>
+where
+    S: Clone + Eq + Hash,
+    I: IntoIterator<Item=S>,
+{
+    let mut distance: HashMap<S, usize> = [(start.clone(), 0)].into_iter().collect();
+    let mut queue: VecDeque<S> = [start].into_iter().collect();
+
+    while let Some(cur) = queue.pop_front() {
+        let steps = distance[&cur] + 1;
+        for next in successors(&cur) {
+            distance.entry(next.clone()).or_insert_with(|| {
+                queue.push_back(next);
+                steps
+            });
+        }
+    }
+
+    distance
+}
+
+/// Dijkstra's algorithm for the cheapest path from `start` to a state matching `goal`.
+///
+/// Returns the path (including `start` and the goal) and its total cost.
+pub fn dijkstra<S, I>(start: S, successors: impl FnMut(&S) -> I, goal: impl FnMut(&S) -> bool) -> Option<(Vec<S>, usize)>
+where
+    S: Clone + Eq + Hash,
+    I: IntoIterator<Item=(S, usize)>,
+{
+    astar(start, successors, |_| 0, goal)
+}
+
+/// A* search for the cheapest path from `start` to a state matching `goal`.
+///
+/// `heuristic` must never overestimate the remaining cost to reach a goal.
+/// Returns the path (including `start` and the goal) and its total cost.
+pub fn astar<S, I>(start: S, successors: impl FnMut(&S) -> I, heuristic: impl FnMut(&S) -> usize, goal: impl FnMut(&S) -> bool) -> Option<(Vec<S>, usize)>
+where
+    S: Clone + Eq + Hash,
+    I: IntoIterator<Item=(S, usize)>,
+{
+    let mut search = Search::new(start, heuristic);
+    let end = search.run(successors, goal, false).into_iter().next()?;
+    let cost = search.tree.distance[&end];
+
+    Some((search.tree.path(end)?, cost))
+}
+
+/// Find every cheapest path from `start` to a state matching `goal`.
+pub fn all_shortest_paths<S, I>(start: S, successors: impl FnMut(&S) -> I, goal: impl FnMut(&S) -> bool) -> Option<ShortestPaths<S>>
+where
+    S: Clone + Eq + Hash,
+    I: IntoIterator<Item=(S, usize)>,
+{
+    let mut search = Search::new(start, |_| 0);
+    let goals = search.run(successors, goal, true);
+    let cost = *search.tree.distance.get(goals.first()?)?;
+
+    Some(ShortestPaths { cost, goals, tree: search.tree })
+}
+
+/// Cheapest paths from `start` to every reachable state.
+pub fn shortest_path_tree<S, I>(start: S, successors: impl FnMut(&S) -> I) -> SearchTree<S>
+where
+    S: Clone + Eq + Hash,
+    I: IntoIterator<Item=(S, usize)>,
+{
+    let mut search = Search::new(start, |_| 0);
+    search.run(successors, |_| false, false);
+
+    search.tree
+}
+
+/// States explored by a weighted search.
+#[derive(Debug, Clone)]
+pub struct SearchTree<S> {
+    /// Cost of the cheapest known path to each state
+    pub distance: HashMap<S, usize>,
+    /// States immediately before each state on any of its cheapest paths
+    pub predecessors: HashMap<S, Vec<S>>,
+}
+
+impl<S: Clone + Eq + Hash> SearchTree<S> {
+    /// A cheapest path from the start to `end` (including both).
+    pub fn path(&self, end: S) -> Option<Vec<S>> {
+        if !self.distance.contains_key(&end) {
+            return None;
+        }
+
+        let mut path = vec![end];
+        while let Some(prev) = self.predecessors.get(path.last().unwrap()).and_then(|p| p.first()) {
+            path.push(prev.clone());
+        }
+        path.reverse();
+
+        Some(path)
+    }
+
+    /// Every state on any cheapest path from the start to one of `ends` (including the start and ends).
+    pub fn ancestors(&self, ends: impl IntoIterator<Item=S>) -> HashSet<S> {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<S> = ends.into_iter().filter(|s| self.distance.contains_key(s)).collect();
+
+        while let Some(cur) = stack.pop() {
+            if !seen.insert(cur.clone()) {
+                continue;
+            }
+
+            stack.extend(self.predecessors.get(&cur).into_iter().flatten().cloned());
+        }
+
+        seen
+    }
+}
+
+/// Result of [`all_shortest_paths`].
+#[derive(Debug, Clone)]
+pub struct ShortestPaths<S> {
+    /// Cost of the cheapest path
+    pub cost: usize,
+    /// Goal states that can be reached with that cost
+    pub goals: Vec<S>,
+    /// Predecessor DAG of all cheapest paths
+    pub tree: SearchTree<S>,
+}
+
+impl<S: Clone + Eq + Hash> ShortestPaths<S> {
+    /// Every state that lies on any of the cheapest paths.
+    pub fn states(&self) -> HashSet<S> {
+        self.tree.ancestors(self.goals.iter().cloned())
+    }
+}
+
+/// Priority queue entry, ordered so the lowest estimated total cost is popped first.
+struct Entry<S> {
+    estimate: usize,
+    cost: usize,
+    state: S,
+}
+
+impl<S> PartialEq for Entry<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<S> Eq for Entry<S> {}
+
+impl<S> PartialOrd for Entry<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S> Ord for Entry<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Prefer deeper entries when tied, since they're likely closer to the goal
+        other.estimate.cmp(&self.estimate).then(self.cost.cmp(&other.cost))
+    }
+}
+
+/// Shared implementation of Dijkstra/A*.
+struct Search<S, H> {
+    tree: SearchTree<S>,
+    heuristic: H,
+    queue: BinaryHeap<Entry<S>>,
+}
+
+impl<S: Clone + Eq + Hash, H: FnMut(&S) -> usize> Search<S, H> {
+    fn new(start: S, mut heuristic: H) -> Self {
+        let estimate = heuristic(&start);
+        let tree = SearchTree {
+            distance: [(start.clone(), 0)].into_iter().collect(),
+            predecessors: HashMap::new(),
+        };
+        let queue = [Entry { estimate, cost: 0, state: start }].into_iter().collect();
+
+        Search { tree, heuristic, queue }
+    }
+
+    /// Explore states in order of cost until reaching a goal.
+    ///
+    /// If `all` is set, keep going until every goal with the same cost has been found.
+    fn run<I>(&mut self, mut successors: impl FnMut(&S) -> I, mut goal: impl FnMut(&S) -> bool, all: bool) -> Vec<S>
+    where
+        I: IntoIterator<Item=(S, usize)>,
+    {
+        let mut goals = Vec::new();
+        let mut best = None;
+
+        while let Some(Entry { cost, state, .. }) = self.queue.pop() {
+            if cost > self.tree.distance[&state] {
+                // Stale entry
+                continue;
+            }
+
+            if best.is_some_and(|best| cost > best) {
+                break;
+            }
+
+            if goal(&state) {
+                best = Some(cost);
+                goals.push(state.clone());
+                if !all {
+                    break;
+                }
+            }
+
+            for (next, step) in successors(&state) {
+                let new_cost = cost + step;
+                match self.tree.distance.get(&next).map(|&d| new_cost.cmp(&d)) {
+                    Some(Ordering::Greater) => continue,
+                    Some(Ordering::Equal) => {
+                        // Only the start has no predecessors, and a zero-cost cycle back to it mustn't add any
+                        if let Some(predecessors) = self.tree.predecessors.get_mut(&next) {
+                            predecessors.push(state.clone());
+                        }
+                        continue;
+                    },
+                    Some(Ordering::Less) | None => (),
+                }
+
+                let estimate = new_cost + (self.heuristic)(&next);
+                self.tree.distance.insert(next.clone(), new_cost);
+                self.tree.predecessors.insert(next.clone(), vec![state.clone()]);
+                self.queue.push(Entry { estimate, cost: new_cost, state: next });
+            }
+        }
+
+        goals
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Grid where `#` is a wall, returning the neighbours of each open tile.
+    fn successors(grid: &'static [&'static str]) -> impl Fn(&(i64, i64)) -> Vec<(i64, i64)> {
+        move |&(x, y)| {
+            [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)].into_iter()
+                .filter(|&(x, y)| {
+                    usize::try_from(y).ok().and_then(|y| grid.get(y))
+                        .and_then(|row| usize::try_from(x).ok().and_then(|x| row.as_bytes().get(x)))
+                        .is_some_and(|&b| b != b'#')
+                })
+                .collect()
+        }
+    }
+
+    const MAZE: [&str; 4] = [
+        "....",
+        ".##.",
+        ".#..",
+        "...#",
+    ];
+
+    #[test]
+    fn test_bfs() {
+        let successors = successors(&MAZE);
+
+        let path = bfs((0, 0), &successors, |&p| p == (2, 2)).unwrap();
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(2, 2)));
+        assert_eq!(path.len(), 7);
+        assert_eq!(bfs((0, 0), &successors, |&p| p == (1, 1)), None);
+
+        let distance = bfs_distances((0, 0), &successors);
+        assert_eq!(distance.len(), 12);
+        assert_eq!(distance[&(2, 2)], 6);
+        assert_eq!(distance[&(2, 3)], 5);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let successors = successors(&MAZE);
+        let weighted = |p: &(i64, i64)| successors(p).into_iter().map(|n| (n, 1)).collect::<Vec<_>>();
+        let manhattan = |&(x, y): &(i64, i64)| ((x - 2).abs() + (y - 2).abs()) as usize;
+
+        let (path, cost) = dijkstra((0, 0), weighted, |&p| p == (2, 2)).unwrap();
+        assert_eq!((path.len(), cost), (7, 6));
+        assert_eq!(astar((0, 0), weighted, manhattan, |&p| p == (2, 2)).map(|(_, cost)| cost), Some(6));
+
+        // Both ways around the wall are equally short
+        let paths = all_shortest_paths((0, 0), weighted, |&p| p == (2, 2)).unwrap();
+        assert_eq!(paths.cost, 6);
+        assert_eq!(paths.goals, vec![(2, 2)]);
+        assert_eq!(paths.states().len(), 12);
+
+        let tree = shortest_path_tree((0, 0), weighted);
+        assert_eq!(tree.distance, bfs_distances((0, 0), &successors));
+        assert_eq!(tree.path((2, 2)).map(|p| p.len()), Some(7));
+    }
+
+    #[test]
+    fn test_zero_weight_cycle() {
+        let successors = |&n: &u32| match n {
+            0 => vec![(1, 0)],
+            1 => vec![(0, 0), (2, 1)],
+            _ => vec![],
+        };
+
+        assert_eq!(dijkstra(0, successors, |&n| n == 2), Some((vec![0, 1, 2], 1)));
+
+        let paths = all_shortest_paths(0, successors, |&n| n == 2).unwrap();
+        assert_eq!(paths.states(), [0, 1, 2].into_iter().collect());
+        assert_eq!(paths.tree.path(0), Some(vec![0]));
+    }
+}